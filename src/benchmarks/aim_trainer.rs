@@ -1,14 +1,18 @@
 use super::TestRunner;
+use crate::consent::accept_consent;
+use crate::state_machine::{Detection, StateMachine, Step};
 use crate::utils::{
-    click_cookies_button, click_on_pixel, is_kill_switch_pressed, spawn_completion_monitor,
+    click_on_pixel, pause_at_end, run_blocking, wait_until_ready, RESULT_SCREEN_SELECTOR,
 };
 use anyhow::Result;
-use headless_chrome::Tab;
-use std::sync::{
-    atomic::{AtomicBool, Ordering},
-    Arc,
-};
+use tracing::{debug, info, instrument};
 
+#[cfg(target_os = "windows")]
+use crate::utils::is_kill_switch_pressed;
+#[cfg(target_os = "windows")]
+use std::time::{Duration, Instant};
+#[cfg(target_os = "windows")]
+use tracing::warn;
 #[cfg(target_os = "windows")]
 use winapi::{
     shared::windef::HDC,
@@ -21,22 +25,43 @@ use winapi::{
     },
 };
 
+/// The area where the targets appear, from the start screen until the last target
+const TARGET_AREA_SELECTOR: &str = ".css-42wpoy.e19owgy79";
+
+/// The screens of the test
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum AimState {
+    Playing,
+    Result,
+}
+
+#[instrument(name = "aim_trainer")]
 pub async fn run() -> Result<()> {
-    println!("🎯 Starting Aim Trainer Test");
+    info!("🎯 Starting Aim Trainer Test");
 
-    let runner = TestRunner::new()?;
-    let tab = runner.get_tab()?;
+    let runner = TestRunner::launch().await?;
+    runner.guard("aim_trainer", play(&runner)).await?;
 
-    tab.navigate_to("https://humanbenchmark.com/tests/aim")?;
+    info!("✅ Aim Trainer Test completed");
+
+    // Keep the result on screen for demos
+    pause_at_end().await?;
+
+    Ok(())
+}
+
+/// Open the test in a new tab of the browser and click the targets until it is done
+async fn play(runner: &TestRunner) -> Result<()> {
+    let tab = runner.open("https://humanbenchmark.com/tests/aim").await?;
 
     // Handle cookies
-    click_cookies_button(&tab)?;
+    accept_consent(&tab).await?;
 
     // Wait for the ads to load in
-    std::thread::sleep(std::time::Duration::from_secs(5));
+    wait_until_ready(&tab, TARGET_AREA_SELECTOR).await?;
 
     // Find the area where the targets appear and determine the pixel coordinates of the corners
-    let target_area = tab.find_element(".css-42wpoy.e19owgy79")?;
+    let target_area = tab.find_element(TARGET_AREA_SELECTOR)?;
 
     // Get the bounding rectangle of the target area
     let quad = target_area.get_box_model()?.content;
@@ -58,28 +83,60 @@ pub async fn run() -> Result<()> {
         quad.bottom_right.y as i32 - 50 + window_y + y_offset_browser,
     );
 
-    println!(
+    debug!(
         "Target area bounds: ({}, {}) to ({}, {})",
         left, top, right, bottom
     );
 
-    // Start by clicking in the middle to begin the test
-    let center_x = (left + right) / 2;
-    let center_y = (top + bottom) / 2;
-    click_on_pixel(center_x, center_y)?;
-
-    #[cfg(target_os = "windows")]
-    {
-        // Use ultra-fast Windows screenshot-based target detection
-        let _ = ultra_fast_target_detection(left, top, right, bottom, &tab);
-    }
-
-    println!("✅ Aim Trainer Test completed");
+    // Click the targets while the test is played, on a dedicated thread as the detection
+    // runs in a tight loop
+    let mut state_machine = aim_state_machine();
+    let page = tab.clone();
+    run_blocking(move || {
+        state_machine.run(page.as_ref(), |state, _| match state {
+            AimState::Playing => {
+                // Start by clicking in the middle to begin the test
+                let center_x = (left + right) / 2;
+                let center_y = (top + bottom) / 2;
+                click_on_pixel(center_x, center_y)?;
+
+                #[cfg(target_os = "windows")]
+                {
+                    // Use ultra-fast Windows screenshot-based target detection
+                    let detector = aim_state_machine();
+                    let is_playing = || {
+                        matches!(
+                            detector.detect_in(page.as_ref()),
+                            Ok(Some(AimState::Playing))
+                        )
+                    };
+                    ultra_fast_target_detection(left, top, right, bottom, &is_playing)
+                        .map_err(anyhow::Error::msg)?;
+
+                    // Still playing means the kill switch stopped the detection
+                    if !is_playing() {
+                        return Ok(Step::Continue);
+                    }
+                }
+                Ok(Step::Stop)
+            }
+            AimState::Result => {
+                info!("🏁 Test completed - found completion element");
+                Ok(Step::Stop)
+            }
+        })
+    })
+    .await
+}
 
-    // Wait to see results
-    std::thread::sleep(std::time::Duration::from_secs(5));
+/// The states of the test and the rules that detect them
+fn aim_state_machine() -> StateMachine<AimState> {
+    use AimState::*;
 
-    Ok(())
+    StateMachine::new("aim")
+        .state(Result, Detection::selector(RESULT_SCREEN_SELECTOR))
+        .state(Playing, Detection::selector(TARGET_AREA_SELECTOR))
+        .transition(Playing, Result)
 }
 
 #[cfg(target_os = "windows")]
@@ -88,14 +145,9 @@ fn ultra_fast_target_detection(
     top: i32,
     right: i32,
     bottom: i32,
-    tab: &Arc<Tab>,
+    is_playing: &dyn Fn() -> bool,
 ) -> Result<(), String> {
-    println!("🚀 Using ultra-fast Windows screenshot-based target detection");
-
-    // Shared atomic boolean to signal when test is complete
-    let test_complete = Arc::new(AtomicBool::new(false));
-
-    let completion_checker = spawn_completion_monitor(tab.clone(), test_complete.clone());
+    debug!("🚀 Using ultra-fast Windows screenshot-based target detection");
 
     unsafe {
         let hwnd = GetDesktopWindow();
@@ -112,16 +164,22 @@ fn ultra_fast_target_detection(
         // Sample the background color from multiple points at the start
         let background_color = 0xD1872B;
 
+        // Checking the page takes a round trip, so it is only checked every 100ms
+        let mut last_check = Instant::now();
+
         'main_loop: loop {
             // Check for kill switch (ESC key)
             if is_kill_switch_pressed() {
-                println!("🛑 Kill switch activated (ESC pressed) - stopping aim trainer");
+                warn!("🛑 Kill switch activated (ESC pressed) - stopping aim trainer");
                 break 'main_loop;
             }
 
-            // Check if test is complete (non-blocking atomic read)
-            if test_complete.load(Ordering::Relaxed) {
-                break 'main_loop;
+            // Check if the test moved on to the result screen
+            if last_check.elapsed() > Duration::from_millis(100) {
+                if !is_playing() {
+                    break 'main_loop;
+                }
+                last_check = Instant::now();
             }
 
             // Create compatible DC and bitmap for screenshot
@@ -144,7 +202,7 @@ fn ultra_fast_target_detection(
                 let _ = click_on_pixel(screen_x, screen_y);
 
                 targets_hit += 1;
-                println!(
+                info!(
                     "✨ Target #{} hit at ({}, {})",
                     targets_hit, screen_x, screen_y
                 );
@@ -159,10 +217,6 @@ fn ultra_fast_target_detection(
         ReleaseDC(hwnd, hdc_screen);
     }
 
-    // Signal the completion checker to stop and wait for it
-    test_complete.store(true, Ordering::Relaxed);
-    let _ = completion_checker.join();
-
     Ok(())
 }
 
@@ -188,3 +242,25 @@ unsafe fn find_target_in_screenshot(
 
     None // No different pixel found
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_the_screens_of_the_test() {
+        let state_machine = aim_state_machine();
+        assert_eq!(
+            state_machine
+                .detect(r#"<div class="css-42wpoy e19owgy79"><div>Aim Trainer</div></div>"#),
+            Some(AimState::Playing)
+        );
+        assert_eq!(
+            state_machine.detect(
+                r#"<div class="css-42wpoy e19owgy79"><h1>152ms</h1><button class="css-qm6rs9 e19owgy710">Save score</button></div>"#
+            ),
+            Some(AimState::Result)
+        );
+        assert_eq!(state_machine.detect("<p>Loading</p>"), None);
+    }
+}
@@ -1,13 +1,40 @@
+use super::replay::TracedSolver;
 use super::TestRunner;
+use crate::consent::accept_consent;
+use crate::input::{Input, InputMode};
+use crate::options::options;
+use crate::page::{Page, PageElement};
+use crate::percentile;
+use crate::state_machine::{Detection, StateMachine, Step};
+use crate::trace::{traced, ReplayPage};
 use crate::utils::{
-    click_cookies_button, click_on_pixel, determ_center_of_element, is_kill_switch_pressed, Point,
+    determ_center_of_element, is_kill_switch_pressed, number_after_label, pause_at_end,
+    run_blocking, wait_until_ready, Point, RESULT_SCREEN_SELECTOR,
 };
 use anyhow::Result;
-use headless_chrome::{Element, Tab};
 use rayon::prelude::*;
 use scraper::{Html, Selector};
-use std::sync::Arc;
+use serde::{Deserialize, Serialize};
 use std::time::{Duration, Instant};
+use tracing::{debug, info, info_span, instrument, warn};
+
+/// Script that returns the numbered cells of the grid with the centres of their cells
+///
+/// Replaces reading the grid HTML and looking up the cells in the grid template, so the
+/// cells of a pass are read with a single round trip. The clicks are still checked one by
+/// one with [`wait_for_cell_removed`], which costs a round trip per click when the click
+/// landed. The result is a JSON string, like `[{"number":1,"x":412.5,"y":310}]`, in page
+/// coordinates.
+const CHIMP_CELLS_SCRIPT: &str = r#"
+JSON.stringify(Array.from(document.querySelectorAll('.desktop-only [data-cellnumber]'), (cell) => {
+    const rect = cell.getBoundingClientRect();
+    return {
+        number: Number(cell.dataset.cellnumber),
+        x: rect.left + rect.width / 2,
+        y: rect.top + rect.height / 2,
+    };
+}))
+"#;
 
 #[derive(Debug, Clone)]
 struct NumberPosition {
@@ -16,6 +43,41 @@ struct NumberPosition {
     col: usize,
 }
 
+/// Progress shown on the screen between the rounds
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Progress {
+    /// Amount of numbers in the next round
+    numbers: u32,
+    /// Strikes so far, the test ends after three
+    strikes: u32,
+}
+
+/// When the solver stops before the end screen, from the options
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ChimpLimits {
+    /// Highest level to play, from `--max-level`, the test stops once it is passed
+    pub max_level: Option<u32>,
+    /// End the test on the first strike, from `--stop-on-strike`
+    pub stop_on_first_strike: bool,
+}
+
+impl ChimpLimits {
+    pub fn from_options() -> Self {
+        Self {
+            max_level: options().max_level,
+            stop_on_first_strike: options().stop_on_first_strike,
+        }
+    }
+}
+
+/// A number on the grid and the centre of its cell in page coordinates
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+struct CellCenter {
+    number: u32,
+    x: f64,
+    y: f64,
+}
+
 #[derive(Debug, Clone)]
 struct GridCell {
     row: usize,
@@ -23,21 +85,38 @@ struct GridCell {
     screen_position: Point,
 }
 
+/// Where the grid is on the screen, the grid template is rebuilt when it changes
+#[derive(Debug, Clone, PartialEq)]
+struct Layout {
+    /// Screen position of the page, including the browser offsets. Zero for clicks through
+    /// the DevTools Protocol, which use page coordinates.
+    origin: Point,
+    /// Bounding box of the grid container in the page: left, top, right, bottom
+    grid_box: (i32, i32, i32, i32),
+}
+
 #[derive(Debug)]
 struct ChimpGrid {
     cells: Vec<Vec<GridCell>>,
     grid_bounds: (usize, usize), // (rows, cols)
+    layout: Layout,
 }
 
 impl ChimpGrid {
-    /// Initialize a new grid
-    fn new() -> Self {
+    /// Initialize a new grid for the layout
+    fn new(layout: Layout) -> Self {
         Self {
             cells: Vec::new(),
             grid_bounds: (0, 0),
+            layout,
         }
     }
 
+    /// Whether the template no longer matches the grid on the page
+    fn is_outdated(&self, layout: &Layout, grid_size: (usize, usize)) -> bool {
+        self.layout != *layout || self.grid_bounds != grid_size
+    }
+
     /// Add a new cell to the grid
     fn add_cell(&mut self, cell: GridCell) {
         // Ensure the grid is large enough
@@ -78,26 +157,53 @@ impl ChimpGrid {
 }
 
 pub async fn run() -> Result<()> {
-    println!("🐵 Starting Chimp Test");
+    info!("🐵 Starting Chimp Test");
 
-    let runner = TestRunner::new()?;
-    let tab = runner.get_tab()?;
+    let runner = TestRunner::launch().await?;
+    let level = runner
+        .guard("chimp_test", play(&runner, InputMode::Os))
+        .await?;
 
-    tab.navigate_to("https://humanbenchmark.com/tests/chimp")?;
+    info!("✅ Chimp Test completed");
+    percentile::report("chimp_test", f64::from(level));
 
-    // Handle cookies
-    click_cookies_button(&tab)?;
+    // Keep the result on screen for demos
+    pause_at_end().await?;
 
-    // Wait for the ads to load in
-    std::thread::sleep(std::time::Duration::from_secs(5));
+    Ok(())
+}
+
+/// Play the test in a new tab of the browser and return the highest level passed
+#[instrument(name = "chimp_test", skip_all)]
+pub async fn play(runner: &TestRunner, input: InputMode) -> Result<u32> {
+    let tab = runner
+        .open("https://humanbenchmark.com/tests/chimp")
+        .await?;
 
-    chimp_test_actions(&tab)?;
+    // Handle cookies
+    accept_consent(&tab).await?;
 
-    println!("✅ Chimp Test completed");
+    // Wait for the ads to load in
+    wait_until_ready(&tab, ".css-de05nr.e19owgy710").await?;
 
-    // Wait to see the result
-    std::thread::sleep(Duration::from_secs(5));
+    // The solver clicks in a tight loop, so it runs on a dedicated thread
+    let page = tab.clone();
+    let input = input.backend(&tab);
+    let limits = ChimpLimits::from_options();
+    run_blocking(move || {
+        traced(
+            &TracedSolver::ChimpTest { limits },
+            page.as_ref(),
+            input.as_ref(),
+            |page, input| chimp_test_actions(page, input, limits),
+        )
+    })
+    .await
+}
 
+/// Play the test on the observations of a trace
+pub fn replay(page: &ReplayPage, limits: ChimpLimits) -> Result<()> {
+    chimp_test_actions(page, page, limits)?;
     Ok(())
 }
 
@@ -105,14 +211,8 @@ pub async fn run() -> Result<()> {
 ///
 /// This function will return a grid template where the screen coordinates of each cell are stored.
 /// The grid template will be used by later logic to know where to click on the screen for each cell.
-fn create_grid_template_from_html(
-    element: &Element,
-    window_x: &i32,
-    window_y: &i32,
-    x_offset: &i32,
-    y_offset: &i32,
-) -> Result<ChimpGrid> {
-    let mut grid = ChimpGrid::new();
+fn create_grid_template_from_html(element: &dyn PageElement, layout: &Layout) -> Result<ChimpGrid> {
+    let mut grid = ChimpGrid::new(layout.clone());
 
     // Find all row elements
     let rows = element.find_elements(".css-k008qs")?;
@@ -132,10 +232,10 @@ fn create_grid_template_from_html(
                     // Get the position of this cell
                     let screen_position = determ_center_of_element(
                         cell_element,
-                        window_x,
-                        window_y,
-                        x_offset,
-                        y_offset,
+                        &layout.origin.x,
+                        &layout.origin.y,
+                        &0,
+                        &0,
                     )?;
 
                     Ok(GridCell {
@@ -186,97 +286,760 @@ fn parse_numbers_from_html(html_content: &str) -> Result<Vec<NumberPosition>> {
     Ok(number_positions)
 }
 
-fn chimp_test_actions(tab: &Arc<Tab>) -> Result<()> {
-    // Get browser window position on screen
-    let window_bounds = tab.get_bounds()?;
-    let window_x = window_bounds.left as i32;
-    let window_y = window_bounds.top as i32;
-    let x_offset_browser = 5; // Offset to take the browser url bar and toolbar into account
-    let y_offset_browser = 140; // Offset to take the browser url bar and toolbar into account
+/// Count the rows of the grid and the cells of its widest row
+fn parse_grid_size(html_content: &str) -> (usize, usize) {
+    let document = Html::parse_document(html_content);
+    let row_selector = Selector::parse(".css-k008qs").unwrap();
+    let cell_selector = Selector::parse(".css-ggichp, .css-19b5rdt").unwrap();
+
+    document
+        .select(&row_selector)
+        .fold((0, 0), |(rows, cols), row_element| {
+            (
+                rows + 1,
+                cols.max(row_element.select(&cell_selector).count()),
+            )
+        })
+}
 
-    let mut grid: Option<ChimpGrid> = None;
+/// Read where the grid currently is, to notice scrolling, resizing and layout shifts
+fn read_layout(grid_element: &dyn PageElement, input: &dyn Input) -> Result<Layout> {
+    let origin = input.origin()?;
+    let grid_box = grid_element.box_model()?;
+    Ok(Layout {
+        origin,
+        grid_box: (
+            grid_box.left as i32,
+            grid_box.top as i32,
+            grid_box.right as i32,
+            grid_box.bottom as i32,
+        ),
+    })
+}
 
-    let next_button = tab.find_element(".css-de05nr.e19owgy710")?;
-    let next_button_location = determ_center_of_element(
-        &next_button,
-        &window_x,
-        &window_y,
-        &x_offset_browser,
-        &y_offset_browser,
-    )?;
-
-    let mut pass: u8 = 1;
+/// Read the numbers on the grid and the centres of their cells with a single evaluate
+fn read_cell_centers(tab: &dyn Page) -> Result<Vec<CellCenter>> {
+    let value = tab.evaluate(CHIMP_CELLS_SCRIPT, false)?;
+    let json = value
+        .as_ref()
+        .and_then(|value| value.as_str())
+        .ok_or_else(|| anyhow::anyhow!("The cell script did not return JSON"))?;
+    parse_cell_centers(json)
+}
+
+/// Parse the cells returned by the cell script, in the order to click them
+fn parse_cell_centers(json: &str) -> Result<Vec<CellCenter>> {
+    let mut cells: Vec<CellCenter> = serde_json::from_str(json)?;
+    cells.sort_by_key(|cell| cell.number);
+    Ok(cells)
+}
+
+/// Wait for a clicked number to disappear from the grid, `false` when it is still there
+///
+/// A click that does not land has to be retried before the next number is clicked, because
+/// a number clicked out of order is a strike. So every click is checked before the next
+/// one, instead of checking the whole pass at once.
+fn wait_for_cell_removed(tab: &dyn Page, number: u32) -> Result<bool> {
+    let script = format!(
+        "document.querySelector('[data-cellnumber=\"{}\"]') === null",
+        number
+    );
     let start_time = Instant::now();
-    let mut init_grid_time: u128 = 0;
 
-    // Click once on the start button
-    click_on_pixel(next_button_location.x, next_button_location.y)?;
+    loop {
+        let removed = tab
+            .evaluate(&script, false)?
+            .and_then(|value| value.as_bool())
+            .unwrap_or(false);
 
-    let grid_element = tab.find_element(".desktop-only")?;
+        if removed {
+            return Ok(true);
+        }
+        if start_time.elapsed() > Duration::from_millis(250) {
+            return Ok(false);
+        }
+        std::thread::sleep(Duration::from_millis(1));
+    }
+}
 
-    while !is_kill_switch_pressed() {
-        println!("Pass {}", pass);
-        // Press the start/continue button
-        click_on_pixel(next_button_location.x, next_button_location.y)?;
-        println!("Clicked the start/continue button");
+/// Find the level and strikes in the text of the screen between the rounds
+///
+/// The screen shows the numbers of the next round and the strikes so far, for example
+/// "NUMBERS 7 STRIKES 1 of 3". Returns `None` when the text is not on the screen.
+fn parse_progress(text: &str) -> Option<Progress> {
+    Some(Progress {
+        numbers: number_after_label(text, "numbers")?,
+        strikes: number_after_label(text, "strikes")?,
+    })
+}
+
+/// Wait for the screen between the rounds and read the progress from it
+///
+/// Returns `None` when the result screen appeared instead.
+///
+/// ## Errors
+/// Returns an error if neither showed up within 2 seconds
+fn wait_for_progress(tab: &dyn Page) -> Result<Option<Progress>> {
+    let start_time = Instant::now();
 
-        // Wait a moment for the grid to appear
+    loop {
+        let text = tab.evaluate("document.body.innerText", false)?;
+        if let Some(progress) = text
+            .as_ref()
+            .and_then(|text| text.as_str())
+            .and_then(parse_progress)
+        {
+            return Ok(Some(progress));
+        }
+        if !tab.find_elements(RESULT_SCREEN_SELECTOR)?.is_empty() {
+            return Ok(None);
+        }
+
+        if start_time.elapsed() > Duration::from_secs(2) {
+            anyhow::bail!("No progress was shown within 2 seconds after the round");
+        }
+        std::thread::sleep(Duration::from_millis(5));
+    }
+}
+
+/// Click the numbers of the round with the positions from the cell script
+///
+/// The cells are read once per pass, then every click is checked before the next one.
+/// With `verify`, the numbers are first compared with the ones parsed from the grid HTML.
+/// Returns the amount of numbers clicked, or `None` when the script found no numbers or
+/// does not agree with the HTML, without clicking anything.
+fn click_numbers_from_script(
+    tab: &dyn Page,
+    input: &dyn Input,
+    grid_element: &dyn PageElement,
+    verify: bool,
+) -> Result<Option<u32>> {
+    // The grid can take a moment to appear after pressing continue
+    let start_time = Instant::now();
+    let mut cells = read_cell_centers(tab)?;
+    while cells.is_empty() {
+        if start_time.elapsed() > Duration::from_millis(500) {
+            return Ok(None);
+        }
         std::thread::sleep(Duration::from_millis(1));
+        cells = read_cell_centers(tab)?;
+    }
+
+    if verify {
+        let mut expected: Vec<u32> = parse_numbers_from_html(&grid_element.get_content()?)?
+            .iter()
+            .map(|pos| pos.number)
+            .collect();
+        expected.sort();
+        let found: Vec<u32> = cells.iter().map(|cell| cell.number).collect();
+        if expected != found {
+            return Ok(None);
+        }
+    }
+
+    let origin = input.origin()?;
+    for cell in &cells {
+        let point = Point {
+            x: cell.x as i32 + origin.x,
+            y: cell.y as i32 + origin.y,
+        };
+        input.click(&point)?;
+
+        // Make sure the click landed, and retry once at the current position of the cell
+        if !wait_for_cell_removed(tab, cell.number)? {
+            warn!(
+                "Number {} is still on the page after clicking it, clicking it again",
+                cell.number
+            );
+            let origin = input.origin()?;
+            if let Some(moved) = read_cell_centers(tab)?
+                .iter()
+                .find(|moved| moved.number == cell.number)
+            {
+                input.click(&Point {
+                    x: moved.x as i32 + origin.x,
+                    y: moved.y as i32 + origin.y,
+                })?;
+            }
+            if !wait_for_cell_removed(tab, cell.number)? {
+                warn!(
+                    "Number {} is still on the page after clicking it again",
+                    cell.number
+                );
+            }
+        }
+    }
 
-        // Initialize the grid on the first run
+    Ok(Some(cells.len() as u32))
+}
+
+/// Click the numbers of the round by parsing the grid HTML and looking up the grid template
+///
+/// This is the fallback for the cell script. Returns the amount of numbers in the round.
+fn click_numbers_from_html(
+    tab: &dyn Page,
+    input: &dyn Input,
+    grid_element: &dyn PageElement,
+    grid: &mut Option<ChimpGrid>,
+) -> Result<u32> {
+    // Get the inner HTML of the container
+    let grid_content = grid_element.get_content()?;
+
+    // (Re)build the grid template on the first run and whenever the grid moved or changed size
+    let layout = read_layout(grid_element, input)?;
+    let grid_size = parse_grid_size(&grid_content);
+    if grid
+        .as_ref()
+        .is_none_or(|template_grid| template_grid.is_outdated(&layout, grid_size))
+    {
         if grid.is_none() {
-            println!("Initializing grid structure from HTML");
-
-            grid = Some(create_grid_template_from_html(
-                &grid_element,
-                &window_x,
-                &window_y,
-                &x_offset_browser,
-                &y_offset_browser,
-            )?);
-            init_grid_time = start_time.elapsed().as_millis();
+            debug!("Initializing grid structure from HTML");
+        } else {
+            info!("Grid layout changed, rebuilding the grid template");
         }
-        // Set the current grid
-        let template_grid = grid.as_ref().unwrap();
 
-        // Get the inner HTML of the container
-        let grid_content = grid_element.get_content()?;
+        *grid = Some(create_grid_template_from_html(grid_element, &layout)?);
+    }
 
-        // Parse numbers and their positions from HTML
-        let number_positions = parse_numbers_from_html(&grid_content)?;
+    // Parse numbers and their positions from HTML
+    let number_positions = parse_numbers_from_html(&grid_content)?;
 
-        // Sort numbers by their value to click them in order
-        let mut sorted_positions = number_positions;
-        sorted_positions.sort_by_key(|pos| pos.number);
+    // Sort numbers by their value to click them in order
+    let mut sorted_positions = number_positions;
+    sorted_positions.sort_by_key(|pos| pos.number);
 
-        // Click numbers in sorted order
-        for pos in sorted_positions {
+    let numbers = sorted_positions.len() as u32;
+
+    // Click numbers in sorted order
+    for pos in sorted_positions {
+        let Some(point) = grid
+            .as_ref()
+            .and_then(|template_grid| template_grid.get_coordinates(pos.row, pos.col))
+        else {
+            warn!(
+                "Could not find coordinates for number {} at row {}, col {}",
+                pos.number, pos.row, pos.col
+            );
+            continue;
+        };
+        input.click(&point)?;
+
+        // Make sure the click landed, a missed click means the template is off
+        if !wait_for_cell_removed(tab, pos.number)? {
+            warn!(
+                "Number {} is still on the page after clicking it, rebuilding the grid template",
+                pos.number
+            );
+            let layout = read_layout(grid_element, input)?;
+            let template_grid = create_grid_template_from_html(grid_element, &layout)?;
             if let Some(point) = template_grid.get_coordinates(pos.row, pos.col) {
-                std::thread::sleep(Duration::from_millis(2));
-                click_on_pixel(point.x, point.y)?;
-            } else {
-                println!(
-                    "Warning: Could not find coordinates for number {} at row {}, col {}",
-                    pos.number, pos.row, pos.col
+                input.click(&point)?;
+            }
+            if !wait_for_cell_removed(tab, pos.number)? {
+                warn!(
+                    "Number {} is still on the page after clicking it again",
+                    pos.number
                 );
             }
+            *grid = Some(template_grid);
+        }
+    }
+
+    Ok(numbers)
+}
+
+/// Screen position of the centre of the start/continue button
+fn find_next_button(tab: &dyn Page, layout: &Layout) -> Result<Point> {
+    let next_button = tab.find_element(".css-de05nr.e19owgy710")?;
+    determ_center_of_element(&next_button, &layout.origin.x, &layout.origin.y, &0, &0)
+}
+
+/// The screens of the test
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ChimpState {
+    /// The start screen, or the progress between the rounds, with the continue button
+    Ready,
+    /// The numbers of a round are on the grid
+    Round,
+    /// The result screen after the last strike
+    Result,
+}
+
+/// The states of the test and the rules that detect them
+fn chimp_state_machine() -> StateMachine<ChimpState> {
+    use ChimpState::*;
+
+    StateMachine::new("chimp")
+        .state(Result, Detection::selector(RESULT_SCREEN_SELECTOR))
+        // The continue button can still be on the page while the grid shows
+        .state(
+            Round,
+            Detection::selector(".desktop-only [data-cellnumber]"),
+        )
+        .state(Ready, Detection::selector(".css-de05nr.e19owgy710"))
+        .transition(Ready, Round)
+        .transition(Round, Ready)
+        .transition(Ready, Result)
+        .transition(Round, Result)
+        .poll_interval(Duration::from_millis(1))
+}
+
+fn chimp_test_actions(tab: &dyn Page, input: &dyn Input, limits: ChimpLimits) -> Result<u32> {
+    let mut grid: Option<ChimpGrid> = None;
+
+    let grid_element = tab.find_element(".desktop-only")?;
+    let mut button_layout = read_layout(&grid_element, input)?;
+    let mut next_button_location = find_next_button(tab, &button_layout)?;
+
+    let mut pass: u32 = 1;
+    let start_time = Instant::now();
+
+    // Use the cell script until it disagrees with the grid HTML on the first pass
+    let mut fast_path = true;
+    let mut fast_path_verified = false;
+
+    // The level is the amount of numbers in the highest round passed
+    let mut level = 0;
+    let mut strikes = 0;
+    // Amount of numbers in the round played last, until its progress is read
+    let mut played = None;
+
+    let mut state_machine = chimp_state_machine();
+    state_machine.run(tab, |state, attempt| {
+        let _round = info_span!("round", pass).entered();
+        if is_kill_switch_pressed() {
+            return Ok(Step::Stop);
+        }
+
+        match state {
+            ChimpState::Ready => {
+                // The screen between the rounds tells whether the round was passed
+                if let Some(numbers) = played.take() {
+                    let Some(progress) = wait_for_progress(tab)? else {
+                        // The state machine stops on the result screen
+                        return Ok(Step::Continue);
+                    };
+
+                    if progress.strikes > strikes {
+                        strikes = progress.strikes;
+                        warn!("❌ Strike {} at level {}", strikes, numbers);
+
+                        if limits.stop_on_first_strike {
+                            info!("Stopping on the first strike");
+                            return Ok(Step::Stop);
+                        }
+                    } else {
+                        level = level.max(numbers);
+                        info!("Level {} passed", level);
+                    }
+
+                    // Stop once the maximum level is passed, before a round above it
+                    if limits.max_level.is_some_and(|max_level| level >= max_level) {
+                        info!("🏁 Reached the maximum level");
+                        return Ok(Step::Stop);
+                    }
+                }
+
+                // Find the button again when the page moved, like the grid template
+                let layout = read_layout(&grid_element, input)?;
+                if layout != button_layout {
+                    info!("Layout changed, finding the continue button again");
+                    next_button_location = find_next_button(tab, &layout)?;
+                    button_layout = layout;
+                }
+
+                if pass == 1 && attempt == 0 {
+                    // Click once on the start button
+                    input.click(&next_button_location)?;
+                }
+
+                // Press the start/continue button
+                input.click(&next_button_location)?;
+                debug!("Clicked the start/continue button");
+            }
+            ChimpState::Round => {
+                // Click the numbers with the fast path, and parse the grid HTML when it
+                // cannot be used
+                let mut numbers = None;
+                if fast_path {
+                    match click_numbers_from_script(tab, input, &grid_element, !fast_path_verified)
+                    {
+                        Ok(Some(count)) => {
+                            fast_path_verified = true;
+                            numbers = Some(count);
+                        }
+                        Ok(None) => {
+                            warn!("The cell script disagrees with the grid HTML, falling back to parsing the HTML");
+                            fast_path = false;
+                        }
+                        Err(e) => {
+                            warn!(
+                                "The cell script failed ({}), falling back to parsing the HTML",
+                                e
+                            );
+                            fast_path = false;
+                        }
+                    }
+                }
+                let numbers = match numbers {
+                    Some(count) => count,
+                    None => click_numbers_from_html(tab, input, &grid_element, &mut grid)?,
+                };
+
+                // A retry clicks the numbers left of the same round
+                if attempt == 0 {
+                    played = Some(numbers);
+                    pass += 1;
+                }
+            }
+            ChimpState::Result => {
+                info!("🏁 Test completed - found completion element");
+                return Ok(Step::Stop);
+            }
         }
 
-        // Increment the pass counter
-        pass += 1;
-
-        // Check if we've reached the maximum number of passes
-        if pass > 37 {
-            let end_time = start_time.elapsed().as_millis();
-            println!("Total time: {} milliseconds", end_time);
-            println!("Initialize grid time: {} milliseconds", init_grid_time);
-            println!(
-                "Clicked through all stages in {} milliseconds",
-                end_time - init_grid_time
+        Ok(Step::Continue)
+    })?;
+
+    info!(
+        "Clicked through {} passes in {} milliseconds",
+        pass - 1,
+        start_time.elapsed().as_millis()
+    );
+    info!("🐵 Final level: {} with {} strikes", level, strikes);
+
+    Ok(level)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::page::ScriptedPage;
+    use crate::trace::TracingPage;
+
+    #[test]
+    fn parses_progress_between_rounds() {
+        let text = "NUMBERS\n7\nSTRIKES\n1 of 3\nContinue";
+        assert_eq!(
+            parse_progress(text),
+            Some(Progress {
+                numbers: 7,
+                strikes: 1
+            })
+        );
+        assert_eq!(
+            parse_progress("Numbers: 12 Strikes: 0 of 3"),
+            Some(Progress {
+                numbers: 12,
+                strikes: 0
+            })
+        );
+    }
+
+    #[test]
+    fn ignores_labels_without_a_number() {
+        // The instructions on the start screen mention the numbers, but not the progress
+        let text = "Click the squares in order according to their numbers.\nStart Test";
+        assert_eq!(parse_progress(text), None);
+        assert_eq!(
+            number_after_label("order the numbers\nNUMBERS 9", "numbers"),
+            Some(9)
+        );
+    }
+
+    #[test]
+    fn counts_rows_and_columns_of_the_grid() {
+        let html = r#"<div class="desktop-only">
+            <div class="css-k008qs"><div class="css-ggichp" data-cellnumber="1">1</div><div class="css-19b5rdt"></div></div>
+            <div class="css-k008qs"><div class="css-19b5rdt"></div><div class="css-19b5rdt"></div><div class="css-ggichp" data-cellnumber="2">2</div></div>
+        </div>"#;
+
+        assert_eq!(parse_grid_size(html), (2, 3));
+        assert_eq!(
+            parse_grid_size("<div class=\"desktop-only\"></div>"),
+            (0, 0)
+        );
+
+        let numbers = parse_numbers_from_html(html).unwrap();
+        let cells: Vec<(u32, usize, usize)> = numbers
+            .iter()
+            .map(|pos| (pos.number, pos.row, pos.col))
+            .collect();
+        assert_eq!(cells, vec![(1, 0, 0), (2, 1, 2)]);
+    }
+
+    #[test]
+    fn template_is_outdated_when_the_grid_moves_or_resizes() {
+        let layout = Layout {
+            origin: Point { x: 5, y: 140 },
+            grid_box: (100, 200, 500, 600),
+        };
+        let mut template_grid = ChimpGrid::new(layout.clone());
+        template_grid.grid_bounds = (5, 8);
+
+        assert!(!template_grid.is_outdated(&layout, (5, 8)));
+        assert!(template_grid.is_outdated(&layout, (6, 8)));
+
+        let scrolled = Layout {
+            grid_box: (100, 150, 500, 550),
+            ..layout.clone()
+        };
+        assert!(template_grid.is_outdated(&scrolled, (5, 8)));
+
+        let moved_window = Layout {
+            origin: Point { x: 300, y: 140 },
+            ..layout
+        };
+        assert!(template_grid.is_outdated(&moved_window, (5, 8)));
+    }
+
+    /// The cell script result for the fixture round, with cells of 80 by 80 pixels
+    const ROUND_CELLS_JSON: &str = r#"[
+        {"number":3,"x":200,"y":40},
+        {"number":1,"x":440,"y":120},
+        {"number":5,"x":40,"y":200},
+        {"number":2,"x":600,"y":280},
+        {"number":4,"x":360,"y":360}
+    ]"#;
+
+    #[test]
+    fn parses_cell_centers_in_click_order() {
+        let cells = parse_cell_centers(ROUND_CELLS_JSON).unwrap();
+        let numbers: Vec<u32> = cells.iter().map(|cell| cell.number).collect();
+        assert_eq!(numbers, vec![1, 2, 3, 4, 5]);
+        assert_eq!(
+            cells[0],
+            CellCenter {
+                number: 1,
+                x: 440.0,
+                y: 120.0
+            }
+        );
+
+        assert!(parse_cell_centers("[]").unwrap().is_empty());
+        assert!(parse_cell_centers("not json").is_err());
+    }
+
+    #[test]
+    fn cell_script_agrees_with_the_html_parser() {
+        let html = include_str!("../../tests/fixtures/chimp/round.html");
+        let mut positions = parse_numbers_from_html(html).unwrap();
+        positions.sort_by_key(|pos| pos.number);
+        let cells = parse_cell_centers(ROUND_CELLS_JSON).unwrap();
+
+        assert_eq!(parse_grid_size(html), (5, 8));
+        assert_eq!(positions.len(), cells.len());
+        for (pos, cell) in positions.iter().zip(&cells) {
+            assert_eq!(pos.number, cell.number);
+            assert_eq!(
+                (pos.row, pos.col),
+                (cell.y as usize / 80, cell.x as usize / 80)
             );
-            break;
         }
     }
 
-    Ok(())
+    /// A chimp screen with a grid of 2 rows of 4 cells of 80 by 80 pixels and the continue
+    /// button, with the numbers at their row and column
+    fn chimp_screen(numbers: &[(u32, usize, usize)], text: &str) -> String {
+        let mut rows = String::new();
+        for row in 0..2 {
+            rows.push_str(r#"<div class="css-k008qs">"#);
+            for col in 0..4 {
+                let data_box = format!(
+                    "{} {} {} {}",
+                    col * 80,
+                    row * 80,
+                    col * 80 + 80,
+                    row * 80 + 80
+                );
+                match numbers.iter().find(|(_, r, c)| (*r, *c) == (row, col)) {
+                    Some((number, _, _)) => rows.push_str(&format!(
+                        r#"<div class="css-ggichp" data-cellnumber="{}" data-box="{}"><div class="css-1o2x5ar">{}</div></div>"#,
+                        number, data_box, number
+                    )),
+                    None => rows.push_str(&format!(
+                        r#"<div class="css-19b5rdt" data-box="{}"></div>"#,
+                        data_box
+                    )),
+                }
+            }
+            rows.push_str("</div>");
+        }
+        format!(
+            r#"<body><p>{}</p><div class="desktop-only" data-box="0 0 320 160">{}</div><button class="css-de05nr e19owgy710" data-box="100 300 200 340">Continue</button></body>"#,
+            text, rows
+        )
+    }
+
+    /// Answer the cell script and the removal check from the data boxes of the snapshot
+    fn answer_chimp_scripts(script: &str, html: &str, _index: usize) -> Option<serde_json::Value> {
+        if script == CHIMP_CELLS_SCRIPT {
+            let document = Html::parse_document(html);
+            let selector = Selector::parse(".desktop-only [data-cellnumber]").unwrap();
+            let cells: Vec<serde_json::Value> = document
+                .select(&selector)
+                .map(|cell| {
+                    let bounds: Vec<f64> = cell
+                        .value()
+                        .attr("data-box")
+                        .unwrap()
+                        .split_whitespace()
+                        .map(|value| value.parse().unwrap())
+                        .collect();
+                    serde_json::json!({
+                        "number": cell.value().attr("data-cellnumber").unwrap().parse::<u32>().unwrap(),
+                        "x": (bounds[0] + bounds[2]) / 2.0,
+                        "y": (bounds[1] + bounds[3]) / 2.0,
+                    })
+                })
+                .collect();
+            return Some(serde_json::Value::String(
+                serde_json::Value::Array(cells).to_string(),
+            ));
+        }
+
+        let number = script
+            .strip_prefix("document.querySelector('[data-cellnumber=\"")?
+            .split('"')
+            .next()?;
+        Some(serde_json::Value::Bool(
+            !html.contains(&format!("data-cellnumber=\"{}\"", number)),
+        ))
+    }
+
+    /// The result screen with the "Save score" button
+    const RESULT_SCREEN: &str =
+        r#"<body><h1>Score 3</h1><button class="css-qm6rs9 e19owgy710">Save score</button></body>"#;
+
+    /// A test that passes a round of three numbers and then ends
+    fn one_round_page() -> ScriptedPage {
+        let round = [(1, 1, 2), (2, 0, 0), (3, 1, 3)];
+        ScriptedPage::new()
+            .then(chimp_screen(&[], "Start Test"))
+            .then(chimp_screen(&[], "Start Test"))
+            .then(chimp_screen(&round, ""))
+            .then(chimp_screen(&round[1..], ""))
+            .then(chimp_screen(&round[2..], ""))
+            .then(chimp_screen(&[], "NUMBERS 4 STRIKES 0 of 3"))
+            .then(RESULT_SCREEN)
+            .on_evaluate(chimp_state_machine().answer_detection())
+            .on_evaluate(answer_chimp_scripts)
+    }
+
+    #[test]
+    fn clicks_the_cells_in_ascending_order() {
+        let page = one_round_page();
+        let level = chimp_test_actions(&page, &page, ChimpLimits::default()).unwrap();
+
+        let button = Point { x: 150, y: 320 };
+        assert_eq!(level, 3);
+        assert_eq!(
+            page.input().clicks(),
+            vec![
+                button.clone(),
+                button.clone(),
+                Point { x: 200, y: 120 },
+                Point { x: 40, y: 40 },
+                Point { x: 280, y: 120 },
+                button,
+            ]
+        );
+    }
+
+    #[test]
+    fn stops_once_the_maximum_level_is_passed() {
+        let limits = |max_level| ChimpLimits {
+            max_level: Some(max_level),
+            stop_on_first_strike: false,
+        };
+
+        // The round of three numbers reaches the maximum, so continue is not pressed again
+        let page = one_round_page();
+        assert_eq!(chimp_test_actions(&page, &page, limits(3)).unwrap(), 3);
+        assert_eq!(page.input().clicks().len(), 5);
+
+        // One level below the maximum plays on
+        let page = one_round_page();
+        assert_eq!(chimp_test_actions(&page, &page, limits(4)).unwrap(), 3);
+        assert_eq!(page.input().clicks().len(), 6);
+    }
+
+    #[test]
+    fn finds_the_continue_button_again_when_the_page_moves() {
+        let round = [(1, 0, 0)];
+        // The page scrolled down by 100 pixels after the round
+        let scrolled = chimp_screen(&[], "NUMBERS 5 STRIKES 0 of 3")
+            .replace(r#"data-box="0 0 320 160""#, r#"data-box="0 -100 320 60""#)
+            .replace(
+                r#"data-box="100 300 200 340""#,
+                r#"data-box="100 200 200 240""#,
+            );
+        let page = ScriptedPage::new()
+            .then(chimp_screen(&[], "Start Test"))
+            .then(chimp_screen(&[], "Start Test"))
+            .then(chimp_screen(&round, ""))
+            .then(scrolled)
+            .then(RESULT_SCREEN)
+            .on_evaluate(chimp_state_machine().answer_detection())
+            .on_evaluate(answer_chimp_scripts);
+
+        chimp_test_actions(&page, &page, ChimpLimits::default()).unwrap();
+        assert_eq!(
+            page.input().clicks().last(),
+            Some(&Point { x: 150, y: 220 })
+        );
+    }
+
+    #[test]
+    fn fails_when_no_progress_follows_a_round() {
+        let round = [(1, 0, 0)];
+        let page = ScriptedPage::new()
+            .then(chimp_screen(&[], "Start Test"))
+            .then(chimp_screen(&[], "Start Test"))
+            .then(chimp_screen(&round, ""))
+            .then(chimp_screen(&[], ""))
+            .on_evaluate(chimp_state_machine().answer_detection())
+            .on_evaluate(answer_chimp_scripts);
+
+        let error = chimp_test_actions(&page, &page, ChimpLimits::default()).unwrap_err();
+        assert!(error.to_string().contains("No progress"));
+    }
+
+    #[test]
+    fn replays_a_traced_run() {
+        let limits = ChimpLimits {
+            max_level: Some(3),
+            stop_on_first_strike: false,
+        };
+        let page = one_round_page();
+        let tracing_page = TracingPage::new(&page, &page);
+        chimp_test_actions(&tracing_page, &tracing_page, limits).unwrap();
+        let trace = tracing_page
+            .into_trace(serde_json::to_value(TracedSolver::ChimpTest { limits }).unwrap());
+
+        // The limits of the recorded run come back from the header of the trace
+        let TracedSolver::ChimpTest { limits: recorded } =
+            serde_json::from_value(trace.solver.clone()).unwrap()
+        else {
+            panic!("The trace header lost the solver");
+        };
+        assert_eq!(recorded, limits);
+
+        let replay_page = ReplayPage::new(trace.clone());
+        let _ = replay(&replay_page, recorded);
+        assert!(replay_page.verify().unwrap() > 0);
+
+        // A run that played on decides differently from the same page with the maximum level
+        let page = one_round_page();
+        let tracing_page = TracingPage::new(&page, &page);
+        let defaults = ChimpLimits::default();
+        chimp_test_actions(&tracing_page, &tracing_page, defaults).unwrap();
+        let trace = tracing_page.into_trace(
+            serde_json::to_value(TracedSolver::ChimpTest { limits: defaults }).unwrap(),
+        );
+
+        let replay_page = ReplayPage::new(trace);
+        let _ = replay(&replay_page, limits);
+        assert!(replay_page.verify().is_err());
+    }
 }
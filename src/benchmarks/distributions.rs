@@ -0,0 +1,234 @@
+use super::TestRunner;
+use crate::artifacts::utc_date_time;
+use crate::percentile::{Distribution, Distributions, DISTRIBUTIONS_VERSION};
+use crate::utils::{run_blocking, wait_until_ready};
+use anyhow::{Context, Result};
+use scraper::{ElementRef, Html, Selector};
+use std::collections::HashMap;
+use std::path::Path;
+use std::time::SystemTime;
+use tracing::info;
+
+/// The bars of the statistics chart, one per range of scores
+const BAR_SELECTOR: &str = ".recharts-bar-rectangle path";
+
+/// The labels of the score axis of the statistics chart
+const TICK_SELECTOR: &str = ".recharts-xAxis .recharts-cartesian-axis-tick-value";
+
+/// A test with a statistics chart of the scores of all users on its page
+struct Chart {
+    /// Name of the test in the logs
+    test: &'static str,
+    url: &'static str,
+    unit: &'static str,
+    lower_is_better: bool,
+}
+
+const CHARTS: &[Chart] = &[
+    Chart {
+        test: "reaction_time",
+        url: "https://humanbenchmark.com/tests/reactiontime",
+        unit: "ms",
+        lower_is_better: true,
+    },
+    Chart {
+        test: "typing",
+        url: "https://humanbenchmark.com/tests/typing",
+        unit: "wpm",
+        lower_is_better: false,
+    },
+    Chart {
+        test: "chimp_test",
+        url: "https://humanbenchmark.com/tests/chimp",
+        unit: "level",
+        lower_is_better: false,
+    },
+    Chart {
+        test: "verbal_memory",
+        url: "https://humanbenchmark.com/tests/verbal-memory",
+        unit: "points",
+        lower_is_better: false,
+    },
+];
+
+/// Read the score distributions from the statistics charts of the test pages, and write them
+/// to the file the percentiles are computed from
+///
+/// ## Errors
+/// Returns an error when a page has no chart that can be read, or the file cannot be written
+pub async fn run(path: &Path) -> Result<()> {
+    info!("📊 Reading the score distributions of the site");
+
+    let runner = TestRunner::launch().await?;
+    let tests = runner.guard("distributions", read_charts(&runner)).await?;
+
+    let (date, _) = utc_date_time(SystemTime::now());
+    let distributions = Distributions {
+        version: DISTRIBUTIONS_VERSION,
+        source: format!(
+            "Statistics charts of the test pages of humanbenchmark.com, read on {}",
+            date
+        ),
+        estimated: false,
+        tests,
+    };
+    distributions.save(path)?;
+
+    info!(
+        "✅ Wrote the score distributions of {} tests to {}",
+        distributions.tests.len(),
+        path.display()
+    );
+    Ok(())
+}
+
+/// Open the page of every test and read the distribution of its chart
+async fn read_charts(runner: &TestRunner) -> Result<HashMap<String, Distribution>> {
+    let mut tests = HashMap::new();
+    for chart in CHARTS {
+        let tab = runner.open(chart.url).await?;
+        wait_until_ready(&tab, BAR_SELECTOR).await?;
+
+        let page = tab.clone();
+        let html = run_blocking(move || page.get_content()).await?;
+        let cdf = read_chart(&html)
+            .with_context(|| format!("Could not read the chart of {}", chart.url))?;
+        info!(
+            "📈 Read the distribution of {} with {} points",
+            chart.test,
+            cdf.len()
+        );
+
+        tests.insert(
+            chart.test.to_string(),
+            Distribution {
+                unit: chart.unit.to_string(),
+                lower_is_better: chart.lower_is_better,
+                cdf,
+            },
+        );
+    }
+    Ok(tests)
+}
+
+/// The scores with the percentage of users that scored lower, from the bars of the chart
+///
+/// Every bar holds the users whose score is in its range, in proportion to its height. The
+/// scores of the bar edges come from the labels of the score axis, which are spread evenly.
+///
+/// ## Errors
+/// Returns an error when the chart has no bars or fewer than two score labels
+pub fn read_chart(html: &str) -> Result<Vec<(f64, f64)>> {
+    let document = Html::parse_document(html);
+
+    let ticks: Vec<(f64, f64)> = document
+        .select(&Selector::parse(TICK_SELECTOR).unwrap())
+        .filter_map(|tick| {
+            let label = tick.text().collect::<String>();
+            Some((attribute(tick, "x")?, leading_number(&label)?))
+        })
+        .collect();
+    let (Some(&(first_x, first_score)), Some(&(last_x, last_score))) =
+        (ticks.first(), ticks.last())
+    else {
+        anyhow::bail!("The chart has no score labels");
+    };
+    if last_x <= first_x || last_score <= first_score {
+        anyhow::bail!("The chart needs two different score labels");
+    }
+    let score_at =
+        |x: f64| first_score + (x - first_x) * (last_score - first_score) / (last_x - first_x);
+
+    let mut bars: Vec<(f64, f64, f64)> = document
+        .select(&Selector::parse(BAR_SELECTOR).unwrap())
+        .filter_map(|bar| {
+            Some((
+                attribute(bar, "x")?,
+                attribute(bar, "width")?,
+                attribute(bar, "height")?,
+            ))
+        })
+        .filter(|(_, width, _)| *width > 0.0)
+        .collect();
+    bars.sort_by(|a, b| a.0.total_cmp(&b.0));
+    let total: f64 = bars.iter().map(|(_, _, height)| height).sum();
+    if total <= 0.0 {
+        anyhow::bail!("The chart has no bars");
+    }
+
+    let mut cdf = vec![(round(score_at(bars[0].0)), 0.0)];
+    let mut below = 0.0;
+    for (x, width, height) in bars {
+        below += height;
+        cdf.push((round(score_at(x + width)), round(100.0 * below / total)));
+    }
+    Ok(cdf)
+}
+
+/// The attribute of the element as a number
+fn attribute(element: ElementRef, name: &str) -> Option<f64> {
+    element.value().attr(name)?.trim().parse().ok()
+}
+
+/// The number a label starts with, like 250 of "250ms"
+fn leading_number(label: &str) -> Option<f64> {
+    let label = label.trim();
+    let end = label
+        .find(|ch: char| !(ch.is_ascii_digit() || ch == '.' || ch == '-'))
+        .unwrap_or(label.len());
+    label[..end].parse().ok()
+}
+
+/// The number rounded to two decimals, so the file stays readable
+fn round(number: f64) -> f64 {
+    (number * 100.0).round() / 100.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const REACTION_TIME: &str =
+        include_str!("../../tests/fixtures/distributions/reaction_time.html");
+
+    #[test]
+    fn reads_the_distribution_from_the_bars() {
+        let cdf = read_chart(REACTION_TIME).unwrap();
+        assert_eq!(
+            cdf,
+            vec![
+                (200.0, 0.0),
+                (250.0, 20.0),
+                (300.0, 70.0),
+                (350.0, 90.0),
+                (400.0, 100.0)
+            ]
+        );
+
+        let distribution = Distribution {
+            unit: "ms".to_string(),
+            lower_is_better: true,
+            cdf,
+        };
+        assert_eq!(distribution.percentile(275.0), 55.0);
+    }
+
+    #[test]
+    fn reads_the_score_labels() {
+        assert_eq!(leading_number("250ms"), Some(250.0));
+        assert_eq!(leading_number(" 40 "), Some(40.0));
+        assert_eq!(leading_number("wpm"), None);
+    }
+
+    #[test]
+    fn fails_without_a_chart() {
+        let error = read_chart("<body><h1>Reaction Time Test</h1></body>").unwrap_err();
+        assert_eq!(error.to_string(), "The chart has no score labels");
+
+        let without_bars = REACTION_TIME.replace("recharts-bar-rectangle\"", "\"");
+        assert_eq!(
+            read_chart(&without_bars).unwrap_err().to_string(),
+            "The chart has no bars"
+        );
+    }
+}
@@ -0,0 +1,87 @@
+use crate::state_machine::{Detection, StateMachine, Step};
+use crate::utils::{run_blocking, RESULT_SCREEN_SELECTOR};
+use anyhow::Result;
+use headless_chrome::Tab;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::info;
+
+/// The screens of a test that is played by hand
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ManualState {
+    /// The test is shown, from its start screen until the last answer
+    Playing,
+    /// The result screen with the "Save score" button
+    Result,
+}
+
+/// The states of a test played by hand, which may be played for at most the time limit
+pub fn manual_state_machine(name: &str, time_limit: Duration) -> StateMachine<ManualState> {
+    use ManualState::*;
+
+    StateMachine::new(name)
+        .state(Result, Detection::selector(RESULT_SCREEN_SELECTOR))
+        .state_with_timeout(Playing, Detection::selector("body"), time_limit)
+        .transition(Playing, Result)
+        .retries(0)
+        .poll_interval(Duration::from_millis(100))
+}
+
+/// Wait while the test in the tab is played by hand, until its result screen shows
+///
+/// ## Errors
+/// Returns an error if the test is still played after the time limit
+pub async fn wait_for_result(tab: &Arc<Tab>, name: &str, time_limit: Duration) -> Result<()> {
+    let mut state_machine = manual_state_machine(name, time_limit);
+    let page = tab.clone();
+    run_blocking(move || {
+        state_machine.run(page.as_ref(), |state, _| {
+            if state == ManualState::Result {
+                info!("🏁 Test completed - found completion element");
+                return Ok(Step::Stop);
+            }
+            Ok(Step::Continue)
+        })
+    })
+    .await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::page::ScriptedPage;
+
+    #[test]
+    fn waits_for_the_result_screen() {
+        let mut state_machine = manual_state_machine("manual", Duration::from_secs(1))
+            .poll_interval(Duration::from_millis(1));
+        let page = ScriptedPage::new()
+            .then_for("<body><p>Level 1</p></body>", 3)
+            .then(r#"<body><button class="css-qm6rs9 e19owgy710">Save score</button></body>"#)
+            .on_evaluate(state_machine.answer_detection());
+
+        let mut handled = Vec::new();
+        state_machine
+            .run(&page, |state, _| {
+                handled.push(state);
+                Ok(if state == ManualState::Result {
+                    Step::Stop
+                } else {
+                    Step::Continue
+                })
+            })
+            .unwrap();
+        assert_eq!(handled, vec![ManualState::Playing, ManualState::Result]);
+    }
+
+    #[test]
+    fn fails_after_the_time_limit() {
+        let mut state_machine = manual_state_machine("manual", Duration::from_millis(20))
+            .poll_interval(Duration::from_millis(1));
+        let page = ScriptedPage::new()
+            .then("<body><p>Level 1</p></body>")
+            .on_evaluate(state_machine.answer_detection());
+
+        assert!(state_machine.run(&page, |_, _| Ok(Step::Continue)).is_err());
+    }
+}
@@ -1,23 +1,72 @@
 pub mod aim_trainer;
 pub mod chimp_test;
+pub mod distributions;
+pub mod manual;
 pub mod number_memory;
+pub mod parallel;
 pub mod reaction_time;
+pub mod replay;
 pub mod sequence_memory;
 pub mod typing;
 pub mod verbal_memory;
 pub mod visual_memory;
 
+use crate::account::{self, Account};
+use crate::artifacts;
+use crate::blocking::{self, blocked_url_patterns};
+use crate::options::options;
+use crate::recording::Recording;
+use crate::utils::{run_blocking, take_kill_switch};
 use anyhow::Result;
+use headless_chrome::protocol::cdp::Network::CookieParam;
 use headless_chrome::{Browser, LaunchOptions, Tab};
 use std::ffi::OsStr;
-use std::sync::Arc;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use tracing::{info, warn};
 
 pub struct TestRunner {
     browser: Browser,
+    /// The tab opened last, where the artifacts of a failure are taken from
+    last_tab: Mutex<Option<Arc<Tab>>>,
+    /// The screencast of the tab opened last, when `--record` is set
+    recording: Mutex<Option<Recording>>,
+    /// Cookies every tab gets before it loads its page, from `--cookies`
+    cookies: Vec<CookieParam>,
+    /// Number of the `--parallel` session the runner plays, its artifacts only hold the
+    /// messages of the session
+    session: Option<usize>,
 }
 
 impl TestRunner {
     pub fn new() -> Result<Self> {
+        Self::with_session(None)
+    }
+
+    /// Start a browser without a window for a `--parallel` session, which only uses
+    /// DevTools Protocol input
+    pub fn new_headless(session: usize) -> Result<Self> {
+        Self::with_session(Some(session))
+    }
+
+    /// Start the browser, without a window when it plays a session
+    fn with_session(session: Option<usize>) -> Result<Self> {
+        let headless = session.is_some();
+        // Read the cookies and the account first, so a broken file fails before Chrome starts
+        let mut cookies = match &options().cookies {
+            Some(path) => blocking::load_cookies(path)?,
+            None => Vec::new(),
+        };
+        // The account is only signed in to save scores, the runs stay anonymous otherwise
+        let account = if options().save_score {
+            Account::load(options().account_file.as_deref())?
+        } else {
+            None
+        };
+        if let Some(session) = account.as_ref().map(Account::cookie).transpose()?.flatten() {
+            cookies.push(session);
+        }
+
         let browser = Browser::new(
             LaunchOptions::default_builder()
                 .args(vec![
@@ -28,15 +77,123 @@ impl TestRunner {
                     OsStr::new("--disable-backgrounding-occluded-windows"),
                     OsStr::new("--disable-renderer-backgrounding"),
                 ])
-                .headless(false) // Set to true for headless mode
+                .headless(headless)
+                .user_data_dir(options().profile_dir.clone())
                 .build()
                 .expect("Could not find chrome-executable"),
         )?;
+        if let Some(account) = &account {
+            cookies.extend(account::login_cookies(&browser, account)?);
+        }
 
-        Ok(Self { browser })
+        Ok(Self {
+            browser,
+            last_tab: Mutex::new(None),
+            recording: Mutex::new(None),
+            cookies,
+            session,
+        })
     }
 
-    pub fn get_tab(&self) -> Result<Arc<Tab>> {
-        self.browser.new_tab()
+    /// Launch the browser on a blocking thread, so the runtime stays free while Chrome starts
+    pub async fn launch() -> Result<Self> {
+        run_blocking(Self::new).await
+    }
+
+    /// Open a new tab and navigate it to the url of a test
+    ///
+    /// The ads and consent banners are blocked and the cookies preseeded before the page
+    /// loads. With `--record`, the tab is recorded from before the page loads.
+    pub async fn open(&self, url: &str) -> Result<Arc<Tab>> {
+        self.finish_recording().await;
+
+        let browser = self.browser.clone();
+        let cookies = self.cookies.clone();
+        let url = url.to_string();
+        let (tab, recording) = run_blocking(move || {
+            let tab = browser.new_tab()?;
+            blocking::block_requests(&tab, blocked_url_patterns(options()))?;
+            blocking::preseed_cookies(&tab, &cookies)?;
+            let recording = match &options().record {
+                Some(manifest) => Some(Recording::start(&tab, manifest)?),
+                None => None,
+            };
+            tab.navigate_to(&url)?;
+            Ok((tab, recording))
+        })
+        .await?;
+
+        *self.last_tab.lock().unwrap() = Some(tab.clone());
+        *self.recording.lock().unwrap() = recording;
+        Ok(tab)
+    }
+
+    /// Stop the screencast of the tab and write its manifest, when one is recorded
+    async fn finish_recording(&self) {
+        let Some(recording) = self.recording.lock().unwrap().take() else {
+            return;
+        };
+        if let Err(e) = run_blocking(move || recording.finish()).await {
+            warn!("Could not finish the recording: {:#}", e);
+        }
+    }
+
+    /// Press "Save score" on the tab opened last, when `--save-score` is set
+    async fn save_score(&self, benchmark: &str) {
+        if !options().save_score {
+            return;
+        }
+        let Some(tab) = self.last_tab.lock().unwrap().clone() else {
+            return;
+        };
+        match run_blocking(move || account::save_score(tab.as_ref(), options().ready_timeout)).await
+        {
+            Ok(()) => info!(benchmark, "💾 Saved the score of {}", benchmark),
+            Err(e) => warn!("Could not save the score of {}: {:#}", benchmark, e),
+        }
+    }
+
+    /// Play a benchmark on the browser, and save the artifacts of the page when it fails
+    ///
+    /// The artifacts are also saved when the kill switch cancelled the benchmark. They are
+    /// taken from the tab opened last, while the browser is still open. With `--save-score`,
+    /// the score of a benchmark that ended well is saved to the account.
+    pub async fn guard<T>(
+        &self,
+        benchmark: &str,
+        play: impl Future<Output = Result<T>>,
+    ) -> Result<T> {
+        // Forget the kill switch presses from before the benchmark
+        take_kill_switch();
+
+        let result = play.await;
+        self.finish_recording().await;
+
+        let cancelled;
+        let failure = match &result {
+            Err(e) => e,
+            Ok(_) if take_kill_switch() => {
+                cancelled = anyhow::anyhow!("Cancelled with the kill switch (ESC)");
+                &cancelled
+            }
+            Ok(_) => {
+                self.save_score(benchmark).await;
+                return result;
+            }
+        };
+
+        let tab = self.last_tab.lock().unwrap().clone();
+        if let Some(tab) = tab {
+            match artifacts::capture(&tab, benchmark, self.session, failure).await {
+                Ok(dir) => info!(
+                    "📁 Saved the artifacts of {} to {}",
+                    benchmark,
+                    dir.display()
+                ),
+                Err(e) => warn!("Could not save the artifacts of {}: {:#}", benchmark, e),
+            }
+        }
+
+        result
     }
 }
@@ -1,31 +1,42 @@
-use crate::utils::click_cookies_button;
+use crate::consent::accept_consent;
+use crate::utils::wait_until_ready;
 
+use super::manual::wait_for_result;
 use super::TestRunner;
 use anyhow::Result;
 use std::time::Duration;
+use tracing::{info, instrument};
 
+#[instrument(name = "number_memory")]
 pub async fn run(max_digits: u32) -> Result<()> {
-    println!("🔢 Starting Number Memory Test");
+    info!("🔢 Starting Number Memory Test");
 
-    let runner = TestRunner::new()?;
-    let tab = runner.get_tab()?;
+    let runner = TestRunner::launch().await?;
+    runner
+        .guard("number_memory", play(&runner, max_digits))
+        .await?;
 
-    tab.navigate_to("https://humanbenchmark.com/tests/memory")?;
+    info!("✅ Number Memory Test completed");
 
-    // Handle cookies
-    click_cookies_button(&tab)?;
+    Ok(())
+}
 
-    // Wait for the ads to load in
-    std::thread::sleep(std::time::Duration::from_secs(5));
+/// Open the test in a new tab of the browser and wait while it is played manually
+async fn play(runner: &TestRunner, max_digits: u32) -> Result<()> {
+    let tab = runner
+        .open("https://humanbenchmark.com/tests/memory")
+        .await?;
 
-    println!("🧠 Please complete the number memory test manually");
-    println!("   Target digits: {}", max_digits);
-    println!("   Memorize the numbers and type them back");
+    // Handle cookies
+    accept_consent(&tab).await?;
 
-    // Wait for user to complete
-    std::thread::sleep(Duration::from_secs(120));
+    // Wait for the ads to load in
+    wait_until_ready(&tab, "body").await?;
 
-    println!("✅ Number Memory Test completed");
+    info!("🧠 Please complete the number memory test manually");
+    info!("   Target digits: {}", max_digits);
+    info!("   Memorize the numbers and type them back");
 
-    Ok(())
+    // Wait for user to complete, at most 120 seconds
+    wait_for_result(&tab, "number_memory", Duration::from_secs(120)).await
 }
@@ -0,0 +1,306 @@
+use super::{chimp_test, reaction_time, typing, TestRunner};
+use crate::input::InputMode;
+use crate::options::options;
+use crate::percentile;
+use crate::utils::run_blocking;
+use anyhow::Result;
+use rayon::prelude::*;
+use tokio::task::JoinSet;
+use tracing::{error, info, info_span, warn, Instrument};
+
+/// The tests that only need DevTools Protocol input, so they can run headless next to each other
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ParallelTest {
+    ReactionTime,
+    Typing,
+    ChimpTest,
+}
+
+impl ParallelTest {
+    /// The whole suite of tests that can run in parallel
+    pub const ALL: [ParallelTest; 3] = [
+        ParallelTest::ReactionTime,
+        ParallelTest::Typing,
+        ParallelTest::ChimpTest,
+    ];
+
+    /// Parse the test from its name as typed in the menu
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.trim().to_lowercase().as_str() {
+            "reaction" | "reaction-time" => Some(ParallelTest::ReactionTime),
+            "typing" => Some(ParallelTest::Typing),
+            "chimp" | "chimp-test" => Some(ParallelTest::ChimpTest),
+            _ => None,
+        }
+    }
+
+    /// Name of the test in the logs and the failure artifacts
+    fn name(self) -> &'static str {
+        match self {
+            ParallelTest::ReactionTime => "reaction_time",
+            ParallelTest::Typing => "typing",
+            ParallelTest::ChimpTest => "chimp_test",
+        }
+    }
+
+    /// Unit of the score of the test
+    fn unit(self) -> &'static str {
+        match self {
+            ParallelTest::ReactionTime => "ms",
+            ParallelTest::Typing => "wpm",
+            ParallelTest::ChimpTest => "level",
+        }
+    }
+
+    /// Play the test in a new tab of the session and return its score
+    async fn play(self, runner: &TestRunner) -> Result<Option<f64>> {
+        match self {
+            ParallelTest::ReactionTime => {
+                reaction_time::play(
+                    runner,
+                    reaction_time::ReactionMode::PageHook,
+                    options().reaction_rounds,
+                )
+                .await
+            }
+            ParallelTest::Typing => Ok(typing::play(runner, typing::TypingMethod::Cdp)
+                .await?
+                .map(f64::from)),
+            ParallelTest::ChimpTest => {
+                Ok(Some(chimp_test::play(runner, InputMode::Cdp).await? as f64))
+            }
+        }
+    }
+}
+
+/// Result of one test in one session, `None` when the test finished without a score
+struct Outcome {
+    test: ParallelTest,
+    score: Result<Option<f64>>,
+}
+
+/// Scores of one test over all sessions
+#[derive(Debug, PartialEq)]
+struct Summary {
+    test: ParallelTest,
+    scores: Vec<f64>,
+    failures: usize,
+}
+
+impl Summary {
+    fn mean(&self) -> Option<f64> {
+        if self.scores.is_empty() {
+            return None;
+        }
+        Some(self.scores.iter().sum::<f64>() / self.scores.len() as f64)
+    }
+
+    fn median(&self) -> Option<f64> {
+        let mut scores = self.scores.clone();
+        scores.sort_by(f64::total_cmp);
+
+        let middle = scores.len() / 2;
+        match scores.len() {
+            0 => None,
+            len if len % 2 == 0 => Some((scores[middle - 1] + scores[middle]) / 2.0),
+            _ => Some(scores[middle]),
+        }
+    }
+}
+
+/// Runs the tests in headless sessions next to each other and prints the scores per test
+///
+/// Every session is its own browser, and plays the tests one after the other. The browsers
+/// are launched in parallel with rayon, the sessions run as tasks on the tokio runtime.
+pub async fn run(tests: Vec<ParallelTest>, sessions: usize) -> Result<()> {
+    info!("🚀 Launching {} headless sessions", sessions);
+
+    // Launching Chrome blocks until the browser is up, so start them all at once
+    let runners = run_blocking(move || {
+        (0..sessions)
+            .into_par_iter()
+            .map(|session| TestRunner::new_headless(session + 1))
+            .collect::<Result<Vec<_>>>()
+    })
+    .await?;
+
+    let mut tasks = JoinSet::new();
+    for (session, runner) in runners.into_iter().enumerate() {
+        let tests = tests.clone();
+        let span = info_span!("session", session = session + 1);
+        tasks.spawn(
+            async move {
+                let mut outcomes = Vec::new();
+                for test in tests {
+                    let benchmark = format!("{}_session_{}", test.name(), session + 1);
+                    let score = runner.guard(&benchmark, test.play(&runner)).await;
+                    match &score {
+                        Ok(Some(score)) => {
+                            info!("{:?} scored {} {}", test, score, test.unit());
+                            percentile::report(test.name(), *score);
+                        }
+                        Ok(None) => warn!("{:?} has no score", test),
+                        Err(e) => error!("❌ {:?} failed: {}", test, e),
+                    }
+                    outcomes.push(Outcome { test, score });
+                }
+                outcomes
+            }
+            .instrument(span),
+        );
+    }
+
+    let mut outcomes = Vec::new();
+    while let Some(session_outcomes) = tasks.join_next().await {
+        outcomes.extend(session_outcomes?);
+    }
+
+    info!(sessions, "📊 Results over {} sessions", sessions);
+    for summary in aggregate(&tests, outcomes) {
+        print_summary(&summary);
+    }
+
+    Ok(())
+}
+
+/// Group the scores of all sessions per test, in the order of the tests
+fn aggregate(tests: &[ParallelTest], outcomes: Vec<Outcome>) -> Vec<Summary> {
+    let mut summaries: Vec<Summary> = tests
+        .iter()
+        .map(|test| Summary {
+            test: *test,
+            scores: Vec::new(),
+            failures: 0,
+        })
+        .collect();
+
+    for outcome in outcomes {
+        let Some(summary) = summaries
+            .iter_mut()
+            .find(|summary| summary.test == outcome.test)
+        else {
+            continue;
+        };
+
+        match outcome.score {
+            Ok(Some(score)) => summary.scores.push(score),
+            Ok(None) | Err(_) => summary.failures += 1,
+        }
+    }
+
+    summaries
+}
+
+fn print_summary(summary: &Summary) {
+    let unit = summary.test.unit();
+    let benchmark = summary.test.name();
+    match (summary.mean(), summary.median()) {
+        (Some(mean), Some(median)) => {
+            let min = summary.scores.iter().copied().fold(f64::INFINITY, f64::min);
+            let max = summary
+                .scores
+                .iter()
+                .copied()
+                .fold(f64::NEG_INFINITY, f64::max);
+            let distributions = percentile::distributions();
+            let percentile = distributions.percentile(benchmark, median);
+            info!(
+                benchmark,
+                scores = summary.scores.len(),
+                mean,
+                median,
+                percentile,
+                estimated = distributions.estimated,
+                min,
+                max,
+                failures = summary.failures,
+                "  {:?}: {} scores, mean {:.1} {}, median {:.1} {}{}, min {:.1} {}, max {:.1} {}, {} failed",
+                summary.test,
+                summary.scores.len(),
+                mean,
+                unit,
+                median,
+                unit,
+                percentile
+                    .map(|percentile| format!(" ({})", distributions.describe(percentile)))
+                    .unwrap_or_default(),
+                min,
+                unit,
+                max,
+                unit,
+                summary.failures
+            );
+        }
+        _ => info!(
+            benchmark,
+            scores = 0,
+            failures = summary.failures,
+            "  {:?}: no scores, {} failed",
+            summary.test,
+            summary.failures
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn outcome(test: ParallelTest, score: Option<f64>) -> Outcome {
+        Outcome {
+            test,
+            score: Ok(score),
+        }
+    }
+
+    #[test]
+    fn aggregates_scores_per_test() {
+        let outcomes = vec![
+            outcome(ParallelTest::ReactionTime, Some(120.0)),
+            outcome(ParallelTest::Typing, Some(300.0)),
+            outcome(ParallelTest::ReactionTime, Some(100.0)),
+            outcome(ParallelTest::Typing, None),
+            Outcome {
+                test: ParallelTest::ReactionTime,
+                score: Err(anyhow::anyhow!("selector timed out")),
+            },
+            outcome(ParallelTest::ReactionTime, Some(140.0)),
+            outcome(ParallelTest::ReactionTime, Some(110.0)),
+        ];
+
+        let summaries = aggregate(
+            &[ParallelTest::ReactionTime, ParallelTest::Typing],
+            outcomes,
+        );
+
+        assert_eq!(
+            summaries,
+            vec![
+                Summary {
+                    test: ParallelTest::ReactionTime,
+                    scores: vec![120.0, 100.0, 140.0, 110.0],
+                    failures: 1,
+                },
+                Summary {
+                    test: ParallelTest::Typing,
+                    scores: vec![300.0],
+                    failures: 1,
+                },
+            ]
+        );
+        assert_eq!(summaries[0].mean(), Some(117.5));
+        assert_eq!(summaries[0].median(), Some(115.0));
+        assert_eq!(summaries[1].median(), Some(300.0));
+    }
+
+    #[test]
+    fn empty_summary_has_no_statistics() {
+        let summary = Summary {
+            test: ParallelTest::ChimpTest,
+            scores: Vec::new(),
+            failures: 2,
+        };
+        assert_eq!(summary.mean(), None);
+        assert_eq!(summary.median(), None);
+    }
+}
@@ -1,81 +1,570 @@
-use std::{sync::Arc, time::Duration};
+use std::time::Instant;
 
 use crate::{
-    benchmarks::TestRunner,
-    utils::{click_cookies_button, click_on_pixel, is_kill_switch_pressed},
+    benchmarks::{replay::TracedSolver, TestRunner},
+    consent::accept_consent,
+    input::{Input, InputMode},
+    page::Page,
+    percentile,
+    state_machine::{Detection, StateMachine, Step},
+    trace::{traced, ReplayPage},
+    utils::{is_kill_switch_pressed, pause_at_end, run_blocking, wait_until_ready, Point},
 };
 
 use anyhow::Result;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, info, info_span, instrument, warn};
 
-use headless_chrome::Tab;
+/// The reaction area, whose `view-*` class changes with every state of the test
+const REACTION_AREA_SELECTOR: &str = ".e18o0sx0.css-saet2v.e19owgy77";
 
-pub async fn run() -> Result<()> {
-    println!("🚦 Starting Reaction Time Test");
+/// Script that watches the reaction area from inside the page
+///
+/// A `MutationObserver` reacts to the class change to the green `.view-go` state right after
+/// the page renders it, with a `requestAnimationFrame` loop as a backup. Once armed, the hook
+/// dispatches the click itself and resolves the promise returned by `arm()` with the time
+/// between detecting the change and dispatching the click, in milliseconds. When the round
+/// ends without turning green, the promise resolves with `null` instead.
+const REACTION_HOOK_SCRIPT: &str = r#"
+(() => {
+    if (window.__reactionHook) {
+        return;
+    }
 
-    let runner = TestRunner::new()?;
-    let tab = runner.get_tab()?;
+    const hook = { armed: false, resolve: null };
+    window.__reactionHook = hook;
 
-    tab.navigate_to("https://humanbenchmark.com/tests/reactiontime")?;
+    hook.arm = () => new Promise((resolve) => {
+        hook.resolve = resolve;
+        hook.armed = true;
+    });
 
-    // Handle cookies
-    click_cookies_button(&tab)?;
+    const check = () => {
+        if (!hook.armed) {
+            return;
+        }
+        const target = document.querySelector('.view-go');
+        if (!target) {
+            // The round ended without turning green, for example after clicking too soon
+            if (!document.querySelector('.view-waiting')) {
+                hook.armed = false;
+                hook.resolve(null);
+            }
+            return;
+        }
+        hook.armed = false;
 
-    // Get the browser window position and reaction area coordinates
-    let (click_x, click_y) = get_reaction_area_coordinates(&tab)?;
-    println!("Reaction area coordinates: ({}, {})", click_x, click_y);
-
-    for round in 0..5 {
-        // Click start button
-        if round == 0 {
-            // Wait for the ads to load in
-            std::thread::sleep(std::time::Duration::from_secs(5));
-
-            tab.wait_for_element(".view-splash.e18o0sx0.css-saet2v.e19owgy77")?;
-            let start_button = tab.find_element(".view-splash.e18o0sx0.css-saet2v.e19owgy77")?;
-            start_button.click()?;
+        const detectedAt = performance.now();
+        target.dispatchEvent(new MouseEvent('mousedown', { bubbles: true, cancelable: true }));
+        hook.resolve(performance.now() - detectedAt);
+    };
+
+    new MutationObserver(check).observe(document.body, {
+        subtree: true,
+        childList: true,
+        attributes: true,
+        attributeFilter: ['class'],
+    });
+
+    const frame = () => {
+        check();
+        requestAnimationFrame(frame);
+    };
+    requestAnimationFrame(frame);
+})()
+"#;
+
+/// How the solver detects the green screen and clicks it
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ReactionMode {
+    /// Poll the page content over CDP and click with the Win32 Api
+    Polling,
+    /// Hook the state change with an injected script and click from inside the page
+    PageHook,
+}
+
+impl ReactionMode {
+    /// Parse the mode from its name as typed in the menu
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.trim().to_lowercase().as_str() {
+            "polling" => Some(ReactionMode::Polling),
+            "hook" | "page-hook" => Some(ReactionMode::PageHook),
+            _ => None,
+        }
+    }
+}
+
+impl Default for ReactionMode {
+    fn default() -> Self {
+        if cfg!(target_os = "windows") {
+            ReactionMode::Polling
         } else {
-            tab.wait_for_element(".view-result.e18o0sx0.css-saet2v.e19owgy77")?;
-            let continue_button = tab.find_element(".view-result.e18o0sx0.css-saet2v.e19owgy77")?;
-            continue_button.click()?;
+            ReactionMode::PageHook
+        }
+    }
+}
+
+/// The states the reaction area goes through during the test
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ReactionState {
+    /// Start screen, clicking it starts the first round
+    Splash,
+    /// Red screen, waiting for it to turn green
+    Waiting,
+    /// Green screen, click as fast as possible
+    Click,
+    /// Reaction time of the last round, clicking it starts the next round
+    Result,
+    /// Clicked before the screen turned green, clicking it retries the round
+    TooSoon,
+    /// Average of all rounds, the test is over
+    Final,
+}
+
+/// Timings of a single round
+#[derive(Debug, Clone, Default)]
+struct RoundResult {
+    /// Reaction time as reported by the page
+    page_ms: Option<u32>,
+    /// Time between detecting the green screen over CDP and sending the click, as measured by
+    /// us in polling mode
+    measured_latency_ms: Option<f64>,
+    /// Time the injected hook took from seeing the green screen to dispatching its click, in
+    /// page hook mode. The hook detects the change inside the page, so this is not comparable
+    /// to the measured latency of polling.
+    hook_dispatch_ms: Option<f64>,
+}
+
+impl RoundResult {
+    /// The result of a round, with the timing of our click as the mode measures it
+    fn new(page_ms: Option<u32>, mode: ReactionMode, click_ms: Option<f64>) -> Self {
+        match mode {
+            ReactionMode::Polling => Self {
+                page_ms,
+                measured_latency_ms: click_ms,
+                hook_dispatch_ms: None,
+            },
+            ReactionMode::PageHook => Self {
+                page_ms,
+                measured_latency_ms: None,
+                hook_dispatch_ms: click_ms,
+            },
         }
+    }
+}
 
-        // Wait for the red waiting screen to appear
-        tab.wait_for_element(".view-waiting.e18o0sx0.css-saet2v.e19owgy77")?;
+pub async fn run(mode: ReactionMode, rounds: u32) -> Result<()> {
+    info!("🚦 Starting Reaction Time Test");
 
-        reaction_time_actions(&tab, click_x, click_y)?;
+    let runner = TestRunner::launch().await?;
+    if let Some(average) = runner
+        .guard("reaction_time", play(&runner, mode, rounds))
+        .await?
+    {
+        percentile::report("reaction_time", average);
     }
 
-    // Wait to see the result
-    std::thread::sleep(Duration::from_secs(5));
+    // Keep the result on screen for demos
+    pause_at_end().await?;
 
     Ok(())
 }
 
-fn get_reaction_area_coordinates(tab: &headless_chrome::Tab) -> Result<(i32, i32)> {
+/// Play the test in a new tab of the browser
+///
+/// Returns the average reaction time reported by the page, `None` when no round finished.
+#[instrument(name = "reaction_time", skip(runner))]
+pub async fn play(runner: &TestRunner, mode: ReactionMode, rounds: u32) -> Result<Option<f64>> {
+    let tab = runner
+        .open("https://humanbenchmark.com/tests/reactiontime")
+        .await?;
+
+    // Handle cookies
+    accept_consent(&tab).await?;
+
+    // Wait for the ads to load in
+    wait_until_ready(&tab, REACTION_AREA_SELECTOR).await?;
+
+    // The rounds poll and click synchronously, so they run on a dedicated thread. Polling
+    // clicks the screen position of the reaction area with the Win32 Api.
+    let page = tab.clone();
+    let input = InputMode::Os.backend(&tab);
+    let results = run_blocking(move || {
+        traced(
+            &TracedSolver::ReactionTime { mode, rounds },
+            page.as_ref(),
+            input.as_ref(),
+            |page, input| play_rounds(page, input, mode, rounds),
+        )
+    })
+    .await?;
+
+    Ok(average_reaction_time(&results))
+}
+
+/// Play the rounds on the observations of a trace
+pub fn replay(page: &ReplayPage, mode: ReactionMode, rounds: u32) -> Result<()> {
+    play_rounds(page, page, mode, rounds)?;
+    Ok(())
+}
+
+/// Play the rounds of the test with the state machine and print the results
+fn play_rounds(
+    tab: &dyn Page,
+    input: &dyn Input,
+    mode: ReactionMode,
+    rounds: u32,
+) -> Result<Vec<RoundResult>> {
+    // Get the browser window position and reaction area coordinates
+    let (click_x, click_y) = get_reaction_area_coordinates(tab)?;
+    debug!("Reaction area coordinates: ({}, {})", click_x, click_y);
+
+    // Polling clicks the screen position of the reaction area
+    let target = Point {
+        x: click_x,
+        y: click_y,
+    };
+
+    if mode == ReactionMode::PageHook {
+        tab.evaluate(REACTION_HOOK_SCRIPT, false)?;
+        debug!("Injected the reaction hook into the page");
+    }
+
+    let mut results = Vec::new();
+    let mut click_ms = None;
+    // Whether the green screen of the current round has been clicked
+    let mut clicked = false;
+    let mut too_soon_count = 0;
+
+    let mut state_machine = reaction_state_machine();
+    state_machine.run(tab, |state, attempt| {
+        let _round = info_span!("round", round = results.len() + 1).entered();
+        if is_kill_switch_pressed() {
+            return Ok(Step::Stop);
+        }
+
+        match state {
+            ReactionState::Splash => click_reaction_area(tab)?,
+            ReactionState::Waiting | ReactionState::Click => {
+                // The green screen can still show on the next poll after the click, and
+                // clicking it again would land on the result screen and skip a round. Only
+                // a retry, when the page stayed green, clicks again.
+                if clicked && attempt == 0 {
+                    return Ok(Step::Continue);
+                }
+                click_ms = match mode {
+                    ReactionMode::Polling => reaction_time_actions(tab, input, &target)?,
+                    ReactionMode::PageHook => page_hook_actions(tab)?,
+                };
+                clicked = click_ms.is_some();
+            }
+            ReactionState::Result => {
+                clicked = false;
+                // A retry only has to click again, the round was already recorded
+                if attempt == 0 {
+                    let result =
+                        RoundResult::new(read_page_reaction_time(tab)?, mode, click_ms.take());
+                    print_round_result(results.len() + 1, &result);
+                    results.push(result);
+                }
+
+                if results.len() as u32 >= rounds {
+                    return Ok(Step::Stop);
+                }
+                click_reaction_area(tab)?;
+            }
+            ReactionState::TooSoon => {
+                clicked = false;
+                if attempt == 0 {
+                    too_soon_count += 1;
+                    warn!("⚠️ Clicked too soon, retrying the round");
+                }
+                click_reaction_area(tab)?;
+            }
+            ReactionState::Final => {
+                // The last round of the page goes straight to the final screen with the average
+                if let Some(latency) = click_ms.take() {
+                    let result = RoundResult::new(None, mode, Some(latency));
+                    print_round_result(results.len() + 1, &result);
+                    results.push(result);
+                }
+                if let Some(average) = read_page_reaction_time(tab)? {
+                    info!("🏁 Final screen reports an average of {} ms", average);
+                }
+                return Ok(Step::Stop);
+            }
+        }
+
+        Ok(Step::Continue)
+    })?;
+
+    if too_soon_count > 0 {
+        info!("Recovered from {} too soon clicks", too_soon_count);
+    }
+    print_summary(&results);
+
+    Ok(results)
+}
+
+fn get_reaction_area_coordinates(tab: &dyn Page) -> Result<(i32, i32)> {
     // Get browser window position and size
-    let bounds = tab.get_bounds()?;
+    let bounds = tab.bounds()?;
 
     // Calculate the center of the reaction area
     // The reaction area is typically centered in the viewport
-    let center_x = (bounds.left + (bounds.width / 2.0) as u32) as i32;
-    let center_y = (bounds.top + (bounds.height / 2.0) as u32) as i32;
+    let center_x = bounds.left + (bounds.width / 2.0) as i32;
+    let center_y = bounds.top + (bounds.height / 2.0) as i32;
 
     Ok((center_x, center_y))
 }
 
-fn reaction_time_actions(tab: &Arc<Tab>, click_x: i32, click_y: i32) -> Result<()> {
-    // Find the element to scan
-    let reaction_element = tab.find_element(".view-waiting.e18o0sx0.css-saet2v.e19owgy77")?;
+/// The states of the reaction area and the rules that detect them
+fn reaction_state_machine() -> StateMachine<ReactionState> {
+    use ReactionState::*;
+
+    StateMachine::new("reaction")
+        // "Too soon!" is shown in the same view the page uses for the other messages
+        .state(TooSoon, Detection::text(REACTION_AREA_SELECTOR, "Too soon"))
+        .state(
+            Click,
+            Detection::Any(vec![
+                Detection::selector(".view-go"),
+                Detection::text(".view-waiting", "Click!"),
+            ]),
+        )
+        .state(Waiting, Detection::selector(".view-waiting"))
+        .state(Splash, Detection::selector(".view-splash"))
+        .state(Result, Detection::selector(".view-result"))
+        .state(Final, Detection::selector(".view-score"))
+        .transition(Splash, Waiting)
+        .transition(Waiting, Click)
+        .transition(Waiting, Result)
+        .transition(Waiting, TooSoon)
+        .transition(Waiting, Final)
+        .transition(Click, Result)
+        .transition(Click, Final)
+        .transition(Result, Waiting)
+        .transition(TooSoon, Waiting)
+}
+
+/// Click the reaction area through the DevTools Protocol to move to the next state
+fn click_reaction_area(tab: &dyn Page) -> Result<()> {
+    tab.find_element(REACTION_AREA_SELECTOR)?.click()?;
+    Ok(())
+}
+
+/// Poll the waiting screen until it turns green and click it
+///
+/// Returns the time between seeing the change and finishing the click, or `None` when the
+/// round ended some other way or the kill switch stopped it.
+fn reaction_time_actions(tab: &dyn Page, input: &dyn Input, target: &Point) -> Result<Option<f64>> {
+    let detector = reaction_state_machine();
 
     while !is_kill_switch_pressed() {
-        let reaction_element_content = reaction_element.get_content()?;
+        match detector.detect_in(tab)? {
+            Some(ReactionState::Click) => {
+                let detected_at = Instant::now();
+                input.click(target)?;
+                return Ok(Some(detected_at.elapsed().as_secs_f64() * 1000.0));
+            }
+            Some(ReactionState::Waiting) => {}
+            _ => return Ok(None),
+        }
+    }
+
+    Ok(None)
+}
+
+/// Arm the injected hook and wait until it has clicked the green screen
+///
+/// The promise returned by the hook resolves right after the in-page click, so the CDP
+/// round-trip only delays reporting the result, not the click itself. Returns the dispatch
+/// delay of the hook, `None` when the round ended without turning green.
+fn page_hook_actions(tab: &dyn Page) -> Result<Option<f64>> {
+    let latency = tab.evaluate("window.__reactionHook.arm()", true)?;
+    Ok(latency.and_then(|value| value.as_f64()))
+}
+
+/// Read the reaction time the page shows on the result screen
+fn read_page_reaction_time(tab: &dyn Page) -> Result<Option<u32>> {
+    let result_element = tab.find_element(REACTION_AREA_SELECTOR)?;
+    Ok(parse_reaction_ms(&result_element.get_inner_text()?))
+}
+
+/// Parse the milliseconds from a text like "253 ms" or "253ms"
+fn parse_reaction_ms(text: &str) -> Option<u32> {
+    let words: Vec<&str> = text.split_whitespace().collect();
+    words
+        .iter()
+        .enumerate()
+        .find_map(|(index, word)| match word.strip_suffix("ms") {
+            Some("") => index
+                .checked_sub(1)
+                .and_then(|previous| words[previous].parse().ok()),
+            Some(value) => value.parse().ok(),
+            None => None,
+        })
+}
+
+fn print_round_result(round: usize, result: &RoundResult) {
+    let page_ms = result
+        .page_ms
+        .map_or("unknown".to_string(), |ms| format!("{} ms", ms));
+    let click_timing = match (result.measured_latency_ms, result.hook_dispatch_ms) {
+        (Some(ms), _) => format!("measured latency {:.3} ms", ms),
+        (None, Some(ms)) => format!("hook dispatch delay {:.3} ms", ms),
+        (None, None) => "click timing unknown".to_string(),
+    };
+
+    info!(
+        page_ms = result.page_ms,
+        latency_ms = result.measured_latency_ms,
+        hook_dispatch_ms = result.hook_dispatch_ms,
+        "Round {}: page reported {}, {}",
+        round,
+        page_ms,
+        click_timing
+    );
+}
+
+fn print_summary(results: &[RoundResult]) {
+    if let Some(average) = average_reaction_time(results) {
+        info!(
+            average_ms = average,
+            "⏱️ Average reaction time over {} rounds: {:.1} ms",
+            results
+                .iter()
+                .filter(|result| result.page_ms.is_some())
+                .count(),
+            average
+        );
+    }
+}
+
+/// Average of the reaction times reported by the page, `None` without any
+fn average_reaction_time(results: &[RoundResult]) -> Option<f64> {
+    let page_times: Vec<u32> = results.iter().filter_map(|result| result.page_ms).collect();
+    if page_times.is_empty() {
+        return None;
+    }
+
+    Some(page_times.iter().sum::<u32>() as f64 / page_times.len() as f64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::page::ScriptedPage;
+    use serde_json::Value;
+
+    fn reaction_area(view: &str, text: &str) -> String {
+        format!(
+            r#"<div class="{} e18o0sx0 css-saet2v e19owgy77"><div><h1>{}</h1></div></div>"#,
+            view, text
+        )
+    }
 
-        // Check if "Click!" is present in the HTML content
-        if reaction_element_content.contains("Click!") {
-            click_on_pixel(click_x, click_y)?;
-            break;
+    #[test]
+    fn detects_every_state() {
+        let state_machine = reaction_state_machine();
+
+        let cases = [
+            ("view-splash", "Reaction Time Test", ReactionState::Splash),
+            ("view-waiting", "Wait for green", ReactionState::Waiting),
+            ("view-go", "Click!", ReactionState::Click),
+            ("view-result", "253 ms", ReactionState::Result),
+            ("view-result", "Too soon!", ReactionState::TooSoon),
+            ("view-score", "Reaction Time 248 ms", ReactionState::Final),
+        ];
+
+        for (view, text, state) in cases {
+            assert_eq!(
+                state_machine.detect(&reaction_area(view, text)),
+                Some(state),
+                "{} with text {:?}",
+                view,
+                text
+            );
         }
     }
 
-    Ok(())
+    #[test]
+    fn unknown_views_have_no_state() {
+        let state_machine = reaction_state_machine();
+        assert_eq!(state_machine.detect(&reaction_area("view-other", "")), None);
+        assert_eq!(state_machine.detect("<div>No reaction area</div>"), None);
+    }
+
+    #[test]
+    fn parses_reaction_ms() {
+        assert_eq!(parse_reaction_ms("253 ms\nClick to keep going"), Some(253));
+        assert_eq!(parse_reaction_ms("Reaction Time 248ms"), Some(248));
+        assert_eq!(parse_reaction_ms("Too soon!"), None);
+    }
+
+    #[test]
+    fn polling_clicks_the_green_screen() {
+        let page = ScriptedPage::new()
+            .then(reaction_area("view-splash", "Reaction Time Test"))
+            .then_for(reaction_area("view-waiting", "Wait for green"), 3)
+            .then(reaction_area("view-go", "Click!"))
+            .then(reaction_area("view-result", "180 ms"))
+            .on_evaluate(reaction_state_machine().answer_detection());
+
+        let results = play_rounds(&page, &page, ReactionMode::Polling, 1).unwrap();
+
+        assert_eq!(results.len(), 1);
+        assert_eq!(results[0].page_ms, Some(180));
+        assert!(results[0].measured_latency_ms.is_some());
+        assert_eq!(results[0].hook_dispatch_ms, None);
+        // The centre of the default window bounds of the scripted page
+        assert_eq!(page.input().clicks(), vec![Point { x: 640, y: 360 }]);
+    }
+
+    #[test]
+    fn clicks_the_green_screen_once_per_round() {
+        // The green screen is still shown on the first poll after the click
+        let page = ScriptedPage::new()
+            .then(reaction_area("view-splash", "Reaction Time Test"))
+            .then_for(reaction_area("view-waiting", "Wait for green"), 3)
+            .then(reaction_area("view-go", "Click!"))
+            .then_for(reaction_area("view-go", "Click!"), 2)
+            .then(reaction_area("view-result", "180 ms"))
+            .then_for(reaction_area("view-waiting", "Wait for green"), 3)
+            .then(reaction_area("view-go", "Click!"))
+            .then_for(reaction_area("view-go", "Click!"), 2)
+            .then(reaction_area("view-result", "210 ms"))
+            .on_evaluate(reaction_state_machine().answer_detection());
+
+        let results = play_rounds(&page, &page, ReactionMode::Polling, 2).unwrap();
+
+        let page_times: Vec<_> = results.iter().map(|result| result.page_ms).collect();
+        assert_eq!(page_times, vec![Some(180), Some(210)]);
+        assert!(results
+            .iter()
+            .all(|result| result.measured_latency_ms.is_some()));
+        assert_eq!(page.input().clicks().len(), 2);
+    }
+
+    #[test]
+    fn page_hook_reports_the_dispatch_delay_apart_from_the_latency() {
+        let page = ScriptedPage::new()
+            .then(reaction_area("view-splash", "Reaction Time Test"))
+            // The hook clicks from inside the page, so the waiting screen moves on by itself
+            .then_for(reaction_area("view-waiting", "Wait for green"), 3)
+            .then(reaction_area("view-result", "150 ms"))
+            .on_evaluate(reaction_state_machine().answer_detection())
+            .on_evaluate(|script, _, _| match script {
+                "window.__reactionHook.arm()" => Some(Value::from(0.1)),
+                _ => Some(Value::Null),
+            });
+
+        let results = play_rounds(&page, &page, ReactionMode::PageHook, 1).unwrap();
+
+        assert_eq!(results[0].page_ms, Some(150));
+        assert_eq!(results[0].hook_dispatch_ms, Some(0.1));
+        assert_eq!(results[0].measured_latency_ms, None);
+        assert!(page.input().clicks().is_empty());
+    }
 }
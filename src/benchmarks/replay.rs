@@ -0,0 +1,75 @@
+use super::chimp_test::{self, ChimpLimits};
+use super::reaction_time::ReactionMode;
+use super::typing::TypingMethod;
+use super::verbal_memory::{self, VerbalLimits};
+use super::{reaction_time, typing};
+use crate::trace::{ReplayPage, Trace};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use tracing::{info, warn};
+
+/// The solver a trace was recorded from, with the arguments and options it was called with
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "benchmark", rename_all = "snake_case")]
+pub enum TracedSolver {
+    ChimpTest {
+        #[serde(default)]
+        limits: ChimpLimits,
+    },
+    ReactionTime {
+        mode: ReactionMode,
+        rounds: u32,
+    },
+    Typing {
+        method: TypingMethod,
+    },
+    VerbalMemory {
+        #[serde(default)]
+        limits: VerbalLimits,
+    },
+}
+
+/// Replay the trace file through the solver it was recorded from, without a browser
+///
+/// The solver gets the page observations of the trace, and has to make the same decisions
+/// as in the recorded run. The options that change the decisions of a solver, like
+/// `--target-score`, come from the header of the trace. With `real_time`, every answer
+/// waits until the time it was made in the recorded run.
+///
+/// ## Errors
+/// Returns an error when the solver makes a call the trace does not have, or stops before
+/// it made every call of the trace
+pub fn run(path: &Path, real_time: bool) -> Result<()> {
+    let trace = Trace::load(path)?;
+    let solver: TracedSolver = serde_json::from_value(trace.solver.clone())
+        .context("The trace was recorded from an unknown solver")?;
+    info!(
+        "🔁 Replaying {:?} from {} ({} calls)",
+        solver,
+        path.display(),
+        trace.entries.len()
+    );
+
+    let page = match real_time {
+        true => ReplayPage::new(trace).in_real_time(),
+        false => ReplayPage::new(trace),
+    };
+    let outcome = match solver {
+        TracedSolver::ChimpTest { limits } => chimp_test::replay(&page, limits),
+        TracedSolver::ReactionTime { mode, rounds } => reaction_time::replay(&page, mode, rounds),
+        TracedSolver::Typing { method } => typing::replay(&page, method),
+        TracedSolver::VerbalMemory { limits } => verbal_memory::replay(&page, limits),
+    };
+    if let Err(e) = outcome {
+        // The recorded run can have failed the same way, the trace decides
+        warn!("The solver returned an error: {:#}", e);
+    }
+
+    let calls = page.verify()?;
+    info!(
+        "✅ The solver made the same {} calls as in the trace",
+        calls
+    );
+    Ok(())
+}
@@ -1,31 +1,42 @@
-use crate::utils::click_cookies_button;
+use crate::consent::accept_consent;
+use crate::utils::wait_until_ready;
 
+use super::manual::wait_for_result;
 use super::TestRunner;
 use anyhow::Result;
 use std::time::Duration;
+use tracing::{info, instrument};
 
+#[instrument(name = "sequence_memory")]
 pub async fn run(max_level: u32) -> Result<()> {
-    println!("🧠 Starting Sequence Memory Test");
+    info!("🧠 Starting Sequence Memory Test");
 
-    let runner = TestRunner::new()?;
-    let tab = runner.get_tab()?;
+    let runner = TestRunner::launch().await?;
+    runner
+        .guard("sequence_memory", play(&runner, max_level))
+        .await?;
 
-    tab.navigate_to("https://humanbenchmark.com/tests/sequence")?;
+    info!("✅ Sequence Memory Test completed");
 
-    // Handle cookies
-    click_cookies_button(&tab)?;
+    Ok(())
+}
 
-    // Wait for the ads to load in
-    std::thread::sleep(std::time::Duration::from_secs(5));
+/// Open the test in a new tab of the browser and wait while it is played manually
+async fn play(runner: &TestRunner, max_level: u32) -> Result<()> {
+    let tab = runner
+        .open("https://humanbenchmark.com/tests/sequence")
+        .await?;
 
-    println!("🎮 Please play the sequence memory game manually");
-    println!("   Target level: {}", max_level);
-    println!("   Watch the sequence and repeat it by clicking the squares");
+    // Handle cookies
+    accept_consent(&tab).await?;
 
-    // Wait for user to complete the game
-    std::thread::sleep(Duration::from_secs(60));
+    // Wait for the ads to load in
+    wait_until_ready(&tab, "body").await?;
 
-    println!("✅ Sequence Memory Test completed");
+    info!("🎮 Please play the sequence memory game manually");
+    info!("   Target level: {}", max_level);
+    info!("   Watch the sequence and repeat it by clicking the squares");
 
-    Ok(())
+    // Wait for user to complete the game, at most 60 seconds
+    wait_for_result(&tab, "sequence_memory", Duration::from_secs(60)).await
 }
@@ -1,167 +1,1054 @@
+use super::replay::TracedSolver;
 use super::TestRunner;
-use crate::utils::click_cookies_button;
+use crate::consent::accept_consent;
+use crate::input::{
+    Input, InputMode, KeyAction, VK_BACK, VK_CONTROL, VK_MENU, VK_RETURN, VK_SHIFT, VK_TAB,
+};
+use crate::page::Page;
+use crate::percentile;
+use crate::trace::{traced, ReplayPage};
+use crate::utils::{pause_at_end, run_blocking, wait_until_ready};
 
 use anyhow::Result;
+use scraper::{Html, Selector};
+use serde::{Deserialize, Serialize};
+use std::time::{Duration, Instant};
+use tracing::{debug, error, info, info_span, instrument, warn};
 
 #[cfg(target_os = "windows")]
-use winapi::um::winuser::{keybd_event, VkKeyScanA, KEYEVENTF_KEYUP, VK_SHIFT, VK_SPACE};
+use winapi::{
+    shared::minwindef::HKL,
+    um::winuser::{
+        GetForegroundWindow, GetKeyboardLayout, GetWindowThreadProcessId, MapVirtualKeyExW,
+        VkKeyScanExW, MAPVK_VK_TO_CHAR,
+    },
+};
 
-pub async fn run() -> Result<()> {
-    println!("⌨️  Starting Typing Test");
+// Virtual key code used by the typing logic. Defined here so the key actions can be
+// built (and tested) on every platform.
+const VK_SPACE: u8 = 0x20;
 
-    let runner = TestRunner::new()?;
-    let tab = runner.get_tab()?;
+/// Number of characters typed before checking which letters the page has rendered
+const CHUNK_SIZE: usize = 64;
 
-    tab.navigate_to("https://humanbenchmark.com/tests/typing")?;
+/// The way the text is delivered to the page
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TypingMethod {
+    /// Send every character as a Unicode key event, independent of the keyboard layout
+    Unicode,
+    /// Translate every character to the virtual keys of the active keyboard layout,
+    /// falling back to Unicode key events for characters the layout can't produce
+    Layout,
+    /// Insert the text through the Chrome DevTools Protocol (`Input.insertText`)
+    Cdp,
+}
+
+impl TypingMethod {
+    /// Parse the method from its name as typed in the menu
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name.trim().to_lowercase().as_str() {
+            "unicode" => Some(TypingMethod::Unicode),
+            "layout" => Some(TypingMethod::Layout),
+            "cdp" => Some(TypingMethod::Cdp),
+            _ => None,
+        }
+    }
+
+    /// Check that the method can type on this platform, before a browser is launched
+    ///
+    /// ## Errors
+    /// Returns an error for [`TypingMethod::Layout`] off Windows, where the keyboard layout
+    /// can't be read
+    pub fn check_supported(self) -> Result<()> {
+        if self == TypingMethod::Layout && !cfg!(target_os = "windows") {
+            anyhow::bail!(
+                "The layout typing method needs the Win32 keyboard layouts and only runs on Windows, use unicode or cdp"
+            );
+        }
+        Ok(())
+    }
+
+    /// The input backend that delivers the text of the method
+    fn input_mode(self) -> InputMode {
+        match self {
+            TypingMethod::Unicode | TypingMethod::Layout => InputMode::Os,
+            TypingMethod::Cdp => InputMode::Cdp,
+        }
+    }
+}
+
+impl Default for TypingMethod {
+    fn default() -> Self {
+        if cfg!(target_os = "windows") {
+            TypingMethod::Unicode
+        } else {
+            TypingMethod::Cdp
+        }
+    }
+}
+
+pub async fn run(method: TypingMethod) -> Result<()> {
+    info!("⌨️  Starting Typing Test");
+    method.check_supported()?;
+
+    let runner = TestRunner::launch().await?;
+    if let Some(wpm) = runner.guard("typing", play(&runner, method)).await? {
+        info!(wpm, "🏁 Result screen reports {} wpm", wpm);
+        percentile::report("typing", f64::from(wpm));
+    }
+
+    // Keep the result on screen for demos
+    pause_at_end().await?;
+
+    Ok(())
+}
+
+/// Play the test in a new tab of the browser
+///
+/// Returns the words per minute shown on the result screen, `None` when it did not appear.
+#[instrument(name = "typing", skip(runner))]
+pub async fn play(runner: &TestRunner, method: TypingMethod) -> Result<Option<u32>> {
+    let tab = runner
+        .open("https://humanbenchmark.com/tests/typing")
+        .await?;
 
     // Handle cookies
-    click_cookies_button(&tab)?;
+    accept_consent(&tab).await?;
 
     // Wait for the ads to load in
-    std::thread::sleep(std::time::Duration::from_secs(5));
+    wait_until_ready(&tab, ".e1q0za6r0.css-1c2t4mr.e19owgy77").await?;
 
+    // Typing blocks on the CDP calls and the injected input, so it runs on a dedicated thread
+    let page = tab.clone();
+    let input = method.input_mode().backend(&tab);
+    run_blocking(move || {
+        traced(
+            &TracedSolver::Typing { method },
+            page.as_ref(),
+            input.as_ref(),
+            |page, input| {
+                type_prompt(page, input, method)?;
+                read_wpm(page)
+            },
+        )
+    })
+    .await
+}
+
+/// Type the prompt on the observations of a trace
+pub fn replay(page: &ReplayPage, method: TypingMethod) -> Result<()> {
+    type_prompt(page, page, method)?;
+    read_wpm(page)?;
+    Ok(())
+}
+
+/// Focus the text area, type the prompt and retype the letters that went missing
+fn type_prompt(tab: &dyn Page, input: &dyn Input, method: TypingMethod) -> Result<()> {
     // Click the text area to focus it
     tab.wait_for_element(".e1q0za6r0.css-1c2t4mr.e19owgy77")?;
     let text_area = tab.find_element(".e1q0za6r0.css-1c2t4mr.e19owgy77")?;
     text_area.click()?;
-    println!("Clicked text area");
+    debug!("Clicked text area");
 
     // Read the text to type
     tab.wait_for_element(".letters.notranslate")?;
-    let letters_container = tab.find_element(".letters.notranslate")?;
-    println!("Found letters container");
+    debug!("Found letters container");
 
-    let text_to_type = letters_container.get_inner_text()?;
-    println!("Text to type: {}", text_to_type);
-    println!("Characters count: {}", text_to_type.len());
+    let letters = read_letters(tab)?.unwrap_or_default();
+    let text_to_type: String = letters.iter().map(|letter| letter.ch).collect();
+    debug!("Text to type: {}", text_to_type);
+    info!("Characters count: {}", letters.len());
 
-    // Wait a moment before starting to type
-    std::thread::sleep(std::time::Duration::from_millis(1000));
+    match type_in_chunks(tab, input, method) {
+        Ok(_) => info!("✅ Typing completed successfully!"),
+        Err(e) => error!("❌ Typing failed: {}", e),
+    }
 
-    // Type the text using Win32 API
-    #[cfg(target_os = "windows")]
-    {
-        match ultra_fast_typing(&text_to_type) {
-            Ok(_) => println!("✅ Typing completed successfully!"),
-            Err(e) => println!("❌ Typing failed: {}", e),
+    // Check which letters the page received and retype the ones that went missing
+    if let Err(e) = verify_typed_text(tab, input, method) {
+        error!("❌ Verifying the typed text failed: {}", e);
+    }
+
+    Ok(())
+}
+
+/// The correctness of a letter as the page marks it
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum LetterState {
+    Correct,
+    Incorrect,
+    Untyped,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Letter {
+    ch: char,
+    state: LetterState,
+}
+
+/// The keys to press to bring the typed text back in line with the prompt
+#[derive(Debug, Clone, PartialEq)]
+struct Correction {
+    /// Number of typed characters to erase, starting from the end of the typed text
+    backspaces: usize,
+    /// Text to type after erasing
+    text: String,
+}
+
+/// A character as it is produced on a keyboard layout
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+struct KeyStroke {
+    vk: u8,
+    shift: bool,
+    ctrl: bool,
+    alt: bool,
+    /// Dead keys only produce their character after another key, like space, is pressed
+    dead: bool,
+}
+
+/// Type the text into the focused element with the given method
+fn type_text(input: &dyn Input, text: &str, method: TypingMethod) -> Result<()> {
+    match method {
+        TypingMethod::Cdp => {
+            debug!("🚀 Inserting text through the DevTools Protocol...");
+            input.insert_text(text)
+        }
+        TypingMethod::Unicode | TypingMethod::Layout => {
+            debug!("🚀 Starting ultra-fast key event typing ({:?})...", method);
+
+            // Pre-calculate all key actions
+            let actions = build_key_actions(text, method, layout_lookup());
+            debug!("📋 Pre-calculated {} key actions", actions.len());
+
+            // Execute all actions at once
+            input.send_keys(&actions)
         }
     }
+}
+
+/// Type the prompt in chunks, reading the letters back from the page after every chunk
+///
+/// The page may only render part of the prompt at a time, so every chunk is taken from the
+/// letters rendered at that moment, starting at the first untyped letter.
+fn type_in_chunks(tab: &dyn Page, input: &dyn Input, method: TypingMethod) -> Result<()> {
+    let mut letters = read_letters(tab)?;
 
-    // Wait to see results
-    std::thread::sleep(std::time::Duration::from_secs(5));
+    while let Some(current_letters) = letters {
+        let Some(cursor) = next_untyped(&current_letters) else {
+            break;
+        };
+
+        let _chunk = info_span!("chunk", cursor).entered();
+        let chunk = next_chunk(&current_letters, cursor);
+        let chunk_length = chunk.chars().count();
+        type_text(input, &chunk, method)?;
+
+        letters = wait_for_cursor(tab, cursor + chunk_length)?;
+        if let Some(new_cursor) = letters.as_deref().and_then(next_untyped) {
+            if new_cursor == cursor {
+                anyhow::bail!("The page did not accept any of the typed characters");
+            }
+            if new_cursor < cursor + chunk_length {
+                warn!(
+                    "⚠️ Only {} of {} characters arrived, continuing from letter {}",
+                    new_cursor - cursor,
+                    chunk_length,
+                    new_cursor
+                );
+            }
+        }
+    }
 
     Ok(())
 }
 
-#[cfg(target_os = "windows")]
-enum KeyAction {
-    KeyPress(u8),
-    KeyRelease(u8),
-    ShiftPress,
-    ShiftRelease,
-    Space,
+/// Poll the letters until the page has processed the typed characters up to `target`
+///
+/// Returns the last letters read, or `None` when the test has finished. Gives up after a
+/// short timeout, in which case some characters were dropped.
+fn wait_for_cursor(tab: &dyn Page, target: usize) -> Result<Option<Vec<Letter>>> {
+    let start_time = Instant::now();
+
+    loop {
+        let letters = read_letters(tab)?;
+        let reached = match letters.as_deref() {
+            Some(current_letters) => {
+                next_untyped(current_letters).is_none_or(|cursor| cursor >= target)
+            }
+            None => true,
+        };
+
+        if reached || start_time.elapsed() > Duration::from_secs(2) {
+            return Ok(letters);
+        }
+
+        std::thread::sleep(Duration::from_millis(10));
+    }
 }
 
-#[cfg(target_os = "windows")]
-fn ultra_fast_typing(text: &str) -> Result<(), String> {
-    println!("🚀 Starting ultra-fast Win32 typing...");
+/// Wait for the result screen and read the words per minute it shows
+fn read_wpm(tab: &dyn Page) -> Result<Option<u32>> {
+    let start_time = Instant::now();
 
-    // Pre-calculate all key actions
-    let actions = build_key_actions(text)?;
-    println!("📋 Pre-calculated {} key actions", actions.len());
+    loop {
+        let text = tab.evaluate("document.body.innerText", false)?;
+        let wpm = text
+            .as_ref()
+            .and_then(|text| text.as_str())
+            .and_then(parse_wpm);
 
-    // Execute all actions at once
-    execute_key_actions(&actions)?;
+        if wpm.is_some() || start_time.elapsed() > Duration::from_secs(5) {
+            return Ok(wpm);
+        }
+
+        std::thread::sleep(Duration::from_millis(100));
+    }
+}
+
+/// Find the words per minute in the text of the result screen, for example "87wpm"
+fn parse_wpm(text: &str) -> Option<u32> {
+    let end = text.find("wpm")?;
+    let digits: String = text[..end]
+        .trim_end()
+        .chars()
+        .rev()
+        .take_while(|c| c.is_ascii_digit())
+        .collect();
+    digits.chars().rev().collect::<String>().parse().ok()
+}
+
+/// Read the letters currently rendered on the page, or `None` when the test has finished
+fn read_letters(tab: &dyn Page) -> Result<Option<Vec<Letter>>> {
+    // The letters disappear once the results are shown
+    let Some(letters_container) = tab
+        .find_elements(".letters.notranslate")?
+        .into_iter()
+        .next()
+    else {
+        return Ok(None);
+    };
+
+    parse_letters_from_html(&letters_container.get_content()?).map(Some)
+}
+
+/// Index of the first letter that has not been typed yet
+fn next_untyped(letters: &[Letter]) -> Option<usize> {
+    letters
+        .iter()
+        .position(|letter| letter.state == LetterState::Untyped)
+}
+
+/// The text of the next chunk to type, starting at the cursor
+fn next_chunk(letters: &[Letter], cursor: usize) -> String {
+    letters
+        .iter()
+        .skip(cursor)
+        .take(CHUNK_SIZE)
+        .map(|letter| letter.ch)
+        .collect()
+}
+
+/// Erase the last `count` typed characters with Backspace
+fn erase_text(input: &dyn Input, count: usize) -> Result<()> {
+    let mut actions = Vec::with_capacity(count * 2);
+    for _ in 0..count {
+        push_key_tap(&mut actions, VK_BACK);
+    }
+    input.send_keys(&actions)
+}
+
+/// Read back the letters from the page and report the ones that were not typed correctly
+///
+/// When the test is still running (some letters are untyped), everything from the first
+/// mistake onwards is erased and typed again.
+fn verify_typed_text(tab: &dyn Page, input: &dyn Input, method: TypingMethod) -> Result<()> {
+    // The letters disappear once the results are shown, in which case nothing can be checked
+    let Some(letters) = read_letters(tab)? else {
+        info!("✅ Test finished, the letters are no longer on the page");
+        return Ok(());
+    };
+
+    let mismatches = report_mismatches(&letters);
+    if mismatches == 0 {
+        info!("✅ All {} letters were typed correctly", letters.len());
+        return Ok(());
+    }
+    warn!(
+        "⚠️ {} of {} letters were not typed correctly",
+        mismatches,
+        letters.len()
+    );
+
+    match plan_correction(&letters) {
+        Some(correction) => {
+            info!(
+                "🔁 Erasing {} and retyping {} characters",
+                correction.backspaces,
+                correction.text.chars().count()
+            );
+            erase_text(input, correction.backspaces)?;
+            type_text(input, &correction.text, method)?;
+        }
+        None => warn!("Every letter has been typed, the mistakes can't be corrected"),
+    }
 
     Ok(())
 }
 
+/// Parse the letters and their correctness from the HTML of the letters container
+///
+/// Every letter of the prompt is its own span, also when the prompt wraps across lines or
+/// paragraphs, so the text is rebuilt from the spans instead of the rendered inner text.
+///
+/// ## Errors
+/// Returns an error when a letter span is empty, as it has no character to type and the
+/// letters after it would be typed at the wrong position
+fn parse_letters_from_html(html_content: &str) -> Result<Vec<Letter>> {
+    let document = Html::parse_fragment(html_content);
+    let letter_selector = Selector::parse(".letters.notranslate span").unwrap();
+
+    document
+        .select(&letter_selector)
+        .enumerate()
+        .map(|(index, letter_element)| {
+            let element = letter_element.value();
+            let state = if element.classes().any(|class| class == "incorrect") {
+                LetterState::Incorrect
+            } else if element.classes().any(|class| class == "correct") {
+                LetterState::Correct
+            } else {
+                LetterState::Untyped
+            };
+
+            let Some(ch) = normalize_letter(&letter_element.text().collect::<String>()) else {
+                anyhow::bail!(
+                    "Letter {} of the prompt is an empty span, the page changed how it renders the letters",
+                    index
+                );
+            };
+            Ok(Letter { ch, state })
+        })
+        .collect()
+}
+
+/// Turn the text of a letter span into the character the page expects to be typed, `None`
+/// for an empty span
+fn normalize_letter(text: &str) -> Option<char> {
+    match text {
+        "\r\n" | "\r" => Some('\n'),
+        _ => text.chars().next().map(|ch| match ch {
+            '\n' | '\t' => ch,
+            // The page renders spaces as non-breaking spaces
+            ch if ch.is_whitespace() => ' ',
+            ch => ch,
+        }),
+    }
+}
+
+/// Index after the last letter that has been typed
+fn typed_until(letters: &[Letter]) -> usize {
+    letters
+        .iter()
+        .rposition(|letter| letter.state != LetterState::Untyped)
+        .map_or(0, |index| index + 1)
+}
+
+/// Report every letter that was typed incorrectly or not at all, returning the number of them
+fn report_mismatches(letters: &[Letter]) -> usize {
+    let typed_until = typed_until(letters);
+
+    let mut mismatches = 0;
+    for (index, letter) in letters.iter().enumerate() {
+        let mismatch = match letter.state {
+            LetterState::Correct => continue,
+            LetterState::Incorrect => "typed incorrectly",
+            LetterState::Untyped if index < typed_until => "skipped",
+            LetterState::Untyped => "missing",
+        };
+        debug!("Letter {} ('{}') was {}", index, letter.ch, mismatch);
+        mismatches += 1;
+    }
+
+    mismatches
+}
+
+/// Determine how to fix the typed text, if the test is still running
+///
+/// The test ends when the last letter is typed, so a correction is only possible while
+/// there are untyped letters left.
+fn plan_correction(letters: &[Letter]) -> Option<Correction> {
+    let first_mistake = letters
+        .iter()
+        .position(|letter| letter.state != LetterState::Correct)?;
+
+    if letters
+        .iter()
+        .all(|letter| letter.state != LetterState::Untyped)
+    {
+        return None;
+    }
+
+    Some(Correction {
+        backspaces: typed_until(letters).saturating_sub(first_mistake),
+        text: letters[first_mistake..]
+            .iter()
+            .map(|letter| letter.ch)
+            .collect(),
+    })
+}
+
+/// Map characters to the key strokes of the active keyboard layout
 #[cfg(target_os = "windows")]
-fn build_key_actions(text: &str) -> Result<Vec<KeyAction>, String> {
+fn layout_lookup() -> impl Fn(char) -> Option<KeyStroke> {
+    let layout = active_keyboard_layout();
+    move |ch| layout_key_stroke(ch, layout)
+}
+
+/// Keyboard layouts can only be read with the Win32 Api, [`TypingMethod::check_supported`]
+/// keeps the layout method from running on other platforms
+#[cfg(not(target_os = "windows"))]
+fn layout_lookup() -> impl Fn(char) -> Option<KeyStroke> {
+    |_| None
+}
+
+/// Translate the text to the key actions needed to type it
+///
+/// `lookup` maps a character to the key stroke producing it on the active keyboard layout.
+/// It is only used by [`TypingMethod::Layout`]; characters it can't map are sent as
+/// Unicode key events instead, so no character is ever skipped.
+fn build_key_actions(
+    text: &str,
+    method: TypingMethod,
+    lookup: impl Fn(char) -> Option<KeyStroke>,
+) -> Vec<KeyAction> {
     let mut actions = Vec::with_capacity(text.len() * 3); // Rough estimate for capacity
 
     for ch in text.chars() {
         match ch {
-            ' ' => {
-                actions.push(KeyAction::Space);
-            }
-            '\'' => {
-                // Handle apostrophe specifically using VK_OEM_7 (0xDE)
-                actions.push(KeyAction::KeyPress(0xDE));
-                actions.push(KeyAction::KeyRelease(0xDE));
-                actions.push(KeyAction::Space);
-            }
-            '"' => {
-                // Handle apostrophe specifically using VK_OEM_7 (0xDE)
-                actions.push(KeyAction::ShiftPress);
-                actions.push(KeyAction::KeyPress(0xDE));
-                actions.push(KeyAction::KeyRelease(0xDE));
-                actions.push(KeyAction::ShiftRelease);
-                actions.push(KeyAction::Space);
-            }
-            _ if ch.is_ascii() => unsafe {
-                let vk_code = VkKeyScanA(ch as u8 as i8);
-
-                // Check if VkKeyScanA failed (returns -1/0xFFFF)
-                if vk_code == -1 {
-                    println!("VkKeyScanA failed for character: '{}'", ch);
-                    continue;
+            // Windows line endings are typed as a single Enter
+            '\r' => continue,
+            '\n' => push_key_tap(&mut actions, VK_RETURN),
+            '\t' => push_key_tap(&mut actions, VK_TAB),
+            _ => match (method, lookup(ch)) {
+                (TypingMethod::Layout, Some(stroke)) => push_key_stroke(&mut actions, stroke),
+                (TypingMethod::Layout, None) => {
+                    warn!(
+                        "No key on the keyboard layout types {:?}, sending it as a Unicode key event",
+                        ch
+                    );
+                    push_unicode(&mut actions, ch)
                 }
+                _ => push_unicode(&mut actions, ch),
+            },
+        }
+    }
+
+    actions
+}
 
-                let vk = (vk_code & 0xFF) as u8;
-                let shift_needed = (vk_code & 0x100) != 0;
+fn push_key_tap(actions: &mut Vec<KeyAction>, vk: u8) {
+    actions.push(KeyAction::KeyPress(vk));
+    actions.push(KeyAction::KeyRelease(vk));
+}
 
-                // Validate virtual key code
-                if vk == 0 {
-                    println!("Invalid virtual key code for character: '{}'", ch);
-                    continue;
-                }
+fn push_key_stroke(actions: &mut Vec<KeyAction>, stroke: KeyStroke) {
+    let modifiers = [
+        (stroke.shift, VK_SHIFT),
+        (stroke.ctrl, VK_CONTROL),
+        (stroke.alt, VK_MENU),
+    ];
 
-                if shift_needed {
-                    actions.push(KeyAction::ShiftPress);
-                }
+    for (_, vk) in modifiers.iter().filter(|(needed, _)| *needed) {
+        actions.push(KeyAction::KeyPress(*vk));
+    }
 
-                actions.push(KeyAction::KeyPress(vk));
-                actions.push(KeyAction::KeyRelease(vk));
+    push_key_tap(actions, stroke.vk);
 
-                if shift_needed {
-                    actions.push(KeyAction::ShiftRelease);
-                }
-            },
-            _ => {
-                println!("Skipping non-ASCII character: '{}'", ch);
-                continue;
-            }
-        }
+    for (_, vk) in modifiers.iter().rev().filter(|(needed, _)| *needed) {
+        actions.push(KeyAction::KeyRelease(*vk));
     }
 
-    Ok(actions)
+    // Commit the dead key so it produces its own character
+    if stroke.dead {
+        push_key_tap(actions, VK_SPACE);
+    }
 }
 
+fn push_unicode(actions: &mut Vec<KeyAction>, ch: char) {
+    // Characters outside the Basic Multilingual Plane are sent as a surrogate pair
+    let mut units = [0u16; 2];
+    for unit in ch.encode_utf16(&mut units) {
+        actions.push(KeyAction::Unicode(*unit));
+    }
+}
+
+/// Get the keyboard layout of the window that receives the key events
 #[cfg(target_os = "windows")]
-fn execute_key_actions(actions: &[KeyAction]) -> Result<(), String> {
+fn active_keyboard_layout() -> HKL {
     unsafe {
-        for action in actions {
-            match action {
-                KeyAction::KeyPress(vk) => {
-                    keybd_event(*vk, 0, 0, 0);
-                }
-                KeyAction::KeyRelease(vk) => {
-                    keybd_event(*vk, 0, KEYEVENTF_KEYUP, 0);
-                }
-                KeyAction::ShiftPress => {
-                    keybd_event(VK_SHIFT as u8, 0, 0, 0);
-                }
-                KeyAction::ShiftRelease => {
-                    keybd_event(VK_SHIFT as u8, 0, KEYEVENTF_KEYUP, 0);
-                }
-                KeyAction::Space => {
-                    keybd_event(VK_SPACE as u8, 0, 0, 0);
-                    keybd_event(VK_SPACE as u8, 0, KEYEVENTF_KEYUP, 0);
-                }
-            }
+        let thread_id = GetWindowThreadProcessId(GetForegroundWindow(), std::ptr::null_mut());
+        GetKeyboardLayout(thread_id)
+    }
+}
+
+/// Look up the key stroke producing the character on the keyboard layout
+#[cfg(target_os = "windows")]
+fn layout_key_stroke(ch: char, layout: HKL) -> Option<KeyStroke> {
+    let mut units = [0u16; 2];
+    let [unit] = ch.encode_utf16(&mut units) else {
+        // Surrogate pairs can't be produced by a single key
+        return None;
+    };
+
+    unsafe {
+        let vk_code = VkKeyScanExW(*unit, layout);
+
+        // Check if VkKeyScanExW failed (returns -1/0xFFFF)
+        if vk_code == -1 {
+            return None;
         }
+
+        let vk = (vk_code & 0xFF) as u8;
+        let shift_state = (vk_code >> 8) & 0xFF;
+
+        // The high bit of the mapped character marks a dead key
+        let dead = MapVirtualKeyExW(vk as u32, MAPVK_VK_TO_CHAR, layout) & 0x8000_0000 != 0;
+
+        Some(KeyStroke {
+            vk,
+            shift: shift_state & 1 != 0,
+            ctrl: shift_state & 2 != 0,
+            alt: shift_state & 4 != 0,
+            dead,
+        })
     }
+}
 
-    Ok(())
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::{Modifiers, RecordingInput};
+    use crate::page::ScriptedPage;
+
+    const VK_A: u8 = 0x41;
+    const VK_Q: u8 = 0x51;
+    const VK_OEM_7: u8 = 0xDE;
+
+    /// A small US layout: letters, space and the apostrophe/quote key
+    fn us_layout(ch: char) -> Option<KeyStroke> {
+        match ch {
+            'a'..='z' => Some(KeyStroke {
+                vk: ch.to_ascii_uppercase() as u8,
+                ..Default::default()
+            }),
+            'A'..='Z' => Some(KeyStroke {
+                vk: ch as u8,
+                shift: true,
+                ..Default::default()
+            }),
+            ' ' => Some(KeyStroke {
+                vk: VK_SPACE,
+                ..Default::default()
+            }),
+            '\'' => Some(KeyStroke {
+                vk: VK_OEM_7,
+                ..Default::default()
+            }),
+            '"' => Some(KeyStroke {
+                vk: VK_OEM_7,
+                shift: true,
+                ..Default::default()
+            }),
+            _ => None,
+        }
+    }
+
+    /// US-International, where the apostrophe and quote are dead keys
+    fn us_international_layout(ch: char) -> Option<KeyStroke> {
+        us_layout(ch).map(|stroke| KeyStroke {
+            dead: matches!(ch, '\'' | '"'),
+            ..stroke
+        })
+    }
+
+    /// German layout, where `@` is typed with AltGr (Ctrl + Alt) and Q
+    fn german_layout(ch: char) -> Option<KeyStroke> {
+        match ch {
+            '@' => Some(KeyStroke {
+                vk: VK_Q,
+                ctrl: true,
+                alt: true,
+                ..Default::default()
+            }),
+            _ => us_layout(ch),
+        }
+    }
+
+    /// The character a key stroke types on the layout, the lookup the other way around
+    fn reverse_layout(
+        layout: impl Fn(char) -> Option<KeyStroke>,
+    ) -> impl Fn(u8, Modifiers) -> Option<char> {
+        move |vk, modifiers| {
+            (' '..='~').find(|ch| {
+                layout(*ch).is_some_and(|stroke| {
+                    stroke.vk == vk
+                        && Modifiers {
+                            shift: stroke.shift,
+                            ctrl: stroke.ctrl,
+                            alt: stroke.alt,
+                        } == modifiers
+                })
+            })
+        }
+    }
+
+    /// The text the key actions type on the layout
+    fn typed_on(actions: &[KeyAction], layout: impl Fn(char) -> Option<KeyStroke>) -> String {
+        let input = RecordingInput::new();
+        input.send_keys(actions).unwrap();
+        input.typed_text_on(reverse_layout(layout))
+    }
+
+    fn unicode_units(text: &str) -> Vec<KeyAction> {
+        text.encode_utf16().map(KeyAction::Unicode).collect()
+    }
+
+    #[test]
+    fn unicode_sends_every_character() {
+        let text = "“Don’t” — café, naïve, Straße";
+        let actions = build_key_actions(text, TypingMethod::Unicode, us_layout);
+        assert_eq!(actions, unicode_units(text));
+    }
+
+    #[test]
+    fn unicode_sends_surrogate_pairs() {
+        let actions = build_key_actions("a😀", TypingMethod::Unicode, us_layout);
+        assert_eq!(
+            actions,
+            vec![
+                KeyAction::Unicode(0x61),
+                KeyAction::Unicode(0xD83D),
+                KeyAction::Unicode(0xDE00),
+            ]
+        );
+    }
+
+    #[test]
+    fn quotes_are_not_followed_by_an_extra_space() {
+        let actions = build_key_actions("it's \"ok\"", TypingMethod::Unicode, us_layout);
+        assert_eq!(actions, unicode_units("it's \"ok\""));
+
+        let actions = build_key_actions("'", TypingMethod::Layout, us_layout);
+        assert_eq!(
+            actions,
+            vec![
+                KeyAction::KeyPress(VK_OEM_7),
+                KeyAction::KeyRelease(VK_OEM_7)
+            ]
+        );
+
+        let actions = build_key_actions("it's \"ok\"", TypingMethod::Layout, us_layout);
+        assert_eq!(typed_on(&actions, us_layout), "it's \"ok\"");
+    }
+
+    #[test]
+    fn newlines_press_enter() {
+        let actions = build_key_actions("a\r\nb", TypingMethod::Unicode, us_layout);
+        assert_eq!(
+            actions,
+            vec![
+                KeyAction::Unicode(0x61),
+                KeyAction::KeyPress(VK_RETURN),
+                KeyAction::KeyRelease(VK_RETURN),
+                KeyAction::Unicode(0x62),
+            ]
+        );
+    }
+
+    #[test]
+    fn layout_wraps_keys_in_modifiers() {
+        let actions = build_key_actions("Aa", TypingMethod::Layout, us_layout);
+        assert_eq!(
+            actions,
+            vec![
+                KeyAction::KeyPress(VK_SHIFT),
+                KeyAction::KeyPress(VK_A),
+                KeyAction::KeyRelease(VK_A),
+                KeyAction::KeyRelease(VK_SHIFT),
+                KeyAction::KeyPress(VK_A),
+                KeyAction::KeyRelease(VK_A),
+            ]
+        );
+        assert_eq!(typed_on(&actions, us_layout), "Aa");
+
+        let actions = build_key_actions("@", TypingMethod::Layout, german_layout);
+        assert_eq!(
+            actions,
+            vec![
+                KeyAction::KeyPress(VK_CONTROL),
+                KeyAction::KeyPress(VK_MENU),
+                KeyAction::KeyPress(VK_Q),
+                KeyAction::KeyRelease(VK_Q),
+                KeyAction::KeyRelease(VK_MENU),
+                KeyAction::KeyRelease(VK_CONTROL),
+            ]
+        );
+        assert_eq!(typed_on(&actions, german_layout), "@");
+
+        // Layout key strokes and the Unicode fallback decode to the text together
+        let text = "Mail me @ Café";
+        let actions = build_key_actions(text, TypingMethod::Layout, german_layout);
+        assert_eq!(typed_on(&actions, german_layout), text);
+    }
+
+    #[test]
+    fn layout_commits_dead_keys_with_space() {
+        let actions = build_key_actions("\"", TypingMethod::Layout, us_international_layout);
+        assert_eq!(
+            actions,
+            vec![
+                KeyAction::KeyPress(VK_SHIFT),
+                KeyAction::KeyPress(VK_OEM_7),
+                KeyAction::KeyRelease(VK_OEM_7),
+                KeyAction::KeyRelease(VK_SHIFT),
+                KeyAction::KeyPress(VK_SPACE),
+                KeyAction::KeyRelease(VK_SPACE),
+            ]
+        );
+    }
+
+    #[test]
+    fn layout_falls_back_to_unicode_for_unmapped_characters() {
+        let actions = build_key_actions("é’", TypingMethod::Layout, us_layout);
+        assert_eq!(actions, unicode_units("é’"));
+        assert_eq!(typed_on(&actions, us_layout), "é’");
+    }
+
+    #[test]
+    fn unicode_typing_ignores_the_layout_lookup() {
+        let actions = build_key_actions("Hi", TypingMethod::Unicode, german_layout);
+        assert_eq!(actions, unicode_units("Hi"));
+    }
+
+    fn letters(text: &str, states: &str) -> Vec<Letter> {
+        text.chars()
+            .zip(states.chars())
+            .map(|(ch, state)| Letter {
+                ch,
+                state: match state {
+                    'c' => LetterState::Correct,
+                    'x' => LetterState::Incorrect,
+                    _ => LetterState::Untyped,
+                },
+            })
+            .collect()
+    }
+
+    #[test]
+    fn layout_method_only_runs_on_windows() {
+        assert!(TypingMethod::Unicode.check_supported().is_ok());
+        assert!(TypingMethod::Cdp.check_supported().is_ok());
+        assert_eq!(
+            TypingMethod::Layout.check_supported().is_ok(),
+            cfg!(target_os = "windows")
+        );
+    }
+
+    #[test]
+    fn parses_letter_states() {
+        let html = r#"<div class="letters notranslate"><span class="correct">H</span><span class="incorrect">i</span><span>&nbsp;</span><span class="">x</span></div>"#;
+        assert_eq!(
+            parse_letters_from_html(html).unwrap(),
+            letters("Hi x", "cx..")
+        );
+    }
+
+    #[test]
+    fn correction_retypes_from_first_dropped_key() {
+        // "b" was dropped, so the letters after it were typed one position too early
+        let correction = plan_correction(&letters("abcde", "cxx.."));
+        assert_eq!(
+            correction,
+            Some(Correction {
+                backspaces: 2,
+                text: "bcde".to_string(),
+            })
+        );
+
+        // The last keys were dropped, only the missing letters have to be typed
+        let correction = plan_correction(&letters("abcde", "ccc.."));
+        assert_eq!(
+            correction,
+            Some(Correction {
+                backspaces: 0,
+                text: "de".to_string(),
+            })
+        );
+    }
+
+    #[test]
+    fn no_correction_when_finished_or_correct() {
+        assert_eq!(plan_correction(&letters("abc", "ccc")), None);
+        assert_eq!(plan_correction(&letters("abc", "cxc")), None);
+    }
+
+    #[test]
+    fn reports_all_mismatches() {
+        assert_eq!(report_mismatches(&letters("abcde", "cx.c.")), 3);
+        assert_eq!(report_mismatches(&letters("abc", "ccc")), 0);
+    }
+
+    // Built after the markup of the live typing test rather than saved from it: a span per
+    // letter in `.letters.notranslate`, spaces as `&nbsp;`
+    const PROMPT_FIXTURE: &str = include_str!("../../tests/fixtures/typing/prompt.html");
+    const MULTI_PARAGRAPH_FIXTURE: &str =
+        include_str!("../../tests/fixtures/typing/multi_paragraph.html");
+    const PARTIALLY_TYPED_FIXTURE: &str =
+        include_str!("../../tests/fixtures/typing/partially_typed.html");
+
+    fn text_of(letters: &[Letter]) -> String {
+        letters.iter().map(|letter| letter.ch).collect()
+    }
+
+    #[test]
+    fn rebuilds_prompt_from_letter_spans() {
+        let letters = parse_letters_from_html(PROMPT_FIXTURE).unwrap();
+        assert_eq!(
+            text_of(&letters),
+            "“Don’t panic,” said the café owner, handing over a crème brûlée."
+        );
+        assert!(letters
+            .iter()
+            .all(|letter| letter.state == LetterState::Untyped));
+    }
+
+    #[test]
+    fn rebuilds_multi_paragraph_prompt_with_whitespace() {
+        let letters = parse_letters_from_html(MULTI_PARAGRAPH_FIXTURE).unwrap();
+        assert_eq!(
+            text_of(&letters),
+            "The first paragraph wraps across two lines.\n\tThe second one is indented."
+        );
+    }
+
+    #[test]
+    fn multi_paragraph_prompt_presses_enter_and_tab() {
+        let text = text_of(&parse_letters_from_html(MULTI_PARAGRAPH_FIXTURE).unwrap());
+        let actions = build_key_actions(&text, TypingMethod::Unicode, us_layout);
+
+        let enter = actions
+            .iter()
+            .filter(|action| **action == KeyAction::KeyPress(VK_RETURN))
+            .count();
+        let tab = actions
+            .iter()
+            .filter(|action| **action == KeyAction::KeyPress(VK_TAB))
+            .count();
+        assert_eq!((enter, tab), (1, 1));
+    }
+
+    #[test]
+    fn continues_from_the_first_untyped_letter() {
+        let letters = parse_letters_from_html(PARTIALLY_TYPED_FIXTURE).unwrap();
+        let cursor = next_untyped(&letters);
+        assert_eq!(cursor, Some(26));
+        assert_eq!(
+            next_chunk(&letters, 26),
+            "it was the worst of times, it was the age of wisdom"
+        );
+    }
+
+    #[test]
+    fn chunks_long_prompts() {
+        let letters = letters(&"a".repeat(150), &".".repeat(150));
+        assert_eq!(next_chunk(&letters, 0).len(), CHUNK_SIZE);
+        assert_eq!(next_chunk(&letters, 128).len(), 22);
+    }
+
+    #[test]
+    fn finished_prompt_has_no_cursor() {
+        assert_eq!(next_untyped(&letters("abc", "cxc")), None);
+    }
+
+    #[test]
+    fn normalizes_letter_whitespace() {
+        assert_eq!(normalize_letter("\u{a0}"), Some(' '));
+        assert_eq!(normalize_letter("\r\n"), Some('\n'));
+        assert_eq!(normalize_letter("\t"), Some('\t'));
+        assert_eq!(normalize_letter("é"), Some('é'));
+        assert_eq!(normalize_letter(""), None);
+    }
+
+    #[test]
+    fn rejects_empty_letter_spans() {
+        let html =
+            r#"<div class="letters notranslate"><span>a</span><span></span><span>b</span></div>"#;
+        let error = parse_letters_from_html(html).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Letter 1 of the prompt is an empty span, the page changed how it renders the letters"
+        );
+    }
+
+    #[test]
+    fn parses_wpm_from_the_result_screen() {
+        assert_eq!(parse_wpm("Typing Test\n87wpm\nSave score"), Some(87));
+        assert_eq!(parse_wpm("Your speed: 112 wpm"), Some(112));
+        assert_eq!(parse_wpm("How many words per minute can you type?"), None);
+    }
+
+    #[test]
+    fn types_and_corrects_exactly_the_text() {
+        for method in [TypingMethod::Unicode, TypingMethod::Cdp] {
+            let input = RecordingInput::new();
+            type_text(&input, "The quick\nfox 😀", method).unwrap();
+            erase_text(&input, 1).unwrap();
+            type_text(&input, "x!", method).unwrap();
+
+            assert_eq!(input.typed_text(), "The quick\nfox x!", "{:?}", method);
+            assert!(input.clicks().is_empty());
+        }
+    }
+
+    /// The typing screen with the first `typed` letters of the prompt typed correctly
+    fn typing_screen(prompt: &str, typed: usize) -> String {
+        let spans: String = prompt
+            .chars()
+            .enumerate()
+            .map(|(index, ch)| match index < typed {
+                true => format!(r#"<span class="correct">{}</span>"#, ch),
+                false => format!("<span>{}</span>", ch),
+            })
+            .collect();
+        format!(
+            r#"<body><div class="e1q0za6r0 css-1c2t4mr e19owgy77"><div class="letters notranslate">{}</div></div></body>"#,
+            spans
+        )
+    }
+
+    #[test]
+    fn only_a_missing_prompt_means_finished() {
+        let results = ScriptedPage::new().then("<body><h1>87wpm</h1></body>");
+        assert_eq!(read_letters(&results).unwrap(), None);
+
+        // A page that cannot be read is an error, not the end of the test
+        assert!(read_letters(&ScriptedPage::new()).is_err());
+    }
+
+    #[test]
+    fn types_the_prompt_in_chunks() {
+        let prompt = "the quick brown fox jumps over the lazy dog ".repeat(2);
+        let page = ScriptedPage::new()
+            .then(typing_screen(&prompt, 0))
+            .then(typing_screen(&prompt, 0))
+            .then(typing_screen(&prompt, CHUNK_SIZE))
+            .then("<body><h1>87wpm</h1></body>");
+
+        type_prompt(&page, &page, TypingMethod::Cdp).unwrap();
+
+        assert_eq!(read_wpm(&page).unwrap(), Some(87));
+        assert_eq!(page.input().typed_text(), prompt);
+        assert_eq!(page.input().events().len(), 2);
+    }
 }
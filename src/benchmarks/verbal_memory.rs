@@ -1,50 +1,482 @@
+use crate::consent::accept_consent;
+use crate::input::{Input, InputMode};
+use crate::options::options;
+use crate::page::Page;
+use crate::percentile;
+use crate::state_machine::{Detection, StateMachine, Step};
+use crate::trace::{traced, ReplayPage};
 use crate::utils::{
-    click_cookies_button, click_on_pixel, determ_center_of_element, is_kill_switch_pressed, Point,
+    determ_center_of_element, is_kill_switch_pressed, number_after_label, pause_at_end,
+    run_blocking, wait_until_ready, Point, RESULT_SCREEN_SELECTOR,
 };
 
+use super::replay::TracedSolver;
 use super::TestRunner;
-use anyhow::Result;
-use headless_chrome::Tab;
-use std::{collections::HashSet, sync::Arc, time::Duration};
+use anyhow::{bail, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::{BTreeMap, HashSet},
+    fs,
+    path::Path,
+    time::{Duration, Instant},
+};
+use tracing::{info, info_span, instrument, warn};
+
+/// Lives at the start of the game, every wrong answer costs one
+const LIVES: u32 = 3;
+
+/// Time for the game to move on after an answer, before the answer is given again
+const ANSWER_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Times the same answer is given again before the game is considered stuck
+const MAX_RETRIES: u32 = 2;
+
+/// Longest a game is played, in case it never ends on its own
+const GAME_TIMEOUT: Duration = Duration::from_secs(30 * 60);
+
+/// Time between two reads of the word while the game has not moved on
+const POLL_INTERVAL: Duration = Duration::from_millis(2);
+
+/// Script that counts the changes of the game, so every new word is noticed
+///
+/// Comparing the word with the previous one misses a word that is shown twice in a row.
+/// Every answer changes the score or the lives, so the text of the game container changes
+/// with every new word, also when the word itself stays the same. A `MutationObserver`
+/// counts those changes, and `read()` returns the count with the current word as JSON.
+const WORD_WATCHER_SCRIPT: &str = r#"
+(() => {
+    if (window.__wordWatcher) {
+        return;
+    }
+
+    const word = document.querySelector('.word');
+    // The closest container of the word that also holds the lives and score
+    let game = word;
+    while (game.parentElement && !/lives/i.test(game.textContent)) {
+        game = game.parentElement;
+    }
+
+    const watcher = { changes: 0, text: game.textContent };
+    window.__wordWatcher = watcher;
+
+    new MutationObserver(() => {
+        if (game.textContent !== watcher.text) {
+            watcher.text = game.textContent;
+            watcher.changes += 1;
+        }
+    }).observe(game, { subtree: true, childList: true, characterData: true });
+
+    watcher.read = () => JSON.stringify({
+        changes: watcher.changes,
+        word: document.querySelector('.word')?.textContent ?? '',
+    });
+})()
+"#;
+
+/// When the solver stops or answers wrong on purpose, from the options
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct VerbalLimits {
+    /// Words to answer before stopping, from `--max-words`
+    pub max_words: Option<u32>,
+    /// Score from which every answer is wrong, from `--target-score`
+    pub target_score: Option<u32>,
+}
+
+impl VerbalLimits {
+    pub fn from_options() -> Self {
+        Self {
+            max_words: options().max_words,
+            target_score: options().target_score,
+        }
+    }
+}
+
+/// The word on the page and the number of changes of the game before it was read
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+struct Observation {
+    changes: u64,
+    word: String,
+}
+
+/// The buttons below the word
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Answer {
+    Seen,
+    New,
+}
+
+/// Decides the answers from what the page shows, apart from the page so it can be tested
+#[derive(Debug, Default)]
+struct VerbalSolver {
+    /// Every word shown so far in this game
+    seen_words: HashSet<String>,
+    /// Change count of the observation that was answered last
+    last_change: Option<u64>,
+    /// The last answer and when it was given
+    last_answer: Option<(Answer, Instant)>,
+    /// Times the last answer was given again
+    retries: u32,
+}
+
+impl VerbalSolver {
+    /// The answer for the word, `None` when the game has not moved on since the last answer
+    fn answer(&mut self, observation: &Observation, now: Instant) -> Option<Answer> {
+        if observation.word.is_empty() || self.last_change == Some(observation.changes) {
+            return None;
+        }
+        self.last_change = Some(observation.changes);
+        self.retries = 0;
+
+        // Check if the word has been seen before. If not, add it to the list
+        let answer = if self.seen_words.insert(observation.word.clone()) {
+            Answer::New
+        } else {
+            Answer::Seen
+        };
+        self.last_answer = Some((answer, now));
+        Some(answer)
+    }
+
+    /// The last answer again when the game did not move on for [`ANSWER_TIMEOUT`] after it,
+    /// like when the click got lost
+    ///
+    /// Fails when the answer was already given again [`MAX_RETRIES`] times.
+    fn retry(&mut self, observation: &Observation, now: Instant) -> Result<Option<Answer>> {
+        let Some((answer, answered_at)) = self.last_answer else {
+            return Ok(None);
+        };
+        if self.last_change != Some(observation.changes)
+            || now.duration_since(answered_at) < ANSWER_TIMEOUT
+        {
+            return Ok(None);
+        }
+        if self.retries >= MAX_RETRIES {
+            bail!(
+                "The game did not move on after answering \"{}\" {} times",
+                observation.word,
+                self.retries + 1
+            );
+        }
+
+        self.retries += 1;
+        self.last_answer = Some((answer, now));
+        Ok(Some(answer))
+    }
+}
+
+/// The "Lives | Score" header above the word
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Status {
+    lives: u32,
+    score: u32,
+}
+
+/// Outcome of a run of the solver
+struct VerbalRun {
+    /// Correct answers given
+    score: u32,
+    /// Every word the page showed during the run
+    words: HashSet<String>,
+}
 
+/// The words the test has shown over all runs, with the number of runs they appeared in
+///
+/// Stored as a text file with a word and its count on every line, separated by a tab.
+#[derive(Debug, Default, PartialEq)]
+pub struct WordCorpus {
+    words: BTreeMap<String, u32>,
+}
+
+impl WordCorpus {
+    /// Load the corpus from the file, empty when the file does not exist yet
+    pub fn load(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("Could not read the word corpus {}", path.display()))?;
+        Self::parse(&text)
+    }
+
+    fn parse(text: &str) -> Result<Self> {
+        let mut words = BTreeMap::new();
+        for line in text.lines().filter(|line| !line.trim().is_empty()) {
+            let (word, count) = match line.split_once('\t') {
+                Some((word, count)) => (
+                    word,
+                    count
+                        .trim()
+                        .parse()
+                        .with_context(|| format!("Invalid count in word corpus line: {}", line))?,
+                ),
+                None => (line, 1),
+            };
+            *words.entry(word.trim().to_string()).or_insert(0) += count;
+        }
+        Ok(Self { words })
+    }
+
+    /// Count the words of a run, returns how many of them were not in the corpus before
+    fn record_run<'a>(&mut self, words: impl IntoIterator<Item = &'a String>) -> usize {
+        let mut new_words = 0;
+        for word in words {
+            let count = self.words.entry(word.clone()).or_insert(0);
+            if *count == 0 {
+                new_words += 1;
+            }
+            *count += 1;
+        }
+        new_words
+    }
+
+    fn to_text(&self) -> String {
+        self.words
+            .iter()
+            .map(|(word, count)| format!("{}\t{}\n", word, count))
+            .collect()
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        fs::write(path, self.to_text())
+            .with_context(|| format!("Could not write the word corpus {}", path.display()))
+    }
+
+    pub fn len(&self) -> usize {
+        self.words.len()
+    }
+}
+
+#[instrument(name = "verbal_memory")]
 pub async fn run() -> Result<()> {
-    println!("📝 Starting Verbal Memory Test");
+    info!("📝 Starting Verbal Memory Test");
+
+    let runner = TestRunner::launch().await?;
+    let result = runner.guard("verbal_memory", play(&runner)).await?;
 
-    let runner = TestRunner::new()?;
-    let tab = runner.get_tab()?;
+    info!(
+        "✅ Verbal Memory Test completed with a score of {} after {} different words",
+        result.score,
+        result.words.len()
+    );
+    percentile::report("verbal_memory", f64::from(result.score));
 
-    tab.navigate_to("https://humanbenchmark.com/tests/verbal-memory")?;
+    // Add the words of this run to the corpus on disk
+    if options().save_words {
+        let path = &options().word_corpus;
+        let mut corpus = WordCorpus::load(path)?;
+        let new_words = corpus.record_run(&result.words);
+        corpus.save(path)?;
+        info!(
+            "Saved {} words to {} ({} new, {} in total)",
+            result.words.len(),
+            path.display(),
+            new_words,
+            corpus.len()
+        );
+    }
+
+    // Keep the result on screen for demos
+    pause_at_end().await?;
+
+    Ok(())
+}
+
+/// Play the test in a new tab of the browser and return the score and the words it showed
+async fn play(runner: &TestRunner) -> Result<VerbalRun> {
+    let tab = runner
+        .open("https://humanbenchmark.com/tests/verbal-memory")
+        .await?;
 
     // Handle cookies
-    click_cookies_button(&tab)?;
+    accept_consent(&tab).await?;
 
     // Wait for the ads to load in
-    std::thread::sleep(std::time::Duration::from_secs(5));
+    wait_until_ready(&tab, ".css-de05nr.e19owgy710").await?;
+
+    // The solver clicks in a tight loop, so it runs on a dedicated thread
+    let page = tab.clone();
+    let input = InputMode::Os.backend(&tab);
+    let limits = VerbalLimits::from_options();
+    run_blocking(move || {
+        traced(
+            &TracedSolver::VerbalMemory { limits },
+            page.as_ref(),
+            input.as_ref(),
+            |page, input| verbal_memory_actions(page, input, limits),
+        )
+    })
+    .await
+}
 
-    verbal_memory_actions(&tab)?;
+/// Play the test on the observations of a trace
+pub fn replay(page: &ReplayPage, limits: VerbalLimits) -> Result<()> {
+    verbal_memory_actions(page, page, limits)?;
+    Ok(())
+}
 
-    println!("✅ Verbal Memory Test completed");
+/// Print the words collected with `--save-words`, the most frequent first
+pub fn print_words() -> Result<()> {
+    let path = &options().word_corpus;
+    let corpus = WordCorpus::load(path)?;
 
-    // Wait to see the result
-    std::thread::sleep(Duration::from_secs(5));
+    let mut words: Vec<(&String, &u32)> = corpus.words.iter().collect();
+    words.sort_by(|a, b| b.1.cmp(a.1).then_with(|| a.0.cmp(b.0)));
+    for (word, count) in words {
+        println!("{}\t{}", count, word);
+    }
 
+    println!("{} words in {}", corpus.len(), path.display());
     Ok(())
 }
 
-fn verbal_memory_actions(tab: &Arc<Tab>) -> Result<()> {
-    // Find and click the start button
-    tab.wait_for_element(".css-de05nr.e19owgy710")?;
-    let start_button = tab.find_element(".css-de05nr.e19owgy710")?;
-    start_button.click()?;
+/// Find the lives and score in the text of the page, `None` when the header is not shown
+fn parse_status(text: &str) -> Option<Status> {
+    Some(Status {
+        lives: number_after_label(text, "lives")?,
+        score: number_after_label(text, "score")?,
+    })
+}
+
+/// Read the current word and change count from the word watcher
+fn read_observation(tab: &dyn Page) -> Result<Observation> {
+    let value = tab.evaluate("window.__wordWatcher.read()", false)?;
+    let json = value
+        .as_ref()
+        .and_then(|value| value.as_str())
+        .ok_or_else(|| anyhow::anyhow!("The word watcher did not return JSON"))?;
+    Ok(serde_json::from_str(json)?)
+}
+
+fn read_status(tab: &dyn Page) -> Result<Option<Status>> {
+    let text = tab.evaluate("document.body.innerText", false)?;
+    Ok(text
+        .as_ref()
+        .and_then(|text| text.as_str())
+        .and_then(parse_status))
+}
+
+/// Wait for the header to change after an answer, returns the last status read
+fn wait_for_status_change(tab: &dyn Page, before: Option<Status>) -> Result<Option<Status>> {
+    let start_time = Instant::now();
+
+    loop {
+        let status = read_status(tab)?;
+        if status != before || start_time.elapsed() > Duration::from_millis(500) {
+            return Ok(status);
+        }
+        std::thread::sleep(Duration::from_millis(2));
+    }
+}
+
+/// Explain why the header did not change as expected after an answer, `None` when it did
+///
+/// A correct answer adds a point, a wrong one costs a life.
+fn diagnose(before: Status, after: Option<Status>, expect_correct: bool) -> Option<String> {
+    let Some(after) = after else {
+        return Some("the lives and score are no longer shown".to_string());
+    };
+
+    let expected = if expect_correct {
+        Status {
+            score: before.score + 1,
+            ..before
+        }
+    } else {
+        Status {
+            lives: before.lives.saturating_sub(1),
+            ..before
+        }
+    };
+    if after == expected {
+        return None;
+    }
+
+    let diagnosis = if after == before {
+        "missed click, the page did not register the answer".to_string()
+    } else if expect_correct && after.lives < before.lives {
+        "the answer was wrong, the word probably changed right before the click".to_string()
+    } else if !expect_correct && after.score > before.score {
+        "the deliberate mistake was counted as a correct answer".to_string()
+    } else {
+        format!(
+            "expected lives {} and score {}, the page shows lives {} and score {}",
+            expected.lives, expected.score, after.lives, after.score
+        )
+    };
+    Some(diagnosis)
+}
+
+/// Whether to answer wrong on purpose, to end the game once the target score is reached
+fn should_make_mistake(score: u32, target_score: Option<u32>) -> bool {
+    target_score.is_some_and(|target_score| score >= target_score)
+}
+
+/// The screens of the test
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum VerbalState {
+    /// The start screen with the start button
+    Start,
+    /// The game screen with the word, for the whole game
+    Playing,
+    /// The result screen after the last life
+    Result,
+}
+
+/// The states of the test and the rules that detect them
+fn verbal_state_machine() -> StateMachine<VerbalState> {
+    use VerbalState::*;
+
+    StateMachine::new("verbal")
+        .state(Result, Detection::selector(RESULT_SCREEN_SELECTOR))
+        // The SEEN and NEW buttons look like the start button
+        .state(Playing, Detection::selector(".word"))
+        .state(Start, Detection::selector(".css-de05nr.e19owgy710"))
+        .transition(Start, Playing)
+        .transition(Playing, Result)
+}
+
+fn verbal_memory_actions(
+    tab: &dyn Page,
+    input: &dyn Input,
+    limits: VerbalLimits,
+) -> Result<VerbalRun> {
+    let mut solver = VerbalSolver::default();
+    let mut score = 0;
+
+    let mut state_machine = verbal_state_machine();
+    state_machine.run(tab, |state, _| match state {
+        VerbalState::Start => {
+            // Find and click the start button
+            tab.find_element(".css-de05nr.e19owgy710")?.click()?;
+            Ok(Step::Continue)
+        }
+        VerbalState::Playing => play_words(tab, input, limits, &mut solver, &mut score),
+        VerbalState::Result => {
+            info!("🏁 Test completed - found completion element");
+            Ok(Step::Stop)
+        }
+    })?;
+
+    Ok(VerbalRun {
+        score,
+        words: solver.seen_words,
+    })
+}
 
-    // Get browser window position on screen
-    let window_bounds = tab.get_bounds()?;
-    let window_x = window_bounds.left as i32;
-    let window_y = window_bounds.top as i32;
-    let x_offset_browser = 5; // Offset to take the browser url bar and toolbar into account
-    let y_offset_browser = 140; // Offset to take the browser url bar and toolbar into account
+/// Answer the words of the game screen until the game ends or a limit is reached
+///
+/// Returns [`Step::Continue`] once the word is gone from the page, so the result screen is
+/// waited for, and [`Step::Stop`] when the solver ended the game itself.
+fn play_words(
+    tab: &dyn Page,
+    input: &dyn Input,
+    limits: VerbalLimits,
+    solver: &mut VerbalSolver,
+    score: &mut u32,
+) -> Result<Step> {
+    // Get the position of the page in the coordinates of the input
+    let origin = input.origin()?;
 
-    let mut seen_words: HashSet<String> = HashSet::new();
     let mut new_button_position: Option<Point> = None;
     let mut seen_button_position: Option<Point> = None;
 
@@ -54,52 +486,355 @@ fn verbal_memory_actions(tab: &Arc<Tab>) -> Result<()> {
         match button.get_inner_text()?.as_str() {
             "SEEN" => {
                 seen_button_position = Some(determ_center_of_element(
-                    &button,
-                    &window_x,
-                    &window_y,
-                    &x_offset_browser,
-                    &y_offset_browser,
+                    &button, &origin.x, &origin.y, &0, &0,
                 )?);
             }
             "NEW" => {
                 new_button_position = Some(determ_center_of_element(
-                    &button,
-                    &window_x,
-                    &window_y,
-                    &x_offset_browser,
-                    &y_offset_browser,
+                    &button, &origin.x, &origin.y, &0, &0,
                 )?);
             }
             _ => {}
         }
     }
+    let seen_button_position = seen_button_position.context("SEEN button not found")?;
+    let new_button_position = new_button_position.context("NEW button not found")?;
+
+    // Start counting the changes of the game
+    tab.wait_for_element(".word")?;
+    tab.evaluate(WORD_WATCHER_SCRIPT, false)?;
 
-    // Initialize the "word" element
-    let word_element = tab.find_element(".word")?;
-    let mut last_word = String::new();
+    let mut words_answered = 0;
+    let mut mistakes = 0;
+    let mut status = read_status(tab)?;
+    let start_time = Instant::now();
+    let mut word_shown_at = start_time;
 
     while !is_kill_switch_pressed() {
-        let word = word_element.get_inner_text()?;
-
-        // Only process if this is a new word (different from the last one we processed)
-        if last_word != word {
-            // Check if the word has been seen before. If not, add it to the list and continue
-            if !seen_words.contains(&word) {
-                seen_words.insert(word.clone()); // Only clone when inserting into HashSet
-
-                // Click the "NEW" button
-                let position = new_button_position.as_ref().unwrap();
-                click_on_pixel(position.x, position.y)?;
-            } else {
-                // Click the "SEEN" button
-                let position = seen_button_position.as_ref().unwrap();
-                click_on_pixel(position.x, position.y)?;
+        if start_time.elapsed() > GAME_TIMEOUT {
+            warn!(
+                "⏱️ Still playing after {} minutes, stopping",
+                GAME_TIMEOUT.as_secs() / 60
+            );
+            break;
+        }
+
+        if limits
+            .max_words
+            .is_some_and(|max_words| words_answered >= max_words)
+        {
+            info!("🏁 Answered {} words, stopping", words_answered);
+            break;
+        }
+
+        // Only answer once the game moved on to the next word, which can be the same word again,
+        // or again when the last answer did not land
+        let now = Instant::now();
+        let observation = read_observation(tab)?;
+        if !observation.word.is_empty() {
+            word_shown_at = now;
+        } else if tab.find_elements(".word")?.is_empty() {
+            // The game screen is gone, the result screen is next
+            return Ok(Step::Continue);
+        } else if now.duration_since(word_shown_at) > options().ready_timeout {
+            bail!(
+                "No word was shown for {} seconds",
+                options().ready_timeout.as_secs_f64()
+            );
+        }
+        let (answer, retry) = match solver.answer(&observation, now) {
+            Some(answer) => (answer, false),
+            None => match solver.retry(&observation, now)? {
+                Some(answer) => (answer, true),
+                None => {
+                    std::thread::sleep(POLL_INTERVAL);
+                    continue;
+                }
+            },
+        };
+
+        if !retry {
+            words_answered += 1;
+        }
+        let word = observation.word;
+        let _word = info_span!("word", number = words_answered).entered();
+        let seen = answer == Answer::Seen;
+
+        // Once the target score is reached, give the wrong answer until the lives run out
+        let mistake = should_make_mistake(*score, limits.target_score);
+        let position = if seen != mistake {
+            // Click the "SEEN" button
+            &seen_button_position
+        } else {
+            // Click the "NEW" button
+            &new_button_position
+        };
+        input.click(position)?;
+
+        if retry {
+            warn!(
+                "⚠️ The game did not move on after answering \"{}\", answering again",
+                word
+            );
+        } else if mistake {
+            mistakes += 1;
+            info!(
+                "Answered \"{}\" wrong on purpose ({} of {})",
+                word, mistakes, LIVES
+            );
+        } else {
+            *score += 1;
+        }
+
+        // Check that the page counted the answer the way we meant it
+        let after = wait_for_status_change(tab, status)?;
+        if let Some(before) = status {
+            if let Some(diagnosis) = diagnose(before, after, !mistake) {
+                warn!("⚠️ Answer for \"{}\" (seen: {}): {}", word, seen, diagnosis);
+            }
+        }
+        if let Some(after) = after {
+            // The page keeps the score, also when an answer went wrong
+            *score = after.score;
+            if after.lives == 0 {
+                info!("🏁 Out of lives at a score of {}", score);
+                break;
             }
+        }
+        status = after.or(status);
 
-            // Move the word instead of cloning
-            last_word = word;
+        if mistakes >= LIVES {
+            info!("🏁 Ended the game at a score of {}", score);
+            break;
         }
     }
 
-    Ok(())
+    Ok(Step::Stop)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::page::ScriptedPage;
+    use scraper::{Html, Selector};
+
+    #[test]
+    fn makes_mistakes_from_the_target_score() {
+        assert!(!should_make_mistake(10, None));
+        assert!(!should_make_mistake(9, Some(10)));
+        assert!(should_make_mistake(10, Some(10)));
+        assert!(should_make_mistake(11, Some(10)));
+    }
+
+    #[test]
+    fn counts_words_over_runs() {
+        let mut corpus = WordCorpus::parse("apple\t2\nbanana\t1\n\ncherry\n").unwrap();
+        assert_eq!(corpus.len(), 3);
+
+        let run: HashSet<String> = ["banana", "date"].iter().map(|w| w.to_string()).collect();
+        assert_eq!(corpus.record_run(&run), 1);
+
+        assert_eq!(
+            corpus.to_text(),
+            "apple\t2\nbanana\t2\ncherry\t1\ndate\t1\n"
+        );
+        assert_eq!(WordCorpus::parse(&corpus.to_text()).unwrap(), corpus);
+    }
+
+    #[test]
+    fn rejects_invalid_counts() {
+        assert!(WordCorpus::parse("apple\tmany\n").is_err());
+    }
+
+    #[test]
+    fn parses_lives_and_score() {
+        assert_eq!(
+            parse_status("Lives | 3\nScore | 12\nmaple\nSEEN\nNEW"),
+            Some(Status {
+                lives: 3,
+                score: 12
+            })
+        );
+        assert_eq!(parse_status("Verbal Memory Test\nStart"), None);
+    }
+
+    #[test]
+    fn diagnoses_unexpected_scores() {
+        let before = Status { lives: 3, score: 5 };
+        let scored = Status { lives: 3, score: 6 };
+        let lost_life = Status { lives: 2, score: 5 };
+
+        assert_eq!(diagnose(before, Some(scored), true), None);
+        assert_eq!(diagnose(before, Some(lost_life), false), None);
+
+        let missed = diagnose(before, Some(before), true).unwrap();
+        assert!(missed.contains("missed click"));
+        let wrong = diagnose(before, Some(lost_life), true).unwrap();
+        assert!(wrong.contains("word probably changed"));
+        let counted = diagnose(before, Some(scored), false).unwrap();
+        assert!(counted.contains("deliberate mistake"));
+        assert!(diagnose(before, None, true).is_some());
+    }
+
+    /// Read the observation from a snapshot of the game, like the word watcher does in the page
+    fn observe(html: &str, changes: u64) -> Observation {
+        let document = scraper::Html::parse_document(html);
+        let selector = scraper::Selector::parse(".word").unwrap();
+        Observation {
+            changes,
+            word: document
+                .select(&selector)
+                .next()
+                .map(|word| word.text().collect())
+                .unwrap_or_default(),
+        }
+    }
+
+    #[test]
+    fn answers_a_word_repeated_right_after_itself() {
+        let first = include_str!("../../tests/fixtures/verbal/first_word.html");
+        let repeated = include_str!("../../tests/fixtures/verbal/repeated_word.html");
+        let mut solver = VerbalSolver::default();
+        let now = Instant::now();
+
+        assert_eq!(solver.answer(&observe(first, 0), now), Some(Answer::New));
+        // Nothing changed yet, the word is still waiting for the answer to land
+        assert_eq!(solver.answer(&observe(first, 0), now), None);
+
+        // The score went up and the same word is shown again, this time it was seen
+        let observation = observe(repeated, 1);
+        assert_eq!(observation.word, observe(first, 0).word);
+        let text: String = scraper::Html::parse_document(repeated)
+            .root_element()
+            .text()
+            .collect();
+        assert_eq!(parse_status(&text).map(|status| status.score), Some(1));
+        assert_eq!(solver.answer(&observation, now), Some(Answer::Seen));
+        assert_eq!(solver.answer(&observe(repeated, 1), now), None);
+    }
+
+    #[test]
+    fn waits_for_the_word_to_appear() {
+        let mut solver = VerbalSolver::default();
+        let now = Instant::now();
+        let empty = Observation {
+            changes: 0,
+            word: String::new(),
+        };
+        assert_eq!(solver.answer(&empty, now), None);
+        assert!(solver.seen_words.is_empty());
+    }
+
+    #[test]
+    fn answers_again_when_the_game_does_not_move_on() {
+        let mut solver = VerbalSolver::default();
+        let start = Instant::now();
+        let observation = Observation {
+            changes: 4,
+            word: "harbor".to_string(),
+        };
+
+        assert_eq!(solver.answer(&observation, start), Some(Answer::New));
+        assert_eq!(
+            solver
+                .retry(&observation, start + Duration::from_millis(500))
+                .unwrap(),
+            None
+        );
+
+        // The click got lost, so the same answer is given again, once per timeout
+        let lost = start + ANSWER_TIMEOUT;
+        assert_eq!(solver.retry(&observation, lost).unwrap(), Some(Answer::New));
+        assert_eq!(solver.retry(&observation, lost).unwrap(), None);
+        let lost_again = lost + ANSWER_TIMEOUT;
+        assert_eq!(
+            solver.retry(&observation, lost_again).unwrap(),
+            Some(Answer::New)
+        );
+        assert!(solver
+            .retry(&observation, lost_again + ANSWER_TIMEOUT)
+            .is_err());
+
+        // Once the game moves on, the next word is answered and the retries start over
+        let next = Observation {
+            changes: 5,
+            word: "harbor".to_string(),
+        };
+        let later = lost_again + ANSWER_TIMEOUT;
+        assert_eq!(solver.retry(&next, later).unwrap(), None);
+        assert_eq!(solver.answer(&next, later), Some(Answer::Seen));
+        assert_eq!(
+            solver.retry(&next, later + ANSWER_TIMEOUT).unwrap(),
+            Some(Answer::Seen)
+        );
+    }
+
+    fn verbal_screen(word: &str, lives: u32, score: u32) -> String {
+        format!(
+            r#"<body><div>Lives | {} Score | {}</div><div class="word">{}</div>
+            <button class="css-de05nr e19owgy710" data-box="500 400 560 440">SEEN</button>
+            <button class="css-de05nr e19owgy710" data-box="600 400 660 440">NEW</button></body>"#,
+            lives, score, word
+        )
+    }
+
+    /// Answer the word watcher with the index of the snapshot as the change count
+    fn answer_watcher(script: &str, html: &str, index: usize) -> Option<serde_json::Value> {
+        if script == WORD_WATCHER_SCRIPT {
+            return Some(serde_json::Value::Null);
+        }
+        if script != "window.__wordWatcher.read()" {
+            return None;
+        }
+        let document = Html::parse_document(html);
+        let word = document
+            .select(&Selector::parse(".word").unwrap())
+            .next()
+            .map(|word| word.text().collect::<String>())
+            .unwrap_or_default();
+        Some(serde_json::Value::String(
+            serde_json::json!({ "changes": index, "word": word }).to_string(),
+        ))
+    }
+
+    #[test]
+    fn fails_without_the_answer_buttons() {
+        let page = ScriptedPage::new()
+            .then(
+                r#"<body><div>Lives | 3 Score | 0</div><div class="word">bishop</div>
+                <button class="css-de05nr e19owgy710" data-box="500 400 560 440">SEEN</button></body>"#,
+            )
+            .on_evaluate(verbal_state_machine().answer_detection())
+            .on_evaluate(answer_watcher);
+
+        let Err(error) = verbal_memory_actions(&page, &page, VerbalLimits::default()) else {
+            panic!("Played without the NEW button");
+        };
+        assert_eq!(error.to_string(), "NEW button not found");
+        assert!(page.input().clicks().is_empty());
+    }
+
+    #[test]
+    fn answers_every_word_including_repeats() {
+        let page = ScriptedPage::new()
+            .then(r#"<body><button class="css-de05nr e19owgy710">Start</button></body>"#)
+            .then(verbal_screen("bishop", 3, 0))
+            .then(verbal_screen("castle", 3, 1))
+            .then(verbal_screen("bishop", 3, 2))
+            .then(verbal_screen("bishop", 3, 3))
+            .then(r#"<body><h1>4 words</h1><button class="css-qm6rs9 e19owgy710">Save score</button></body>"#)
+            .on_evaluate(verbal_state_machine().answer_detection())
+            .on_evaluate(answer_watcher);
+
+        let run = verbal_memory_actions(&page, &page, VerbalLimits::default()).unwrap();
+
+        let seen = Point { x: 530, y: 420 };
+        let new = Point { x: 630, y: 420 };
+        assert_eq!(
+            page.input().clicks(),
+            vec![new.clone(), new, seen.clone(), seen]
+        );
+        assert_eq!(run.score, 4);
+        assert_eq!(run.words.len(), 2);
+    }
 }
@@ -1,23 +1,39 @@
+use crate::consent::accept_consent;
+use crate::utils::wait_until_ready;
+
+use super::manual::wait_for_result;
 use super::TestRunner;
 use anyhow::Result;
 use std::time::Duration;
+use tracing::{info, instrument};
 
+#[instrument(name = "visual_memory")]
 pub async fn run() -> Result<()> {
-    println!("👁️ Starting Visual Memory Test");
+    info!("👁️ Starting Visual Memory Test");
+
+    let runner = TestRunner::launch().await?;
+    runner.guard("visual_memory", play(&runner)).await?;
+
+    info!("✅ Visual Memory Test completed");
 
-    let runner = TestRunner::new()?;
-    let tab = runner.get_tab()?;
+    Ok(())
+}
 
-    tab.navigate_to("https://humanbenchmark.com/tests/visual-memory")?;
-    tab.wait_for_element("div[data-testid='start-button']")?;
+/// Open the test in a new tab of the browser and wait while it is played manually
+async fn play(runner: &TestRunner) -> Result<()> {
+    let tab = runner
+        .open("https://humanbenchmark.com/tests/visual-memory")
+        .await?;
 
-    println!("🧠 Please complete the visual memory test manually");
-    println!("   Memorize the pattern and click the squares that lit up");
+    // Handle cookies
+    accept_consent(&tab).await?;
 
-    // Wait for user to complete
-    std::thread::sleep(Duration::from_secs(180));
+    // Wait for the ads to load in
+    wait_until_ready(&tab, "div[data-testid='start-button']").await?;
 
-    println!("✅ Visual Memory Test completed");
+    info!("🧠 Please complete the visual memory test manually");
+    info!("   Memorize the pattern and click the squares that lit up");
 
-    Ok(())
+    // Wait for user to complete, at most 180 seconds
+    wait_for_result(&tab, "visual_memory", Duration::from_secs(180)).await
 }
@@ -0,0 +1,424 @@
+use super::options::options;
+use super::page::{Page, PageElement};
+use anyhow::{Context, Result};
+use headless_chrome::protocol::cdp::Network::{Cookie, CookieParam};
+use headless_chrome::{Browser, Tab};
+use serde::Deserialize;
+use std::fs;
+use std::path::Path;
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+use tracing::{debug, info};
+
+/// Account file read when `--account` is not given, skipped when it does not exist
+pub const DEFAULT_ACCOUNT_FILE: &str = "account.json";
+
+const LOGIN_URL: &str = "https://humanbenchmark.com/login";
+
+/// Cookies of the login, once a browser logged in, shared with the browsers started later
+static LOGIN_COOKIES: Mutex<Option<Vec<CookieParam>>> = Mutex::new(None);
+
+/// The Human Benchmark account the scores are saved to
+///
+/// Read from the account file, a JSON object with the same fields, and from the
+/// `HUMAN_BENCHMARK_EMAIL`, `HUMAN_BENCHMARK_PASSWORD` and `HUMAN_BENCHMARK_SESSION_COOKIE`
+/// environment variables, which take precedence.
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Account {
+    pub email: Option<String>,
+    pub password: Option<String>,
+    /// Session cookie of a browser that is logged in, as `name=value`
+    pub session_cookie: Option<String>,
+}
+
+impl Account {
+    /// Read the account from the file and the environment
+    ///
+    /// ## Returns
+    /// Returns `None` when neither has an account
+    ///
+    /// ## Errors
+    /// Returns an error when the file cannot be read, or the account is incomplete
+    pub fn load(file: Option<&Path>) -> Result<Option<Self>> {
+        let json =
+            match file {
+                Some(path) => Some(fs::read_to_string(path).with_context(|| {
+                    format!("Could not read the account from {}", path.display())
+                })?),
+                None => fs::read_to_string(DEFAULT_ACCOUNT_FILE).ok(),
+            };
+        Self::resolve(json.as_deref(), |name| std::env::var(name).ok())
+    }
+
+    fn resolve(json: Option<&str>, env: impl Fn(&str) -> Option<String>) -> Result<Option<Self>> {
+        let mut account: Account = match json {
+            Some(json) => serde_json::from_str(json).context("Invalid account file")?,
+            None => Account::default(),
+        };
+        let overrides = [
+            (&mut account.email, "HUMAN_BENCHMARK_EMAIL"),
+            (&mut account.password, "HUMAN_BENCHMARK_PASSWORD"),
+            (
+                &mut account.session_cookie,
+                "HUMAN_BENCHMARK_SESSION_COOKIE",
+            ),
+        ];
+        for (field, name) in overrides {
+            if let Some(value) = env(name).filter(|value| !value.is_empty()) {
+                *field = Some(value);
+            }
+        }
+
+        if account.email.is_some() != account.password.is_some() {
+            anyhow::bail!("The account needs both an email and a password");
+        }
+        if account == Account::default() {
+            return Ok(None);
+        }
+        account.cookie()?;
+        Ok(Some(account))
+    }
+
+    /// The session cookie to preseed, so the browser starts logged in
+    ///
+    /// ## Errors
+    /// Returns an error when the session cookie is not `name=value`
+    pub fn cookie(&self) -> Result<Option<CookieParam>> {
+        let Some(session_cookie) = &self.session_cookie else {
+            return Ok(None);
+        };
+        let Some((name, value)) = session_cookie
+            .split_once('=')
+            .filter(|(name, _)| !name.trim().is_empty())
+        else {
+            anyhow::bail!("The session cookie needs to be name=value");
+        };
+        let cookie = serde_json::from_value(serde_json::json!({
+            "name": name.trim(),
+            "value": value.trim(),
+            "domain": "humanbenchmark.com",
+            "path": "/",
+            "secure": true,
+        }))?;
+        Ok(Some(cookie))
+    }
+}
+
+/// The cookies of a login to the site, to preseed in every tab
+///
+/// The first browser logs in with the email and password in a new tab, unless its profile
+/// already is, and the cookies of the site are kept. The browsers started after it, like
+/// the other `--parallel` sessions, get the same cookies without logging in again.
+///
+/// ## Returns
+/// Returns no cookies when the account only has a session cookie, which is preseeded as is
+///
+/// ## Errors
+/// Returns an error when the login form does not go away, like with wrong credentials
+pub fn login_cookies(browser: &Browser, account: &Account) -> Result<Vec<CookieParam>> {
+    let (Some(email), Some(password)) = (&account.email, &account.password) else {
+        return Ok(Vec::new());
+    };
+
+    // Sessions starting at the same time wait for the first login instead of logging in too
+    let mut login = LOGIN_COOKIES.lock().unwrap();
+    if let Some(cookies) = login.as_ref() {
+        debug!("Reusing the login of an earlier browser");
+        return Ok(cookies.clone());
+    }
+
+    let tab = browser.new_tab()?;
+    tab.navigate_to(LOGIN_URL)?.wait_until_navigated()?;
+    let outcome = submit_login(&tab, email, password).and_then(|()| {
+        tab.get_cookies()?
+            .iter()
+            .map(cookie_param)
+            .collect::<Result<Vec<_>>>()
+    });
+    let _ = tab.close(false);
+
+    let cookies = outcome?;
+    *login = Some(cookies.clone());
+    Ok(cookies)
+}
+
+/// The cookie as a cookie to set in another browser
+fn cookie_param(cookie: &Cookie) -> Result<CookieParam> {
+    let mut param = serde_json::json!({
+        "name": cookie.name,
+        "value": cookie.value,
+        "domain": cookie.domain,
+        "path": cookie.path,
+        "secure": cookie.secure,
+        "httpOnly": cookie.http_only,
+    });
+    if !cookie.session {
+        param["expires"] = serde_json::json!(cookie.expires);
+    }
+    Ok(serde_json::from_value(param)?)
+}
+
+fn submit_login(tab: &Tab, email: &str, password: &str) -> Result<()> {
+    if !has_login_form(tab)? {
+        debug!("The profile is already logged in");
+        return Ok(());
+    }
+
+    let submitted = tab
+        .evaluate(&login_script(email, password)?, false)?
+        .value
+        .and_then(|value| value.as_bool())
+        .unwrap_or(false);
+    if !submitted {
+        anyhow::bail!("The login page has no form to fill in");
+    }
+
+    let start_time = Instant::now();
+    while has_login_form(tab)? {
+        if start_time.elapsed() > options().ready_timeout {
+            anyhow::bail!("Could not log in as {}, check the credentials", email);
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+    info!("🔑 Logged in as {}", email);
+    Ok(())
+}
+
+fn has_login_form(tab: &Tab) -> Result<bool> {
+    Ok(tab
+        .evaluate("!!document.querySelector('input[type=password]')", false)?
+        .value
+        .and_then(|value| value.as_bool())
+        .unwrap_or(false))
+}
+
+/// Script that fills in the login form and submits it, returning whether it found the form
+///
+/// The values are set through the setter of the input prototype and announced with an input
+/// event, so the form of the site sees them like typed text.
+fn login_script(email: &str, password: &str) -> Result<String> {
+    Ok(format!(
+        r#"(() => {{
+    const user = document.querySelector('input[type=email], input[name=username], input[name=email]');
+    const password = document.querySelector('input[type=password]');
+    const form = password && password.closest('form');
+    if (!user || !form) return false;
+    const setValue = Object.getOwnPropertyDescriptor(HTMLInputElement.prototype, 'value').set;
+    for (const [input, value] of [[user, {}], [password, {}]]) {{
+        setValue.call(input, value);
+        input.dispatchEvent(new Event('input', {{ bubbles: true }}));
+    }}
+    form.requestSubmit();
+    return true;
+}})()"#,
+        serde_json::to_string(email)?,
+        serde_json::to_string(password)?
+    ))
+}
+
+/// Press "Save score" on the result screen, and wait until the site confirms the save
+///
+/// The save is confirmed when the button is gone and the result screen says the score was
+/// saved. A button that only goes away is no confirmation, and when the site asks to log in
+/// instead, the score was not saved.
+///
+/// ## Errors
+/// Returns an error when there is no button, the site asks to log in or does not confirm
+/// the save within the timeout
+pub fn save_score(page: &dyn Page, timeout: Duration) -> Result<()> {
+    save_score_button(page)?
+        .ok_or_else(|| anyhow::anyhow!("The result screen has no Save score button"))?
+        .click()?;
+
+    let start_time = Instant::now();
+    loop {
+        if page.find_element("input[type=password]").is_ok() {
+            anyhow::bail!("The site asked to log in, the score was not saved");
+        }
+        if save_score_button(page)?.is_none() && shows_saved(page)? {
+            return Ok(());
+        }
+        if start_time.elapsed() > timeout {
+            anyhow::bail!("The site did not confirm the save of the score");
+        }
+        thread::sleep(Duration::from_millis(100));
+    }
+}
+
+/// The paragraphs of the result panel, where the site confirms the save
+const RESULT_PANEL_TEXT_SELECTOR: &str = ".css-1qvtbrk.e19owgy78 .css-1c3ylhl p";
+
+/// Whether the result panel says that the score was saved, with "Score saved!"
+fn shows_saved(page: &dyn Page) -> Result<bool> {
+    for paragraph in page.find_elements(RESULT_PANEL_TEXT_SELECTOR)? {
+        if paragraph
+            .get_inner_text()?
+            .trim()
+            .eq_ignore_ascii_case("score saved!")
+        {
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
+
+/// The "Save score" button of the result screen, when it is shown
+fn save_score_button(page: &dyn Page) -> Result<Option<Box<dyn PageElement + '_>>> {
+    for button in page.find_elements("button")? {
+        if button
+            .get_inner_text()?
+            .trim()
+            .eq_ignore_ascii_case("save score")
+        {
+            return Ok(Some(button));
+        }
+    }
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::page::ScriptedPage;
+    use std::collections::HashMap;
+
+    const RESULT: &str = include_str!("../../tests/fixtures/account/result.html");
+
+    fn env(vars: &[(&str, &str)]) -> impl Fn(&str) -> Option<String> {
+        let vars: HashMap<String, String> = vars
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+        move |name| vars.get(name).cloned()
+    }
+
+    #[test]
+    fn reads_the_account_from_the_file_and_the_environment() {
+        let account = Account::resolve(
+            Some(r#"{"email": "chimp@example.com", "password": "from the file"}"#),
+            env(&[("HUMAN_BENCHMARK_PASSWORD", "from the environment")]),
+        )
+        .unwrap()
+        .unwrap();
+        assert_eq!(account.email.as_deref(), Some("chimp@example.com"));
+        assert_eq!(account.password.as_deref(), Some("from the environment"));
+        assert_eq!(account.cookie().unwrap(), None);
+
+        assert_eq!(Account::resolve(None, env(&[])).unwrap(), None);
+        assert!(Account::resolve(Some(r#"{"email": "chimp@example.com"}"#), env(&[])).is_err());
+        assert!(Account::resolve(Some(r#"{"user": "chimp"}"#), env(&[])).is_err());
+    }
+
+    #[test]
+    fn preseeds_the_session_cookie() {
+        let account = Account::resolve(
+            None,
+            env(&[("HUMAN_BENCHMARK_SESSION_COOKIE", "session = abc=123")]),
+        )
+        .unwrap()
+        .unwrap();
+        let cookie = account.cookie().unwrap().unwrap();
+        assert_eq!(cookie.name, "session");
+        assert_eq!(cookie.value, "abc=123");
+        assert_eq!(cookie.domain.as_deref(), Some("humanbenchmark.com"));
+
+        let error =
+            Account::resolve(None, env(&[("HUMAN_BENCHMARK_SESSION_COOKIE", "abc")])).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "The session cookie needs to be name=value"
+        );
+    }
+
+    #[test]
+    fn escapes_the_credentials_in_the_login_script() {
+        let script = login_script("chimp@example.com", "it's \"secret\"").unwrap();
+        assert!(script.contains(r#"[[user, "chimp@example.com"], [password, "it's \"secret\""]]"#));
+    }
+
+    #[test]
+    fn saves_the_score_when_the_site_confirms() {
+        // The save endpoint answers by replacing the button with a confirmation
+        let page = ScriptedPage::new()
+            .then(RESULT)
+            .then(include_str!("../../tests/fixtures/account/saved.html"));
+
+        save_score(&page, Duration::from_secs(1)).unwrap();
+        assert_eq!(page.index(), 1);
+    }
+
+    #[test]
+    fn shares_the_cookies_of_the_login() {
+        let cookie: Cookie = serde_json::from_value(serde_json::json!({
+            "name": "hb_session",
+            "value": "abc",
+            "domain": "humanbenchmark.com",
+            "path": "/",
+            "expires": 1_800_000_000.0,
+            "size": 13,
+            "httpOnly": true,
+            "secure": true,
+            "session": false,
+            "priority": "Medium",
+            "sourceScheme": "Secure",
+            "sourcePort": 443,
+        }))
+        .unwrap();
+
+        let param = cookie_param(&cookie).unwrap();
+        assert_eq!(param.name, "hb_session");
+        assert_eq!(param.domain.as_deref(), Some("humanbenchmark.com"));
+        assert_eq!(param.http_only, Some(true));
+        assert_eq!(param.expires, Some(1_800_000_000.0));
+
+        let session = Cookie {
+            session: true,
+            ..cookie
+        };
+        assert_eq!(cookie_param(&session).unwrap().expires, None);
+    }
+
+    #[test]
+    fn reports_a_save_that_needs_a_login() {
+        let page = ScriptedPage::new()
+            .then(RESULT)
+            .then(include_str!("../../tests/fixtures/account/login.html"));
+
+        let error = save_score(&page, Duration::from_secs(1)).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "The site asked to log in, the score was not saved"
+        );
+    }
+
+    #[test]
+    fn reports_a_save_that_is_not_confirmed() {
+        let page = ScriptedPage::new().then(RESULT);
+        let error = save_score(&page, Duration::from_millis(200)).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "The site did not confirm the save of the score"
+        );
+
+        // The button going away is not enough, the site has to say it saved the score
+        let page = ScriptedPage::new().then(RESULT).then(RESULT.replace(
+            r#"<button class="css-qm6rs9 e19owgy710">Save score</button>"#,
+            "",
+        ));
+        assert!(save_score(&page, Duration::from_millis(200)).is_err());
+
+        let page = ScriptedPage::new().then("<body><h1>Chimp Test</h1></body>");
+        assert!(save_score(&page, Duration::ZERO).is_err());
+    }
+
+    #[test]
+    fn only_the_result_panel_confirms_the_save() {
+        // "Saved" elsewhere on the page, like a banner about settings, is no confirmation
+        let page = ScriptedPage::new().then(RESULT).then(include_str!(
+            "../../tests/fixtures/account/saved_elsewhere.html"
+        ));
+        assert!(save_score(&page, Duration::from_millis(200)).is_err());
+    }
+}
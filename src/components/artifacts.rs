@@ -0,0 +1,264 @@
+use super::logging::recent_messages;
+use super::options::options;
+use super::utils::run_blocking;
+use anyhow::{Context, Result};
+use headless_chrome::protocol::cdp::Page::CaptureScreenshotFormatOption;
+use headless_chrome::Tab;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// What could be read from the page of a benchmark that failed
+pub struct PageSnapshot {
+    pub url: String,
+    /// PNG image of the visible part of the page
+    pub screenshot: Result<Vec<u8>>,
+    /// HTML of the whole page
+    pub html: Result<String>,
+}
+
+impl PageSnapshot {
+    /// Read the page, a failed read is kept so the other parts can still be saved
+    pub fn read(tab: &Tab) -> Self {
+        Self {
+            url: tab.get_url(),
+            screenshot: tab.capture_screenshot(
+                CaptureScreenshotFormatOption::Png,
+                None,
+                None,
+                true,
+            ),
+            html: tab.get_content(),
+        }
+    }
+}
+
+/// Saves the artifacts of a failed or cancelled benchmark, before its browser closes
+///
+/// Every failure gets its own directory in the artifacts directory, named after the time
+/// and the benchmark, with:
+/// * `screenshot.png` - the visible part of the page
+/// * `page.html` - the HTML of the whole page
+/// * `trace.jsonl` - the last messages of the program down to the debug level, with the
+///   actions and state transitions of the solver. For a `--parallel` session, only the
+///   messages of that session.
+/// * `summary.txt` - the benchmark, the url of the page and the error chain
+///
+/// ## Returns
+/// Returns the directory the artifacts were saved to
+pub async fn capture(
+    tab: &Arc<Tab>,
+    benchmark: &str,
+    session: Option<usize>,
+    error: &anyhow::Error,
+) -> Result<PathBuf> {
+    let page = tab.clone();
+    let snapshot = run_blocking(move || Ok(PageSnapshot::read(&page))).await?;
+    save(
+        &options().artifacts_dir,
+        benchmark,
+        SystemTime::now(),
+        &snapshot,
+        error,
+        &recent_messages(session),
+    )
+}
+
+/// Write the artifacts to a new directory in the root directory
+fn save(
+    root: &Path,
+    benchmark: &str,
+    time: SystemTime,
+    snapshot: &PageSnapshot,
+    error: &anyhow::Error,
+    trace: &[String],
+) -> Result<PathBuf> {
+    let (date, clock) = utc_date_time(time);
+    let dir = create_new_dir(
+        root,
+        &format!("{}_{}_{}", date, clock.replace(':', "-"), benchmark),
+    )?;
+
+    if let Ok(png) = &snapshot.screenshot {
+        fs::write(dir.join("screenshot.png"), png)?;
+    }
+    if let Ok(html) = &snapshot.html {
+        fs::write(dir.join("page.html"), html)?;
+    }
+    let lines: String = trace.iter().map(|line| format!("{}\n", line)).collect();
+    fs::write(dir.join("trace.jsonl"), lines)?;
+    fs::write(
+        dir.join("summary.txt"),
+        summary(benchmark, time, snapshot, error, trace.len()),
+    )?;
+
+    Ok(dir)
+}
+
+/// Describe the failure, with every cause of the error on its own line
+fn summary(
+    benchmark: &str,
+    time: SystemTime,
+    snapshot: &PageSnapshot,
+    error: &anyhow::Error,
+    trace_len: usize,
+) -> String {
+    let (date, clock) = utc_date_time(time);
+    let saved = |file: &str, read: Result<(), &anyhow::Error>| match read {
+        Ok(()) => file.to_string(),
+        Err(e) => format!("not saved, {:#}", e),
+    };
+
+    let mut summary = format!(
+        "Benchmark: {}\nTime: {} {} UTC\nUrl: {}\nScreenshot: {}\nPage: {}\nTrace: trace.jsonl ({} messages)\n\nError:\n",
+        benchmark,
+        date,
+        clock,
+        snapshot.url,
+        saved("screenshot.png", snapshot.screenshot.as_ref().map(|_| ())),
+        saved("page.html", snapshot.html.as_ref().map(|_| ())),
+        trace_len
+    );
+    for (index, cause) in error.chain().enumerate() {
+        summary.push_str(&format!("  {}: {}\n", index, cause));
+    }
+    summary
+}
+
+/// Create a directory with the name in the root, adding a number when it already exists
+fn create_new_dir(root: &Path, name: &str) -> Result<PathBuf> {
+    fs::create_dir_all(root).with_context(|| {
+        format!(
+            "Could not create the artifacts directory {}",
+            root.display()
+        )
+    })?;
+
+    for attempt in 1.. {
+        let dir = match attempt {
+            1 => root.join(name),
+            _ => root.join(format!("{}_{}", name, attempt)),
+        };
+        match fs::create_dir(&dir) {
+            Ok(()) => return Ok(dir),
+            Err(e) if e.kind() == ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e).with_context(|| format!("Could not create {}", dir.display())),
+        }
+    }
+    unreachable!("every attempt number is tried")
+}
+
+/// The UTC date as `yyyy-mm-dd` and time of day as `hh:mm:ss` of the time
+pub fn utc_date_time(time: SystemTime) -> (String, String) {
+    let seconds = time
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |since| since.as_secs());
+    let (days, second_of_day) = ((seconds / 86400) as i64, seconds % 86400);
+
+    // Convert the days since 1970-01-01 to a civil date, with years starting in March
+    let shifted = days + 719_468;
+    let era = shifted.div_euclid(146_097);
+    let day_of_era = shifted - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_from_march = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_from_march + 2) / 5 + 1;
+    let month = if month_from_march < 10 {
+        month_from_march + 3
+    } else {
+        month_from_march - 9
+    };
+    let year = year_of_era + era * 400 + i64::from(month <= 2);
+
+    (
+        format!("{:04}-{:02}-{:02}", year, month, day),
+        format!(
+            "{:02}:{:02}:{:02}",
+            second_of_day / 3600,
+            second_of_day / 60 % 60,
+            second_of_day % 60
+        ),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn at(seconds: u64) -> SystemTime {
+        UNIX_EPOCH + Duration::from_secs(seconds)
+    }
+
+    #[test]
+    fn formats_the_utc_time() {
+        assert_eq!(
+            utc_date_time(at(0)),
+            ("1970-01-01".to_string(), "00:00:00".to_string())
+        );
+        assert_eq!(
+            utc_date_time(at(951_782_400)),
+            ("2000-02-29".to_string(), "00:00:00".to_string())
+        );
+        assert_eq!(
+            utc_date_time(at(1_792_332_187)),
+            ("2026-10-18".to_string(), "14:03:07".to_string())
+        );
+    }
+
+    #[test]
+    fn saves_the_page_trace_and_error_chain() {
+        let root = std::env::temp_dir().join(format!("artifacts_test_{}", std::process::id()));
+        let snapshot = PageSnapshot {
+            url: "https://humanbenchmark.com/tests/chimp".to_string(),
+            screenshot: Err(anyhow::anyhow!("the browser is gone")),
+            html: Ok("<body>NUMBERS 4</body>".to_string()),
+        };
+        let error =
+            anyhow::anyhow!("Element not found: .css-19b5rdt").context("Could not read the grid");
+        let trace = vec![r#"{"level":"DEBUG"}"#.to_string()];
+
+        let first = save(
+            &root,
+            "chimp_test",
+            at(1_792_332_187),
+            &snapshot,
+            &error,
+            &trace,
+        )
+        .unwrap();
+        let second = save(
+            &root,
+            "chimp_test",
+            at(1_792_332_187),
+            &snapshot,
+            &error,
+            &trace,
+        )
+        .unwrap();
+
+        assert!(first.ends_with("2026-10-18_14-03-07_chimp_test"));
+        assert!(second.ends_with("2026-10-18_14-03-07_chimp_test_2"));
+        assert!(!first.join("screenshot.png").exists());
+        assert_eq!(
+            fs::read_to_string(first.join("page.html")).unwrap(),
+            "<body>NUMBERS 4</body>"
+        );
+        assert_eq!(
+            fs::read_to_string(first.join("trace.jsonl")).unwrap(),
+            "{\"level\":\"DEBUG\"}\n"
+        );
+
+        let summary = fs::read_to_string(first.join("summary.txt")).unwrap();
+        assert!(summary.contains("Benchmark: chimp_test\nTime: 2026-10-18 14:03:07 UTC\n"));
+        assert!(summary.contains("Screenshot: not saved, the browser is gone\n"));
+        assert!(summary.ends_with(
+            "Error:\n  0: Could not read the grid\n  1: Element not found: .css-19b5rdt\n"
+        ));
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
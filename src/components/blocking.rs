@@ -0,0 +1,284 @@
+use super::options::Options;
+use anyhow::{Context, Result};
+use headless_chrome::browser::tab::RequestPausedDecision;
+use headless_chrome::protocol::cdp::Fetch::{
+    events::RequestPausedEvent, FailRequest, RequestPattern, RequestStage,
+};
+use headless_chrome::protocol::cdp::Network::{CookieParam, ErrorReason, SetCookies};
+use headless_chrome::Tab;
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+use tracing::debug;
+
+/// Domains of ad networks and consent-management platforms the site loads, with their
+/// subdomains
+///
+/// Blocking the ads keeps the layout from shifting while a benchmark plays, and blocking the
+/// consent platforms keeps their banner from covering the page.
+pub const DEFAULT_BLOCKED_DOMAINS: &[&str] = &[
+    // Ads
+    "doubleclick.net",
+    "googlesyndication.com",
+    "googletagservices.com",
+    "googleadservices.com",
+    "adservice.google.com",
+    "amazon-adsystem.com",
+    "adnxs.com",
+    "pubmatic.com",
+    "rubiconproject.com",
+    "openx.net",
+    "casalemedia.com",
+    "indexww.com",
+    "criteo.com",
+    "criteo.net",
+    "taboola.com",
+    "outbrain.com",
+    "moatads.com",
+    "adsafeprotected.com",
+    "playwire.com",
+    "intergient.com",
+    "33across.com",
+    "sharethrough.com",
+    "smartadserver.com",
+    "lijit.com",
+    "sonobi.com",
+    "teads.tv",
+    "id5-sync.com",
+    "quantserve.com",
+    "scorecardresearch.com",
+    // Consent banners
+    "fundingchoicesmessages.google.com",
+    "consensu.org",
+    "quantcast.com",
+    "cookielaw.org",
+    "onetrust.com",
+    "cookiebot.com",
+    "privacy-mgmt.com",
+    "trustarc.com",
+    "didomi.io",
+    "usercentrics.eu",
+];
+
+/// The url patterns to block, in the wildcard syntax of the DevTools Protocol
+///
+/// These are the default domains unless `--no-blocking` is set, and the patterns added with
+/// `--block`.
+pub fn blocked_url_patterns(options: &Options) -> Vec<String> {
+    let defaults = DEFAULT_BLOCKED_DOMAINS
+        .iter()
+        .filter(|_| options.block_requests)
+        .flat_map(|domain| [format!("*://{}/*", domain), format!("*://*.{}/*", domain)]);
+    defaults
+        .chain(options.blocked_urls.iter().cloned())
+        .collect()
+}
+
+/// Whether the url matches the pattern, where `*` is any text, `?` is one character and `\`
+/// escapes the next character, like the url patterns of the DevTools Protocol
+pub fn matches_url_pattern(pattern: &str, url: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let url: Vec<char> = url.chars().collect();
+
+    // Match from the left, going back to the last `*` when a character does not match
+    let (mut p, mut u) = (0, 0);
+    let mut last_star: Option<(usize, usize)> = None;
+    while u < url.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                last_star = Some((p, u));
+                p += 1;
+            }
+            Some('?') => {
+                p += 1;
+                u += 1;
+            }
+            Some('\\') if pattern.get(p + 1) == Some(&url[u]) => {
+                p += 2;
+                u += 1;
+            }
+            Some(c) if *c != '\\' && *c == url[u] => {
+                p += 1;
+                u += 1;
+            }
+            _ => match last_star {
+                Some((star, matched)) => {
+                    last_star = Some((star, matched + 1));
+                    p = star + 1;
+                    u = matched + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+/// Fail the requests of the tab that match one of the patterns, before they are sent
+///
+/// Only the matching requests are paused with `Fetch.enable`, the others load as usual.
+pub fn block_requests(tab: &Arc<Tab>, patterns: Vec<String>) -> Result<()> {
+    if patterns.is_empty() {
+        return Ok(());
+    }
+
+    let fetch_patterns: Vec<RequestPattern> = patterns
+        .iter()
+        .map(|pattern| RequestPattern {
+            url_pattern: Some(pattern.clone()),
+            resource_Type: None,
+            request_stage: Some(RequestStage::Request),
+        })
+        .collect();
+
+    tab.enable_request_interception(Arc::new(
+        move |_transport, _session_id, event: RequestPausedEvent| {
+            let params = event.params;
+            let url = &params.request.url;
+            if !patterns
+                .iter()
+                .any(|pattern| matches_url_pattern(pattern, url))
+            {
+                return RequestPausedDecision::Continue(None);
+            }
+
+            debug!("🚫 Blocked {}", url);
+            RequestPausedDecision::Fail(FailRequest {
+                request_id: params.request_id,
+                error_reason: ErrorReason::BlockedByClient,
+            })
+        },
+    ))?;
+    tab.enable_fetch(Some(&fetch_patterns), None)?;
+
+    Ok(())
+}
+
+/// Read the cookies to preseed from a JSON file, an array of cookies in the `Network.CookieParam`
+/// format of the DevTools Protocol
+///
+/// ## Errors
+/// Returns an error when the file cannot be read, or a cookie has neither a domain nor a url
+pub fn load_cookies(path: &Path) -> Result<Vec<CookieParam>> {
+    let json = fs::read_to_string(path)
+        .with_context(|| format!("Could not read the cookies from {}", path.display()))?;
+    parse_cookies(&json).with_context(|| format!("Invalid cookies in {}", path.display()))
+}
+
+fn parse_cookies(json: &str) -> Result<Vec<CookieParam>> {
+    let cookies: Vec<CookieParam> = serde_json::from_str(json)?;
+    if let Some(cookie) = cookies
+        .iter()
+        .find(|cookie| cookie.domain.is_none() && cookie.url.is_none())
+    {
+        anyhow::bail!("The cookie {} needs a domain or a url", cookie.name);
+    }
+    Ok(cookies)
+}
+
+/// Set the cookies in the browser of the tab, before it navigates, so the site finds the
+/// consent it asks for and does not show its banner
+pub fn preseed_cookies(tab: &Tab, cookies: &[CookieParam]) -> Result<()> {
+    if cookies.is_empty() {
+        return Ok(());
+    }
+    tab.call_method(SetCookies {
+        cookies: cookies.to_vec(),
+    })?;
+    debug!("Preseeded {} cookies", cookies.len());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn is_blocked(patterns: &[String], url: &str) -> bool {
+        patterns
+            .iter()
+            .any(|pattern| matches_url_pattern(pattern, url))
+    }
+
+    #[test]
+    fn matches_wildcards() {
+        assert!(matches_url_pattern(
+            "*://*.doubleclick.net/*",
+            "https://g.doubleclick.net/tag"
+        ));
+        assert!(matches_url_pattern("*.js", "https://a.b/c.js"));
+        assert!(matches_url_pattern("https://?.b/*", "https://a.b/"));
+        assert!(matches_url_pattern(r"*\*", "https://a.b/*"));
+        assert!(!matches_url_pattern(r"*\*", "https://a.b/c"));
+        assert!(!matches_url_pattern(
+            "*://*.doubleclick.net/*",
+            "https://doubleclick.net/tag"
+        ));
+        assert!(!matches_url_pattern("*.js", "https://a.b/c.json"));
+    }
+
+    #[test]
+    fn blocks_ads_and_consent_banners_but_not_the_site() {
+        let patterns = blocked_url_patterns(&Options::default());
+
+        assert!(is_blocked(
+            &patterns,
+            "https://securepubads.g.doubleclick.net/tag/js/gpt.js"
+        ));
+        assert!(is_blocked(
+            &patterns,
+            "https://fundingchoicesmessages.google.com/i/pub-123?ers=1"
+        ));
+        assert!(is_blocked(
+            &patterns,
+            "https://cdn.cookielaw.org/consent.js"
+        ));
+        assert!(!is_blocked(
+            &patterns,
+            "https://humanbenchmark.com/tests/chimp"
+        ));
+        assert!(!is_blocked(
+            &patterns,
+            "https://humanbenchmark.com/?ref=doubleclick.net"
+        ));
+    }
+
+    #[test]
+    fn blocks_only_the_added_patterns_without_the_defaults() {
+        let options = Options {
+            block_requests: false,
+            blocked_urls: vec!["*/ads/*".to_string()],
+            ..Options::default()
+        };
+        let patterns = blocked_url_patterns(&options);
+
+        assert_eq!(patterns, vec!["*/ads/*".to_string()]);
+        assert!(is_blocked(
+            &patterns,
+            "https://humanbenchmark.com/ads/banner.png"
+        ));
+        assert!(!is_blocked(&patterns, "https://g.doubleclick.net/tag"));
+    }
+
+    #[test]
+    fn reads_the_cookies_to_preseed() {
+        let cookies = parse_cookies(
+            r#"[
+                {"name": "FCCDCF", "value": "consent", "domain": ".humanbenchmark.com", "path": "/"},
+                {"name": "euconsent-v2", "value": "CP", "url": "https://humanbenchmark.com", "secure": true}
+            ]"#,
+        )
+        .unwrap();
+
+        assert_eq!(cookies.len(), 2);
+        assert_eq!(cookies[0].name, "FCCDCF");
+        assert_eq!(cookies[0].domain.as_deref(), Some(".humanbenchmark.com"));
+        assert_eq!(cookies[1].secure, Some(true));
+
+        let error = parse_cookies(r#"[{"name": "FCCDCF", "value": "consent"}]"#).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "The cookie FCCDCF needs a domain or a url"
+        );
+        assert!(parse_cookies("{}").is_err());
+    }
+}
@@ -0,0 +1,475 @@
+use super::options::options;
+use super::utils::run_blocking;
+use anyhow::Result;
+use headless_chrome::protocol::cdp::Page::{CreateIsolatedWorld, FrameTree, GetFrameTree};
+use headless_chrome::protocol::cdp::Runtime::{Evaluate, ExecutionContextId};
+use headless_chrome::Tab;
+use scraper::{ElementRef, Html, Selector};
+use serde_json::Value;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tracing::{debug, info};
+
+/// A consent-management platform, recognised by the structure of its banner
+struct Strategy {
+    name: &'static str,
+    /// Elements that are only on the page while the banner is shown
+    banner: &'static str,
+    /// The accept button in the banner, tried before the texts of its buttons
+    accept: &'static [&'static str],
+}
+
+/// The known platforms to look for, before the generic banner
+const STRATEGIES: &[Strategy] = &[
+    Strategy {
+        name: "Google Funding Choices",
+        banner: ".fc-consent-root",
+        accept: &[".fc-cta-consent"],
+    },
+    Strategy {
+        name: "OneTrust",
+        banner: "#onetrust-banner-sdk, #onetrust-consent-sdk",
+        accept: &["#onetrust-accept-btn-handler"],
+    },
+    Strategy {
+        name: "Cookiebot",
+        banner: "#CybotCookiebotDialog",
+        accept: &[
+            "#CybotCookiebotDialogBodyLevelButtonLevelOptinAllowAll",
+            "#CybotCookiebotDialogBodyButtonAccept",
+        ],
+    },
+    Strategy {
+        name: "Quantcast Choice",
+        banner: "#qc-cmp2-container, .qc-cmp2-container",
+        accept: &[".qc-cmp2-summary-buttons button[mode=primary]"],
+    },
+    Strategy {
+        name: "Sourcepoint",
+        banner: ".message-container, #sp_message_container",
+        accept: &[".sp_choice_type_11"],
+    },
+    Strategy {
+        name: "Didomi",
+        banner: "#didomi-popup, #didomi-notice",
+        accept: &["#didomi-notice-agree-button"],
+    },
+    Strategy {
+        name: "TrustArc",
+        banner: "#truste-consent-track",
+        accept: &["#truste-consent-button"],
+    },
+];
+
+/// Any element about cookies or consent, looked for when no known platform is on the page
+///
+/// Such an element can also be a settings panel of the site, so only the unmistakable
+/// [`ACCEPT_TEXTS`] are clicked in it.
+const GENERIC: Strategy = Strategy {
+    name: "generic cookie banner",
+    banner: "[id*=cookie i], [class*=cookie i], [id*=consent i], [class*=consent i], \
+             [id*=gdpr i], [class*=gdpr i], [aria-label*=cookie i], [aria-label*=consent i]",
+    accept: &[],
+};
+
+/// Elements of a banner that can be an accept button
+const BUTTONS: &str = "button, [role=button], a, input[type=button], input[type=submit]";
+
+/// Texts of buttons that give consent, in lower case and with single spaces, the most
+/// explicit first
+const ACCEPT_TEXTS: &[&str] = &[
+    "accept",
+    "accept all",
+    "accept all cookies",
+    "accept cookies",
+    "accept and close",
+    "allow all",
+    "allow all cookies",
+    "allow cookies",
+    "agree",
+    "i agree",
+    "agree and close",
+    "agree and proceed",
+    "yes, i agree",
+    "i consent",
+    "got it",
+];
+
+/// Texts that only give consent in the banner of a known platform, elsewhere an "OK" can as
+/// well save the settings of a panel
+const PLATFORM_ACCEPT_TEXTS: &[&str] = &["consent", "ok", "okay"];
+
+/// How the accept button is told apart from the other buttons of its banner
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Accept {
+    /// The known accept button of the platform
+    Selector(&'static str),
+    /// The button with the text or label, one of the accept texts
+    Text(&'static str),
+}
+
+/// The accept button of a consent banner found in a document
+///
+/// The button is described by its banner and how to find it in there, rather than by its
+/// place in the document, so the same button is found again in the live page.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConsentButton {
+    /// The platform the banner was recognised as
+    pub strategy: &'static str,
+    /// Elements that are only on the page while the banner is shown
+    pub banner: &'static str,
+    pub accept: Accept,
+}
+
+impl ConsentButton {
+    /// Script that clicks the button in the first banner of the live page that has it, and
+    /// returns whether there was one to click
+    fn click_script(&self) -> String {
+        let find = match self.accept {
+            Accept::Selector(selector) => format!("banner.querySelector({})", js_string(selector)),
+            Accept::Text(text) => format!(
+                "Array.from(banner.querySelectorAll({})).find((button) =>
+            [button.textContent, button.getAttribute('value'), button.getAttribute('aria-label')]
+                .some((label) => label !== null && normalize(label) === {}))",
+                js_string(BUTTONS),
+                js_string(text)
+            ),
+        };
+        format!(
+            r#"(() => {{
+    const normalize = (text) => text.trim().split(/\s+/).join(' ').toLowerCase();
+    for (const banner of document.querySelectorAll({})) {{
+        const button = {};
+        if (button) {{
+            button.click();
+            return true;
+        }}
+    }}
+    return false;
+}})()"#,
+            js_string(self.banner),
+            find
+        )
+    }
+}
+
+/// The text as a JavaScript string literal
+fn js_string(text: &str) -> String {
+    Value::from(text).to_string()
+}
+
+/// Find the accept button of a consent banner in the HTML of a page or frame
+///
+/// The banner is recognised by the structure of a known consent-management platform, or by
+/// cookie and consent in the ids, classes and labels of its elements. In the banner, the
+/// known accept button is taken, or else the button with the most explicit accept text.
+pub fn find_accept_button(html: &str) -> Option<ConsentButton> {
+    let document = Html::parse_document(html);
+    let platform_texts: Vec<&'static str> = [ACCEPT_TEXTS, PLATFORM_ACCEPT_TEXTS].concat();
+
+    STRATEGIES
+        .iter()
+        .find_map(|strategy| find_in_banner(&document, strategy, &platform_texts))
+        .or_else(|| find_in_banner(&document, &GENERIC, ACCEPT_TEXTS))
+}
+
+/// Find the accept button of a banner of the strategy, with one of the texts
+fn find_in_banner(
+    document: &Html,
+    strategy: &Strategy,
+    texts: &[&'static str],
+) -> Option<ConsentButton> {
+    let banner = Selector::parse(strategy.banner).ok()?;
+    // The first text any button has wins, so "Accept all" beats the "OK" of a panel
+    let candidates: Vec<Accept> = strategy
+        .accept
+        .iter()
+        .map(|accept| Accept::Selector(accept))
+        .chain(texts.iter().map(|text| Accept::Text(text)))
+        .collect();
+
+    document.select(&banner).find_map(|banner| {
+        let accept = candidates
+            .iter()
+            .find(|accept| find_button(banner, **accept).is_some())?;
+        Some(ConsentButton {
+            strategy: strategy.name,
+            banner: strategy.banner,
+            accept: *accept,
+        })
+    })
+}
+
+/// The button of the banner the accept rule picks
+fn find_button(banner: ElementRef, accept: Accept) -> Option<ElementRef> {
+    match accept {
+        Accept::Selector(selector) => banner.select(&Selector::parse(selector).ok()?).next(),
+        Accept::Text(text) => banner
+            .select(&Selector::parse(BUTTONS).ok()?)
+            .find(|button| has_text(button, text)),
+    }
+}
+
+/// Whether the text or label of the button is the text
+fn has_text(button: &ElementRef, text: &str) -> bool {
+    let element = button.value();
+    let content = button.text().collect::<String>();
+    let labels = [
+        Some(content.as_str()),
+        element.attr("value"),
+        element.attr("aria-label"),
+    ];
+    labels
+        .iter()
+        .flatten()
+        .any(|label| normalize(label) == text)
+}
+
+/// The text in lower case, with the whitespace collapsed to single spaces
+fn normalize(text: &str) -> String {
+    text.split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+/// Accepts the consent banner of the page, when one appears
+///
+/// Looks for a banner in the page and in its frames every 100ms, until one is accepted or
+/// the consent timeout passes, also while the consent scripts are blocked, since a banner
+/// can still come from elsewhere. When the consent cookies are preseeded, the banner is
+/// looked for once without waiting.
+///
+/// ## Returns
+/// Returns the platform of the banner that was accepted, `None` when no banner appeared
+///
+/// ## Errors
+/// Returns an error when the page cannot be read or the accept button cannot be clicked
+pub async fn accept_consent(tab: &Arc<Tab>) -> Result<Option<&'static str>> {
+    let start_time = Instant::now();
+    let timeout = if options().cookies.is_some() {
+        Duration::ZERO
+    } else {
+        options().consent_timeout
+    };
+    let mut interval = tokio::time::interval(Duration::from_millis(100));
+
+    loop {
+        interval.tick().await;
+
+        let page = tab.clone();
+        if let Some((button, place)) = run_blocking(move || accept_in_page(&page)).await? {
+            info!(
+                "🍪 Accepted the consent banner in {} ({})",
+                place, button.strategy
+            );
+            return Ok(Some(button.strategy));
+        }
+
+        if start_time.elapsed() >= timeout {
+            debug!("No consent banner appeared");
+            return Ok(None);
+        }
+    }
+}
+
+/// Click the accept button of a banner in the page or one of its frames
+///
+/// ## Returns
+/// Returns the button that was clicked and where it was
+fn accept_in_page(tab: &Tab) -> Result<Option<(ConsentButton, String)>> {
+    // The button is looked up again in the live page, so the click cannot hit another element
+    // when the page changed since its HTML was read
+    if let Some(button) = find_accept_button(&tab.get_content()?) {
+        let clicked = tab.evaluate(&button.click_script(), false)?.value;
+        return Ok((clicked == Some(Value::Bool(true))).then(|| (button, "the page".to_string())));
+    }
+
+    let tree = tab.call_method(GetFrameTree(None))?.frame_tree;
+    for frame in child_frames(tree) {
+        // Frames can go away while they are read, the others are still looked at
+        let Ok(context) = isolated_world(tab, &frame.frame.id) else {
+            continue;
+        };
+        let Ok(Some(Value::String(html))) =
+            evaluate_in(tab, context, "document.documentElement.outerHTML")
+        else {
+            continue;
+        };
+        if let Some(button) = find_accept_button(&html) {
+            let clicked = evaluate_in(tab, context, &button.click_script())?;
+            if clicked == Some(Value::Bool(true)) {
+                return Ok(Some((button, format!("the frame {}", frame.frame.url))));
+            }
+        }
+    }
+    Ok(None)
+}
+
+/// The frames in the frame tree below its root, outer frames first
+fn child_frames(tree: FrameTree) -> Vec<FrameTree> {
+    let mut frames = Vec::new();
+    let mut pending = tree.child_frames.unwrap_or_default();
+    while !pending.is_empty() {
+        let mut frame = pending.remove(0);
+        pending.extend(frame.child_frames.take().unwrap_or_default());
+        frames.push(frame);
+    }
+    frames
+}
+
+/// A script context in the frame, next to the scripts of the frame itself
+fn isolated_world(tab: &Tab, frame_id: &str) -> Result<ExecutionContextId> {
+    Ok(tab
+        .call_method(CreateIsolatedWorld {
+            frame_id: frame_id.to_string(),
+            world_name: Some("consent".to_string()),
+            grant_univeral_access: None,
+        })?
+        .execution_context_id)
+}
+
+/// Run the script in the context and return its value
+fn evaluate_in(tab: &Tab, context: ExecutionContextId, script: &str) -> Result<Option<Value>> {
+    let result = tab.call_method(Evaluate {
+        expression: script.to_string(),
+        object_group: None,
+        include_command_line_api: None,
+        silent: Some(true),
+        context_id: Some(context),
+        return_by_value: Some(true),
+        generate_preview: None,
+        user_gesture: Some(true),
+        await_promise: None,
+        throw_on_side_effect: None,
+        timeout: None,
+        disable_breaks: None,
+        repl_mode: None,
+        allow_unsafe_eval_blocked_by_csp: None,
+        unique_context_id: None,
+        serialization_options: None,
+    })?;
+    if let Some(exception) = result.exception_details {
+        anyhow::bail!("The script failed in the frame: {}", exception.text);
+    }
+    Ok(result.result.value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// The text of the button in the document
+    fn selected_text(html: &str, button: &ConsentButton) -> String {
+        let document = Html::parse_document(html);
+        let banner = Selector::parse(button.banner).unwrap();
+        let selected = document
+            .select(&banner)
+            .find_map(|banner| find_button(banner, button.accept))
+            .expect("the button is not in the page");
+        normalize(&selected.text().collect::<String>())
+    }
+
+    fn accepts(html: &str, strategy: &str, text: &str) {
+        let button = find_accept_button(html).expect("no consent banner found");
+        assert_eq!(button.strategy, strategy);
+        assert_eq!(selected_text(html, &button), text);
+    }
+
+    #[test]
+    fn recognises_funding_choices() {
+        accepts(
+            include_str!("../../tests/fixtures/consent/funding_choices.html"),
+            "Google Funding Choices",
+            "consent",
+        );
+    }
+
+    #[test]
+    fn recognises_onetrust() {
+        accepts(
+            include_str!("../../tests/fixtures/consent/onetrust.html"),
+            "OneTrust",
+            "accept all cookies",
+        );
+    }
+
+    #[test]
+    fn recognises_cookiebot() {
+        accepts(
+            include_str!("../../tests/fixtures/consent/cookiebot.html"),
+            "Cookiebot",
+            "allow all",
+        );
+    }
+
+    #[test]
+    fn recognises_quantcast_by_the_primary_button() {
+        accepts(
+            include_str!("../../tests/fixtures/consent/quantcast.html"),
+            "Quantcast Choice",
+            "agree",
+        );
+    }
+
+    #[test]
+    fn recognises_the_sourcepoint_frame() {
+        accepts(
+            include_str!("../../tests/fixtures/consent/sourcepoint_frame.html"),
+            "Sourcepoint",
+            "accept",
+        );
+    }
+
+    #[test]
+    fn recognises_other_banners_by_their_button_text() {
+        accepts(
+            include_str!("../../tests/fixtures/consent/generic.html"),
+            "generic cookie banner",
+            "got it",
+        );
+    }
+
+    #[test]
+    fn does_not_confirm_a_settings_panel() {
+        // The panel has cookie in its class, but its OK saves the settings, it is no banner
+        let panel = include_str!("../../tests/fixtures/consent/settings_panel.html");
+        assert_eq!(find_accept_button(panel), None);
+
+        // In a banner, the explicit accept button wins over the OK of its settings
+        let html = r#"<body><div class="cookie-consent"><p>We use cookies.</p>
+            <div class="cookie-options"><label><input type="checkbox"> Statistics</label><button>OK</button></div>
+            <button>Accept all</button></div></body>"#;
+        accepts(html, "generic cookie banner", "accept all");
+    }
+
+    #[test]
+    fn ignores_pages_without_a_banner() {
+        let chimp = include_str!("../../tests/fixtures/chimp/round.html");
+        assert_eq!(find_accept_button(chimp), None);
+        assert_eq!(
+            find_accept_button("<body><button>Accept</button></body>"),
+            None
+        );
+    }
+
+    #[test]
+    fn clicks_the_button_found_in_the_live_banner() {
+        let html = include_str!("../../tests/fixtures/consent/generic.html");
+        let button = find_accept_button(html).unwrap();
+        assert_eq!(button.accept, Accept::Text("got it"));
+
+        let script = button.click_script();
+        assert!(script.contains(&format!(
+            "document.querySelectorAll({})",
+            js_string(GENERIC.banner)
+        )));
+        assert!(script.contains(r#"normalize(label) === "got it""#));
+
+        let onetrust =
+            find_accept_button(include_str!("../../tests/fixtures/consent/onetrust.html")).unwrap();
+        assert!(onetrust
+            .click_script()
+            .contains(r##"banner.querySelector("#onetrust-accept-btn-handler")"##));
+    }
+}
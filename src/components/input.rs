@@ -0,0 +1,525 @@
+use super::utils::{click_on_pixel, Point};
+use anyhow::Result;
+use headless_chrome::Tab;
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+#[cfg(target_os = "windows")]
+use winapi::um::winuser::{
+    SendInput, SetCursorPos, INPUT, INPUT_KEYBOARD, KEYEVENTF_KEYUP, KEYEVENTF_UNICODE,
+};
+
+// Virtual key codes the backends translate or decode themselves
+pub const VK_BACK: u8 = 0x08;
+pub const VK_TAB: u8 = 0x09;
+pub const VK_RETURN: u8 = 0x0D;
+pub const VK_SHIFT: u8 = 0x10;
+pub const VK_CONTROL: u8 = 0x11;
+pub const VK_MENU: u8 = 0x12;
+
+/// A single key event, as sent to the focused window
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum KeyAction {
+    KeyPress(u8),
+    KeyRelease(u8),
+    /// A single UTF-16 code unit sent as a Unicode key event (press and release)
+    Unicode(u16),
+}
+
+/// The modifier keys held down while a key is pressed
+#[cfg_attr(not(test), allow(dead_code))]
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub struct Modifiers {
+    pub shift: bool,
+    pub ctrl: bool,
+    pub alt: bool,
+}
+
+/// Where the solvers send their mouse and keyboard input
+///
+/// Points are in page coordinates plus the [`Input::origin`] of the backend, so a solver
+/// computes its targets the same way for every backend.
+pub trait Input: Send + Sync {
+    /// Move the cursor to the point without clicking
+    fn move_to(&self, point: &Point) -> Result<()>;
+
+    /// Click the point with the left mouse button
+    fn click(&self, point: &Point) -> Result<()>;
+
+    /// Send the key actions to the focused element in a single batch
+    fn send_keys(&self, actions: &[KeyAction]) -> Result<()>;
+
+    /// Insert the text into the focused element
+    fn insert_text(&self, text: &str) -> Result<()>;
+
+    /// Position of the page in the coordinates of the backend
+    fn origin(&self) -> Result<Point> {
+        Ok(Point { x: 0, y: 0 })
+    }
+}
+
+/// How solvers send mouse clicks to the page
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum InputMode {
+    /// Move the cursor and click with the Win32 Api, on screen coordinates of a visible window
+    Os,
+    /// Dispatch the click through the DevTools Protocol, on page coordinates, so it also works
+    /// in headless sessions running next to each other
+    Cdp,
+}
+
+impl InputMode {
+    /// Create the input backend of the mode for the tab
+    pub fn backend(self, tab: &Arc<Tab>) -> Arc<dyn Input> {
+        match self {
+            InputMode::Os => Arc::new(OsInput::new(tab.clone())),
+            InputMode::Cdp => Arc::new(CdpInput::new(tab.clone())),
+        }
+    }
+}
+
+/// Input through the Win32 Api, on the screen coordinates of the browser window
+pub struct OsInput {
+    tab: Arc<Tab>,
+}
+
+impl OsInput {
+    pub fn new(tab: Arc<Tab>) -> Self {
+        Self { tab }
+    }
+}
+
+impl Input for OsInput {
+    #[cfg(target_os = "windows")]
+    fn move_to(&self, point: &Point) -> Result<()> {
+        unsafe {
+            SetCursorPos(point.x, point.y);
+        }
+        Ok(())
+    }
+
+    #[cfg(not(target_os = "windows"))]
+    fn move_to(&self, point: &Point) -> Result<()> {
+        anyhow::bail!(
+            "Moving the cursor to ({}, {}) is only supported on Windows",
+            point.x,
+            point.y
+        )
+    }
+
+    fn click(&self, point: &Point) -> Result<()> {
+        click_on_pixel(point.x, point.y)
+    }
+
+    /// `SendInput` inserts the events serially into the input stream, so no other input can
+    /// end up between them. Fails when not every event could be injected.
+    #[cfg(target_os = "windows")]
+    fn send_keys(&self, actions: &[KeyAction]) -> Result<()> {
+        let mut inputs = Vec::with_capacity(actions.len() * 2);
+        for action in actions {
+            match action {
+                KeyAction::KeyPress(vk) => inputs.push(keyboard_input(*vk as u16, 0, 0)),
+                KeyAction::KeyRelease(vk) => {
+                    inputs.push(keyboard_input(*vk as u16, 0, KEYEVENTF_KEYUP))
+                }
+                KeyAction::Unicode(unit) => {
+                    inputs.push(keyboard_input(0, *unit, KEYEVENTF_UNICODE));
+                    inputs.push(keyboard_input(
+                        0,
+                        *unit,
+                        KEYEVENTF_UNICODE | KEYEVENTF_KEYUP,
+                    ));
+                }
+            }
+        }
+
+        let injected = unsafe {
+            SendInput(
+                inputs.len() as u32,
+                inputs.as_mut_ptr(),
+                std::mem::size_of::<INPUT>() as i32,
+            )
+        };
+
+        if injected as usize != inputs.len() {
+            anyhow::bail!(
+                "SendInput only injected {} of {} key events",
+                injected,
+                inputs.len()
+            );
+        }
+
+        Ok(())
+    }
+
+    #[cfg(not(target_os = "windows"))]
+    fn send_keys(&self, actions: &[KeyAction]) -> Result<()> {
+        anyhow::bail!(
+            "Sending {} key events is only supported on Windows",
+            actions.len()
+        )
+    }
+
+    fn insert_text(&self, text: &str) -> Result<()> {
+        let actions: Vec<KeyAction> = text.encode_utf16().map(KeyAction::Unicode).collect();
+        self.send_keys(&actions)
+    }
+
+    fn origin(&self) -> Result<Point> {
+        let window_bounds = self.tab.get_bounds()?;
+        // Offsets to take the browser url bar and toolbar into account
+        Ok(Point {
+            x: window_bounds.left as i32 + 5,
+            y: window_bounds.top as i32 + 140,
+        })
+    }
+}
+
+/// Create a keyboard event for `SendInput`
+#[cfg(target_os = "windows")]
+fn keyboard_input(vk: u16, scan: u16, flags: u32) -> INPUT {
+    unsafe {
+        let mut input: INPUT = std::mem::zeroed();
+        input.type_ = INPUT_KEYBOARD;
+
+        let keyboard_input = input.u.ki_mut();
+        keyboard_input.wVk = vk;
+        keyboard_input.wScan = scan;
+        keyboard_input.dwFlags = flags;
+
+        input
+    }
+}
+
+/// Input dispatched through the DevTools Protocol, on page coordinates
+pub struct CdpInput {
+    tab: Arc<Tab>,
+}
+
+impl CdpInput {
+    pub fn new(tab: Arc<Tab>) -> Self {
+        Self { tab }
+    }
+}
+
+impl Input for CdpInput {
+    fn move_to(&self, point: &Point) -> Result<()> {
+        self.tab.move_mouse_to_point(page_point(point))?;
+        Ok(())
+    }
+
+    fn click(&self, point: &Point) -> Result<()> {
+        self.tab.click_point(page_point(point))?;
+        Ok(())
+    }
+
+    /// Unicode key events are inserted as text and Enter, Tab and Backspace are pressed as
+    /// keys. The DevTools Protocol does not type through a keyboard layout, so the key
+    /// strokes of a layout can't be sent, and nothing is sent when the actions contain any
+    /// other virtual key.
+    fn send_keys(&self, actions: &[KeyAction]) -> Result<()> {
+        check_key_names(actions)?;
+
+        let mut units = Vec::new();
+        for action in actions {
+            match action {
+                KeyAction::Unicode(unit) => {
+                    units.push(*unit);
+                    continue;
+                }
+                KeyAction::KeyPress(vk) => {
+                    self.insert_units(&mut units)?;
+                    if let Some(key) = key_name(*vk) {
+                        self.tab.press_key(key)?;
+                    }
+                }
+                KeyAction::KeyRelease(_) => self.insert_units(&mut units)?,
+            }
+        }
+        self.insert_units(&mut units)
+    }
+
+    fn insert_text(&self, text: &str) -> Result<()> {
+        self.tab.send_character(text)?;
+        Ok(())
+    }
+}
+
+impl CdpInput {
+    /// Insert the collected UTF-16 code units as text and clear them
+    fn insert_units(&self, units: &mut Vec<u16>) -> Result<()> {
+        if !units.is_empty() {
+            self.insert_text(&String::from_utf16_lossy(units))?;
+            units.clear();
+        }
+        Ok(())
+    }
+}
+
+fn page_point(point: &Point) -> headless_chrome::browser::tab::point::Point {
+    headless_chrome::browser::tab::point::Point {
+        x: point.x as f64,
+        y: point.y as f64,
+    }
+}
+
+/// The DevTools Protocol name of the virtual keys the solvers press
+fn key_name(vk: u8) -> Option<&'static str> {
+    match vk {
+        VK_BACK => Some("Backspace"),
+        VK_TAB => Some("Tab"),
+        VK_RETURN => Some("Enter"),
+        _ => None,
+    }
+}
+
+/// Fail on the first virtual key that has no DevTools Protocol name
+fn check_key_names(actions: &[KeyAction]) -> Result<()> {
+    for action in actions {
+        if let KeyAction::KeyPress(vk) | KeyAction::KeyRelease(vk) = action {
+            if key_name(*vk).is_none() {
+                anyhow::bail!(
+                    "The DevTools Protocol input can't press the virtual key 0x{:02X}, only Enter, Tab and Backspace",
+                    vk
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
+/// An input event as the recording backend received it
+#[derive(Debug, Clone, PartialEq)]
+pub enum InputEvent {
+    Move(Point),
+    Click(Point),
+    Key(KeyAction),
+    Text(String),
+}
+
+/// An input event with the time since the recording started
+#[derive(Debug, Clone, PartialEq)]
+pub struct RecordedEvent {
+    pub elapsed: Duration,
+    pub event: InputEvent,
+}
+
+/// Input backend that records every event instead of sending it, for tests
+#[cfg_attr(not(test), allow(dead_code))]
+pub struct RecordingInput {
+    start: Instant,
+    origin: Point,
+    events: Mutex<Vec<RecordedEvent>>,
+}
+
+#[cfg_attr(not(test), allow(dead_code))]
+impl RecordingInput {
+    pub fn new() -> Self {
+        Self::with_origin(Point { x: 0, y: 0 })
+    }
+
+    /// Record on a page that is at `origin`, like a browser window on the screen
+    pub fn with_origin(origin: Point) -> Self {
+        Self {
+            start: Instant::now(),
+            origin,
+            events: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// All events recorded so far, in the order they were received
+    pub fn events(&self) -> Vec<RecordedEvent> {
+        self.events.lock().unwrap().clone()
+    }
+
+    /// The points that were clicked, in order
+    pub fn clicks(&self) -> Vec<Point> {
+        self.events()
+            .into_iter()
+            .filter_map(|recorded| match recorded.event {
+                InputEvent::Click(point) => Some(point),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// The text the Unicode key events and inserted text produce, with Backspace applied
+    ///
+    /// Other virtual keys are ignored, [`RecordingInput::typed_text_on`] decodes them.
+    pub fn typed_text(&self) -> String {
+        self.typed_text_on(|_, _| None)
+    }
+
+    /// The text the events produce on a keyboard layout, with Backspace applied
+    ///
+    /// `layout` maps a virtual key and the modifiers held down with it to the character it
+    /// types, like the lookup of the layout method the other way around.
+    pub fn typed_text_on(&self, layout: impl Fn(u8, Modifiers) -> Option<char>) -> String {
+        let mut text = String::new();
+        let mut units = Vec::new();
+        let mut modifiers = Modifiers::default();
+        for recorded in self.events() {
+            match recorded.event {
+                InputEvent::Key(KeyAction::Unicode(unit)) => {
+                    units.push(unit);
+                    continue;
+                }
+                InputEvent::Key(KeyAction::KeyPress(vk)) => {
+                    text.push_str(&String::from_utf16_lossy(&units));
+                    units.clear();
+                    match vk {
+                        VK_BACK => {
+                            text.pop();
+                        }
+                        VK_TAB => text.push('\t'),
+                        VK_RETURN => text.push('\n'),
+                        VK_SHIFT => modifiers.shift = true,
+                        VK_CONTROL => modifiers.ctrl = true,
+                        VK_MENU => modifiers.alt = true,
+                        _ => text.extend(layout(vk, modifiers)),
+                    }
+                }
+                InputEvent::Key(KeyAction::KeyRelease(vk)) => match vk {
+                    VK_SHIFT => modifiers.shift = false,
+                    VK_CONTROL => modifiers.ctrl = false,
+                    VK_MENU => modifiers.alt = false,
+                    _ => {}
+                },
+                InputEvent::Text(inserted) => {
+                    text.push_str(&String::from_utf16_lossy(&units));
+                    units.clear();
+                    text.push_str(&inserted);
+                }
+                _ => {}
+            }
+        }
+        text.push_str(&String::from_utf16_lossy(&units));
+        text
+    }
+
+    fn record(&self, event: InputEvent) {
+        self.events.lock().unwrap().push(RecordedEvent {
+            elapsed: self.start.elapsed(),
+            event,
+        });
+    }
+}
+
+impl Default for RecordingInput {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Input for RecordingInput {
+    fn move_to(&self, point: &Point) -> Result<()> {
+        self.record(InputEvent::Move(point.clone()));
+        Ok(())
+    }
+
+    fn click(&self, point: &Point) -> Result<()> {
+        self.record(InputEvent::Click(point.clone()));
+        Ok(())
+    }
+
+    fn send_keys(&self, actions: &[KeyAction]) -> Result<()> {
+        for action in actions {
+            self.record(InputEvent::Key(*action));
+        }
+        Ok(())
+    }
+
+    fn insert_text(&self, text: &str) -> Result<()> {
+        self.record(InputEvent::Text(text.to_string()));
+        Ok(())
+    }
+
+    fn origin(&self) -> Result<Point> {
+        Ok(self.origin.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn records_events_in_order() {
+        let input = RecordingInput::with_origin(Point { x: 10, y: 20 });
+        input.move_to(&Point { x: 1, y: 2 }).unwrap();
+        input.click(&Point { x: 3, y: 4 }).unwrap();
+        input.send_keys(&[KeyAction::KeyPress(VK_RETURN)]).unwrap();
+
+        let events: Vec<InputEvent> = input.events().into_iter().map(|e| e.event).collect();
+        assert_eq!(
+            events,
+            vec![
+                InputEvent::Move(Point { x: 1, y: 2 }),
+                InputEvent::Click(Point { x: 3, y: 4 }),
+                InputEvent::Key(KeyAction::KeyPress(VK_RETURN)),
+            ]
+        );
+        assert!(input
+            .events()
+            .windows(2)
+            .all(|pair| pair[0].elapsed <= pair[1].elapsed));
+        assert_eq!(input.clicks(), vec![Point { x: 3, y: 4 }]);
+        assert_eq!(input.origin().unwrap(), Point { x: 10, y: 20 });
+    }
+
+    #[test]
+    fn decodes_typed_text() {
+        let input = RecordingInput::new();
+        let mut actions: Vec<KeyAction> = "a😀".encode_utf16().map(KeyAction::Unicode).collect();
+        actions.push(KeyAction::KeyPress(VK_RETURN));
+        actions.push(KeyAction::KeyRelease(VK_RETURN));
+        input.send_keys(&actions).unwrap();
+        input.insert_text("bcx").unwrap();
+        input
+            .send_keys(&[KeyAction::KeyPress(VK_BACK), KeyAction::KeyRelease(VK_BACK)])
+            .unwrap();
+
+        assert_eq!(input.typed_text(), "a😀\nbc");
+    }
+
+    #[test]
+    fn decodes_key_strokes_on_a_layout() {
+        const VK_A: u8 = 0x41;
+        let layout = |vk: u8, modifiers: Modifiers| match (vk, modifiers) {
+            (VK_A, Modifiers { shift: false, .. }) => Some('a'),
+            (VK_A, Modifiers { shift: true, .. }) => Some('A'),
+            _ => None,
+        };
+
+        let input = RecordingInput::new();
+        input
+            .send_keys(&[
+                KeyAction::KeyPress(VK_SHIFT),
+                KeyAction::KeyPress(VK_A),
+                KeyAction::KeyRelease(VK_A),
+                KeyAction::KeyRelease(VK_SHIFT),
+                KeyAction::KeyPress(VK_A),
+                KeyAction::KeyRelease(VK_A),
+                KeyAction::KeyPress(0x51),
+                KeyAction::KeyRelease(0x51),
+            ])
+            .unwrap();
+
+        assert_eq!(input.typed_text_on(layout), "Aa");
+        assert_eq!(input.typed_text(), "");
+    }
+
+    #[test]
+    fn cdp_input_rejects_keys_it_cannot_press() {
+        let mut actions: Vec<KeyAction> = "ok".encode_utf16().map(KeyAction::Unicode).collect();
+        actions.push(KeyAction::KeyPress(VK_RETURN));
+        actions.push(KeyAction::KeyRelease(VK_RETURN));
+        assert!(check_key_names(&actions).is_ok());
+
+        actions.push(KeyAction::KeyPress(VK_SHIFT));
+        let error = check_key_names(&actions).unwrap_err();
+        assert!(error.to_string().contains("0x10"));
+    }
+}
@@ -0,0 +1,156 @@
+use super::options::Options;
+use anyhow::{Context, Result};
+use std::collections::VecDeque;
+use std::fs::OpenOptions;
+use std::io;
+use std::sync::Mutex;
+use tracing::level_filters::LevelFilter;
+use tracing_subscriber::{fmt, prelude::*};
+
+/// Number of messages kept in memory for the trace of the failure artifacts
+const RECENT_CAPACITY: usize = 500;
+
+/// The last messages down to the debug level, as JSON lines, oldest first
+static RECENT: Mutex<VecDeque<String>> = Mutex::new(VecDeque::new());
+
+/// Send the messages of the program to the console, and to the log file when one is set
+///
+/// The console shows the messages down to the level set with `-v` and `-q`, without
+/// timestamps. The log file gets a JSON object per line with the timestamp, level, message
+/// and spans of every message, at least down to the debug level, so runs can be looked into
+/// afterwards. Runs are appended to the file. The last messages are also kept in memory in
+/// the same format, for the trace of the failure artifacts.
+pub fn init(options: &Options) -> Result<()> {
+    let console = fmt::layer()
+        .without_time()
+        .with_target(false)
+        .with_filter(options.log_level);
+
+    let log_file = match &options.log_file {
+        Some(path) => {
+            let file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(path)
+                .with_context(|| format!("Could not open the log file {}", path.display()))?;
+            Some(
+                fmt::layer()
+                    .json()
+                    .with_writer(Mutex::new(file))
+                    .with_filter(options.log_level.max(LevelFilter::DEBUG)),
+            )
+        }
+        None => None,
+    };
+
+    let recent = fmt::layer()
+        .json()
+        .with_writer(|| RecentWriter)
+        .with_filter(LevelFilter::DEBUG);
+
+    tracing_subscriber::registry()
+        .with(console)
+        .with(log_file)
+        .with(recent)
+        .try_init()?;
+
+    Ok(())
+}
+
+/// The last messages of the program as JSON lines, oldest first
+///
+/// With a session, only the messages logged in the `session` span of that `--parallel`
+/// session, so the trace of a failed session does not hold the messages of the others.
+pub fn recent_messages(session: Option<usize>) -> Vec<String> {
+    let recent = RECENT.lock().unwrap();
+    recent
+        .iter()
+        .filter(|message| session.is_none_or(|session| in_session(message, session)))
+        .cloned()
+        .collect()
+}
+
+/// Whether the JSON line was logged inside the `session` span of the session
+fn in_session(message: &str, session: usize) -> bool {
+    let Ok(message) = serde_json::from_str::<serde_json::Value>(message) else {
+        return false;
+    };
+    message["spans"].as_array().is_some_and(|spans| {
+        spans.iter().any(|span| {
+            span["name"] == "session" && span["session"].as_u64() == Some(session as u64)
+        })
+    })
+}
+
+/// Writes every formatted message into the ring of recent messages
+///
+/// The formatter writes a whole message, ending in a newline, at once.
+struct RecentWriter;
+
+impl io::Write for RecentWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let message = String::from_utf8_lossy(buf).trim_end().to_string();
+        let mut recent = RECENT.lock().unwrap();
+        if recent.len() == RECENT_CAPACITY {
+            recent.pop_front();
+        }
+        recent.push_back(message);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use tracing::{info, info_span};
+
+    /// Collects the formatted messages of a test
+    #[derive(Clone, Default)]
+    struct Buffer(Arc<Mutex<Vec<u8>>>);
+
+    impl io::Write for Buffer {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn keeps_the_messages_of_a_session_apart() {
+        let buffer = Buffer::default();
+        let writer = buffer.clone();
+        let subscriber = tracing_subscriber::registry()
+            .with(fmt::layer().json().with_writer(move || writer.clone()));
+
+        tracing::subscriber::with_default(subscriber, || {
+            info!("🚀 Launching 2 headless sessions");
+            for session in 1..=2 {
+                let _session = info_span!("session", session).entered();
+                let _round = info_span!("round", pass = 1).entered();
+                info!("Clicked in session {}", session);
+            }
+        });
+
+        let text = String::from_utf8(buffer.0.lock().unwrap().clone()).unwrap();
+        let messages: Vec<&str> = text.lines().collect();
+        assert_eq!(messages.len(), 3);
+
+        let first: Vec<&&str> = messages
+            .iter()
+            .filter(|message| in_session(message, 1))
+            .collect();
+        assert_eq!(first.len(), 1);
+        assert!(first[0].contains("Clicked in session 1"));
+        assert!(!in_session(messages[0], 1));
+        assert!(!in_session("not json", 1));
+    }
+}
@@ -1 +1,14 @@
+pub mod account;
+pub mod artifacts;
+pub mod blocking;
+pub mod consent;
+pub mod input;
+pub mod logging;
+pub mod options;
+pub mod page;
+pub mod percentile;
+pub mod recording;
+pub mod results;
+pub mod state_machine;
+pub mod trace;
 pub mod utils;
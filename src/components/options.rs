@@ -0,0 +1,427 @@
+use anyhow::Result;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+use std::time::Duration;
+use tracing::level_filters::LevelFilter;
+
+static OPTIONS: OnceLock<Options> = OnceLock::new();
+
+/// What the program does after parsing the options
+#[derive(Debug, Clone, PartialEq, Default)]
+pub enum Command {
+    /// Show the menu of tests
+    #[default]
+    Menu,
+    /// Print the words of the Verbal Memory test collected over earlier runs
+    Words,
+    /// Replay the trace file through the solver it was recorded from
+    Replay(PathBuf),
+    /// Read the score distributions from the charts of the site into the distributions file
+    Distributions,
+}
+
+/// Command line options of the program, read once at startup
+#[derive(Debug, Clone)]
+pub struct Options {
+    /// What to do, the menu unless a subcommand was given
+    pub command: Command,
+    /// Wait for Enter before closing the browser at the end of a test, for demos
+    pub pause_at_end: bool,
+    /// Maximum time to wait for an element the benchmark needs to appear
+    pub ready_timeout: Duration,
+    /// Maximum time to wait for the page to stop loading resources like ads
+    pub idle_timeout: Duration,
+    /// Maximum time to wait for the cookie consent banner to appear
+    pub consent_timeout: Duration,
+    /// Number of rounds the Reaction Time test plays, the default of the menu and the rounds
+    /// of every `--parallel` session
+    pub reaction_rounds: u32,
+    /// Highest level the Chimp Test plays, `None` to play until the end screen
+    pub max_level: Option<u32>,
+    /// End the Chimp Test on its first strike instead of playing on
+    pub stop_on_first_strike: bool,
+    /// Number of words the Verbal Memory test answers before it stops
+    pub max_words: Option<u32>,
+    /// Score at which the Verbal Memory test makes mistakes on purpose to end the game
+    pub target_score: Option<u32>,
+    /// Add the words of every Verbal Memory run to the word corpus file
+    pub save_words: bool,
+    /// File with the words the Verbal Memory test has shown over all runs
+    pub word_corpus: PathBuf,
+    /// Number of headless sessions to run next to each other, `None` for the interactive menu
+    pub parallel: Option<usize>,
+    /// Most detailed level of the messages on the console, raised with `-v` and lowered with `-q`
+    pub log_level: LevelFilter,
+    /// File to write every message to as JSON lines, at least down to the debug level
+    pub log_file: Option<PathBuf>,
+    /// Directory to save a screenshot, the page and the trace to when a benchmark fails
+    pub artifacts_dir: PathBuf,
+    /// Manifest file of the screencast of the benchmark, `None` to not record
+    pub record: Option<PathBuf>,
+    /// File to save a trace of the page observations and input of the solver to
+    pub trace: Option<PathBuf>,
+    /// Replay a trace at the pace of the recorded run, instead of as fast as possible
+    pub real_time: bool,
+    /// Block the requests to the default ad and consent domains, turned off with `--no-blocking`
+    pub block_requests: bool,
+    /// Url patterns to block on top of the default domains
+    pub blocked_urls: Vec<String>,
+    /// JSON file with cookies to set before a test page loads, like the consent of the site
+    pub cookies: Option<PathBuf>,
+    /// File with the account to log in to, `account.json` when it exists
+    pub account_file: Option<PathBuf>,
+    /// Chrome profile directory to keep the login and cookies in between runs
+    pub profile_dir: Option<PathBuf>,
+    /// Press "Save score" after every test and check that the site saved it
+    pub save_score: bool,
+    /// File with the score distributions of the tests, read from the site with the
+    /// `distributions` command
+    pub distributions: PathBuf,
+    /// JSON lines file every score is added to, with its percentile
+    pub results: PathBuf,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Self {
+            command: Command::Menu,
+            pause_at_end: false,
+            ready_timeout: Duration::from_secs(20),
+            idle_timeout: Duration::from_secs(5),
+            consent_timeout: Duration::from_secs(3),
+            reaction_rounds: 5,
+            max_level: None,
+            stop_on_first_strike: false,
+            max_words: None,
+            target_score: None,
+            save_words: false,
+            word_corpus: PathBuf::from("verbal_memory_words.txt"),
+            parallel: None,
+            log_level: LevelFilter::INFO,
+            log_file: None,
+            artifacts_dir: PathBuf::from("artifacts"),
+            record: None,
+            trace: None,
+            real_time: false,
+            block_requests: true,
+            blocked_urls: Vec::new(),
+            cookies: None,
+            account_file: None,
+            profile_dir: None,
+            save_score: false,
+            distributions: PathBuf::from("score_distributions.json"),
+            results: PathBuf::from("results.jsonl"),
+        }
+    }
+}
+
+impl Options {
+    /// Parse the options from the command line arguments, without the program name
+    pub fn from_args(args: impl IntoIterator<Item = String>) -> Result<Self> {
+        let mut options = Options::default();
+        let mut args = args.into_iter();
+
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--pause-at-end" => options.pause_at_end = true,
+                "--ready-timeout" => options.ready_timeout = parse_seconds(&arg, args.next())?,
+                "--idle-timeout" => options.idle_timeout = parse_seconds(&arg, args.next())?,
+                "--consent-timeout" => options.consent_timeout = parse_seconds(&arg, args.next())?,
+                "--rounds" => options.reaction_rounds = parse_count(&arg, args.next())?,
+                "--max-level" => options.max_level = Some(parse_count(&arg, args.next())?),
+                "--stop-on-strike" => options.stop_on_first_strike = true,
+                "--max-words" => options.max_words = Some(parse_count(&arg, args.next())?),
+                "--target-score" => options.target_score = Some(parse_count(&arg, args.next())?),
+                "--save-words" => options.save_words = true,
+                "--word-corpus" => options.word_corpus = parse_path(&arg, args.next())?,
+                "words" => options.command = Command::Words,
+                "replay" => options.command = Command::Replay(parse_path(&arg, args.next())?),
+                "distributions" => options.command = Command::Distributions,
+                "--parallel" => options.parallel = Some(parse_count(&arg, args.next())?),
+                "-v" | "--verbose" => options.log_level = more_verbose(options.log_level),
+                "-q" | "--quiet" => options.log_level = less_verbose(options.log_level),
+                "--log-file" => options.log_file = Some(parse_path(&arg, args.next())?),
+                "--artifacts-dir" => options.artifacts_dir = parse_path(&arg, args.next())?,
+                "--record" => options.record = Some(parse_path(&arg, args.next())?),
+                "--trace" => options.trace = Some(parse_path(&arg, args.next())?),
+                "--real-time" => options.real_time = true,
+                "--no-blocking" => options.block_requests = false,
+                "--block" => options.blocked_urls.push(
+                    args.next()
+                        .ok_or_else(|| anyhow::anyhow!("{} needs a url pattern", arg))?,
+                ),
+                "--cookies" => options.cookies = Some(parse_path(&arg, args.next())?),
+                "--account" => options.account_file = Some(parse_path(&arg, args.next())?),
+                "--profile" => options.profile_dir = Some(parse_path(&arg, args.next())?),
+                "--save-score" => options.save_score = true,
+                "--distributions" => options.distributions = parse_path(&arg, args.next())?,
+                "--results" => options.results = parse_path(&arg, args.next())?,
+                _ => anyhow::bail!("Unknown argument: {}\n\n{}", arg, Options::usage()),
+            }
+        }
+
+        if options.record.is_some() && options.parallel.is_some() {
+            anyhow::bail!("--record can only record a single session, not --parallel ones");
+        }
+        if options.trace.is_some() && options.parallel.is_some() {
+            anyhow::bail!("--trace can only trace a single session, not --parallel ones");
+        }
+        if options.profile_dir.is_some() && options.parallel.is_some() {
+            anyhow::bail!("--profile can only be used by a single session, not --parallel ones");
+        }
+
+        Ok(options)
+    }
+
+    pub fn usage() -> &'static str {
+        "Usage: human_benchmark [words | replay <file> | distributions] [options]
+
+Commands:
+  words                      Print the Verbal Memory words collected with --save-words
+  replay <file>              Replay a trace through its solver and check its decisions
+  distributions              Read the score distributions from the charts of the site
+
+Options:
+  --pause-at-end             Wait for Enter before closing the browser after a test
+  --ready-timeout <secs>     Maximum wait for the elements of a test (default: 20)
+  --idle-timeout <secs>      Maximum wait for the page to stop loading ads (default: 5)
+  --consent-timeout <secs>   Maximum wait for the cookie banner (default: 3)
+  --rounds <n>               Rounds of the Reaction Time test (default: 5)
+  --max-level <n>            Highest level the Chimp Test plays (default: until the end)
+  --stop-on-strike           End the Chimp Test on its first strike
+  --max-words <n>            Number of words the Verbal Memory test answers
+  --target-score <n>         Make mistakes on purpose to end Verbal Memory at this score
+  --save-words               Add the Verbal Memory words of the run to the word corpus
+  --word-corpus <file>       Word corpus file (default: verbal_memory_words.txt)
+  --parallel <n>             Run a test or the whole suite in n headless sessions at once
+  -v, --verbose              Show more details, twice for every step
+  -q, --quiet                Only show warnings, twice for errors only
+  --log-file <file>          Also write the messages as JSON lines to the file
+  --artifacts-dir <dir>      Where failed benchmarks save their artifacts (default: artifacts)
+  --record <file>            Record the benchmark as PNG frames with an ffconcat manifest
+  --trace <file>             Save the page observations and input of the solver to replay
+  --real-time                Replay a trace at the pace of the recorded run
+  --no-blocking              Load the ads and consent banners instead of blocking them
+  --block <pattern>          Also block the urls matching the pattern, like *://*.ads.com/*
+  --cookies <file>           Set the cookies of the JSON file before a test page loads
+  --account <file>           Account to log in to (default: account.json when it exists)
+  --profile <dir>            Keep the Chrome profile with the login in the directory
+  --save-score               Press Save score after every test and check that it saved
+  --distributions <file>     Score distributions file (default: score_distributions.json)
+  --results <file>           Scores with their percentiles (default: results.jsonl)"
+    }
+}
+
+fn parse_seconds(arg: &str, value: Option<String>) -> Result<Duration> {
+    let value = value.ok_or_else(|| anyhow::anyhow!("{} needs a number of seconds", arg))?;
+    let seconds: f64 = value
+        .parse()
+        .map_err(|_| anyhow::anyhow!("{} needs a number of seconds, got {}", arg, value))?;
+    Duration::try_from_secs_f64(seconds).map_err(|_| {
+        anyhow::anyhow!(
+            "{} needs a non-negative number of seconds, got {}",
+            arg,
+            value
+        )
+    })
+}
+
+fn parse_count<T: std::str::FromStr + PartialOrd + Default>(
+    arg: &str,
+    value: Option<String>,
+) -> Result<T> {
+    let value = value.ok_or_else(|| anyhow::anyhow!("{} needs a number", arg))?;
+    match value.parse() {
+        Ok(count) if count > T::default() => Ok(count),
+        _ => anyhow::bail!("{} needs a positive number, got {}", arg, value),
+    }
+}
+
+fn parse_path(arg: &str, value: Option<String>) -> Result<PathBuf> {
+    value
+        .map(PathBuf::from)
+        .ok_or_else(|| anyhow::anyhow!("{} needs a path", arg))
+}
+
+/// The next level down from the level, trace stays trace
+fn more_verbose(level: LevelFilter) -> LevelFilter {
+    match level {
+        LevelFilter::OFF => LevelFilter::ERROR,
+        LevelFilter::ERROR => LevelFilter::WARN,
+        LevelFilter::WARN => LevelFilter::INFO,
+        LevelFilter::INFO => LevelFilter::DEBUG,
+        _ => LevelFilter::TRACE,
+    }
+}
+
+/// The next level up from the level, off stays off
+fn less_verbose(level: LevelFilter) -> LevelFilter {
+    match level {
+        LevelFilter::TRACE => LevelFilter::DEBUG,
+        LevelFilter::DEBUG => LevelFilter::INFO,
+        LevelFilter::INFO => LevelFilter::WARN,
+        LevelFilter::WARN => LevelFilter::ERROR,
+        _ => LevelFilter::OFF,
+    }
+}
+
+/// Set the options for the rest of the program. Only the first call has an effect.
+pub fn init(options: Options) {
+    let _ = OPTIONS.set(options);
+}
+
+/// The options of the program, the defaults when they were never set
+pub fn options() -> &'static Options {
+    OPTIONS.get_or_init(Options::default)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn parses_options() {
+        let options = Options::from_args(args(&[
+            "--pause-at-end",
+            "--ready-timeout",
+            "2.5",
+            "--consent-timeout",
+            "0",
+            "--parallel",
+            "4",
+            "--max-level",
+            "20",
+            "--rounds",
+            "3",
+            "--stop-on-strike",
+            "words",
+            "--word-corpus",
+            "words.txt",
+            "--target-score",
+            "30",
+            "-v",
+            "--verbose",
+            "--log-file",
+            "run.jsonl",
+            "--artifacts-dir",
+            "failures",
+        ]))
+        .unwrap();
+
+        assert!(options.pause_at_end);
+        assert_eq!(options.ready_timeout, Duration::from_millis(2500));
+        assert_eq!(options.idle_timeout, Options::default().idle_timeout);
+        assert_eq!(options.consent_timeout, Duration::ZERO);
+        assert_eq!(options.parallel, Some(4));
+        assert_eq!(options.max_level, Some(20));
+        assert_eq!(options.reaction_rounds, 3);
+        assert!(options.stop_on_first_strike);
+        assert_eq!(options.command, Command::Words);
+        assert_eq!(options.word_corpus, PathBuf::from("words.txt"));
+        assert_eq!(options.target_score, Some(30));
+        assert_eq!(options.max_words, None);
+        assert_eq!(options.log_level, LevelFilter::TRACE);
+        assert_eq!(options.log_file, Some(PathBuf::from("run.jsonl")));
+        assert_eq!(options.artifacts_dir, PathBuf::from("failures"));
+    }
+
+    #[test]
+    fn quiet_flags_lower_the_level() {
+        let options = Options::from_args(args(&["-q"])).unwrap();
+        assert_eq!(options.log_level, LevelFilter::WARN);
+
+        let options = Options::from_args(args(&["-q", "-q", "-q", "-q", "-v"])).unwrap();
+        assert_eq!(options.log_level, LevelFilter::ERROR);
+        assert_eq!(Options::default().log_level, LevelFilter::INFO);
+    }
+
+    #[test]
+    fn records_a_single_session() {
+        let options = Options::from_args(args(&["--record", "chimp.ffconcat"])).unwrap();
+        assert_eq!(options.record, Some(PathBuf::from("chimp.ffconcat")));
+        assert_eq!(Options::default().record, None);
+
+        let options = Options::from_args(args(&["--trace", "chimp.jsonl"])).unwrap();
+        assert_eq!(options.trace, Some(PathBuf::from("chimp.jsonl")));
+        assert!(Options::from_args(args(&["--trace", "chimp.jsonl", "--parallel", "2"])).is_err());
+    }
+
+    #[test]
+    fn parses_the_replay_command() {
+        let options = Options::from_args(args(&["replay", "chimp.jsonl"])).unwrap();
+        assert_eq!(
+            options.command,
+            Command::Replay(PathBuf::from("chimp.jsonl"))
+        );
+        assert!(!options.real_time);
+        let options = Options::from_args(args(&["replay", "chimp.jsonl", "--real-time"])).unwrap();
+        assert!(options.real_time);
+        assert!(Options::from_args(args(&["replay"])).is_err());
+    }
+
+    #[test]
+    fn parses_the_request_blocking() {
+        let options = Options::default();
+        assert!(options.block_requests);
+        assert!(options.blocked_urls.is_empty());
+
+        let options = Options::from_args(args(&[
+            "--no-blocking",
+            "--block",
+            "*/ads/*",
+            "--block",
+            "*.gif",
+            "--cookies",
+            "consent.json",
+        ]))
+        .unwrap();
+        assert!(!options.block_requests);
+        assert_eq!(options.blocked_urls, vec!["*/ads/*", "*.gif"]);
+        assert_eq!(options.cookies, Some(PathBuf::from("consent.json")));
+        assert!(Options::from_args(args(&["--block"])).is_err());
+    }
+
+    #[test]
+    fn parses_the_account_options() {
+        let options = Options::from_args(args(&[
+            "--account",
+            "me.json",
+            "--profile",
+            "profile",
+            "--save-score",
+        ]))
+        .unwrap();
+        assert_eq!(options.account_file, Some(PathBuf::from("me.json")));
+        assert_eq!(options.profile_dir, Some(PathBuf::from("profile")));
+        assert!(options.save_score);
+        assert!(!Options::default().save_score);
+
+        let options =
+            Options::from_args(args(&["distributions", "--distributions", "scores.json"])).unwrap();
+        assert_eq!(options.command, Command::Distributions);
+        assert_eq!(options.distributions, PathBuf::from("scores.json"));
+        let options = Options::from_args(args(&["--results", "scores.jsonl"])).unwrap();
+        assert_eq!(options.results, PathBuf::from("scores.jsonl"));
+        assert!(Options::from_args(args(&["--profile", "profile", "--parallel", "2"])).is_err());
+    }
+
+    #[test]
+    fn rejects_invalid_arguments() {
+        assert!(Options::from_args(args(&["--unknown"])).is_err());
+        assert!(Options::from_args(args(&["--idle-timeout"])).is_err());
+        assert!(Options::from_args(args(&["--idle-timeout", "soon"])).is_err());
+        assert!(Options::from_args(args(&["--ready-timeout", "-1"])).is_err());
+        assert!(Options::from_args(args(&["--idle-timeout", "nan"])).is_err());
+        assert!(Options::from_args(args(&["--parallel", "0"])).is_err());
+        assert!(Options::from_args(args(&["--rounds", "0"])).is_err());
+        assert!(Options::from_args(args(&["--word-corpus"])).is_err());
+        assert!(Options::from_args(args(&["--log-file"])).is_err());
+        assert!(
+            Options::from_args(args(&["--record", "run.ffconcat", "--parallel", "2"])).is_err()
+        );
+    }
+}
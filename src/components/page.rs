@@ -0,0 +1,513 @@
+use super::input::{Input, KeyAction, RecordingInput};
+use super::utils::Point;
+use anyhow::Result;
+use headless_chrome::browser::tab::NoElementFound;
+use headless_chrome::{Element, Tab};
+use scraper::{ElementRef, Html, Selector};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::sync::Mutex;
+
+/// Content box of an element in page coordinates
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ContentBox {
+    pub left: f64,
+    pub top: f64,
+    pub right: f64,
+    pub bottom: f64,
+}
+
+impl ContentBox {
+    pub fn center(&self) -> (f64, f64) {
+        (
+            (self.left + self.right) / 2.0,
+            (self.top + self.bottom) / 2.0,
+        )
+    }
+}
+
+/// Position and size of the browser window on the screen
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct WindowBounds {
+    pub left: i32,
+    pub top: i32,
+    pub width: f64,
+    pub height: f64,
+}
+
+/// The page operations the solvers use, so they can run against a browser tab or a fake
+pub trait Page: Send + Sync {
+    /// Wait for an element matching the selector to appear
+    fn wait_for_element(&self, selector: &str) -> Result<Box<dyn PageElement + '_>>;
+
+    /// The first element matching the selector
+    fn find_element(&self, selector: &str) -> Result<Box<dyn PageElement + '_>>;
+
+    /// All elements matching the selector, empty when none match
+    fn find_elements(&self, selector: &str) -> Result<Vec<Box<dyn PageElement + '_>>>;
+
+    /// Position of the browser window on the screen
+    fn bounds(&self) -> Result<WindowBounds>;
+
+    /// Evaluate the script in the page and return its value
+    fn evaluate(&self, script: &str, await_promise: bool) -> Result<Option<Value>>;
+}
+
+/// An element of a [`Page`]
+pub trait PageElement: Send + Sync {
+    /// All descendants matching the selector
+    fn find_elements(&self, selector: &str) -> Result<Vec<Box<dyn PageElement + '_>>>;
+
+    /// Outer HTML of the element
+    fn get_content(&self) -> Result<String>;
+
+    /// Rendered text of the element
+    fn get_inner_text(&self) -> Result<String>;
+
+    /// Content box of the element, in page coordinates
+    fn box_model(&self) -> Result<ContentBox>;
+
+    /// Click the centre of the element
+    fn click(&self) -> Result<()>;
+}
+
+impl<T: PageElement + ?Sized> PageElement for Box<T> {
+    fn find_elements(&self, selector: &str) -> Result<Vec<Box<dyn PageElement + '_>>> {
+        (**self).find_elements(selector)
+    }
+
+    fn get_content(&self) -> Result<String> {
+        (**self).get_content()
+    }
+
+    fn get_inner_text(&self) -> Result<String> {
+        (**self).get_inner_text()
+    }
+
+    fn box_model(&self) -> Result<ContentBox> {
+        (**self).box_model()
+    }
+
+    fn click(&self) -> Result<()> {
+        (**self).click()
+    }
+}
+
+/// headless_chrome reports an empty match as an error, which hides the real failures
+fn none_found<T>(elements: Result<Vec<T>>) -> Result<Vec<T>> {
+    match elements {
+        Err(error) if error.is::<NoElementFound>() => Ok(Vec::new()),
+        elements => elements,
+    }
+}
+
+impl Page for Tab {
+    fn wait_for_element(&self, selector: &str) -> Result<Box<dyn PageElement + '_>> {
+        Ok(Box::new(Tab::wait_for_element(self, selector)?))
+    }
+
+    fn find_element(&self, selector: &str) -> Result<Box<dyn PageElement + '_>> {
+        Ok(Box::new(Tab::find_element(self, selector)?))
+    }
+
+    fn find_elements(&self, selector: &str) -> Result<Vec<Box<dyn PageElement + '_>>> {
+        Ok(none_found(Tab::find_elements(self, selector))?
+            .into_iter()
+            .map(|element| Box::new(element) as Box<dyn PageElement>)
+            .collect())
+    }
+
+    fn bounds(&self) -> Result<WindowBounds> {
+        let bounds = self.get_bounds()?;
+        Ok(WindowBounds {
+            left: bounds.left as i32,
+            top: bounds.top as i32,
+            width: bounds.width,
+            height: bounds.height,
+        })
+    }
+
+    fn evaluate(&self, script: &str, await_promise: bool) -> Result<Option<Value>> {
+        Ok(Tab::evaluate(self, script, await_promise)?.value)
+    }
+}
+
+impl PageElement for Element<'_> {
+    fn find_elements(&self, selector: &str) -> Result<Vec<Box<dyn PageElement + '_>>> {
+        Ok(none_found(Element::find_elements(self, selector))?
+            .into_iter()
+            .map(|element| Box::new(element) as Box<dyn PageElement>)
+            .collect())
+    }
+
+    fn get_content(&self) -> Result<String> {
+        Element::get_content(self)
+    }
+
+    fn get_inner_text(&self) -> Result<String> {
+        Element::get_inner_text(self)
+    }
+
+    fn box_model(&self) -> Result<ContentBox> {
+        let quad = self.get_box_model()?.content;
+        Ok(ContentBox {
+            left: quad.top_left.x,
+            top: quad.top_left.y,
+            right: quad.bottom_right.x,
+            bottom: quad.bottom_right.y,
+        })
+    }
+
+    fn click(&self) -> Result<()> {
+        Element::click(self)?;
+        Ok(())
+    }
+}
+
+/// Answers an evaluated script from the HTML and index of the current snapshot, `None` when
+/// the script is not one it knows
+type ScriptHandler = Box<dyn Fn(&str, &str, usize) -> Option<Value> + Send + Sync>;
+
+/// A snapshot of the page and when the page moves on to the next one
+struct Snapshot {
+    html: String,
+    /// Number of reads after which the page moves on by itself, like a timer on the page
+    reads: Option<usize>,
+}
+
+/// Which snapshot is shown and how often it has been read
+#[derive(Default)]
+struct Cursor {
+    index: usize,
+    reads: usize,
+}
+
+/// A fake page that plays back a sequence of HTML snapshots, for testing solvers offline
+///
+/// The page moves on to the next snapshot on every click and on every batch of typed keys or
+/// text, as the real page reacts to input. The last snapshot stays. Waiting for an element
+/// moves on until a snapshot has it, and snapshots added with [`ScriptedPage::then_for`]
+/// also move on after a number of reads.
+///
+/// The page is its own [`Input`] backend: input is recorded in a [`RecordingInput`], at page
+/// coordinates. Element boxes come from a `data-box="left top right bottom"` attribute, and
+/// `document.body.innerText` is the only script it answers without a handler.
+#[cfg_attr(not(test), allow(dead_code))]
+pub struct ScriptedPage {
+    snapshots: Vec<Snapshot>,
+    cursor: Mutex<Cursor>,
+    handlers: Vec<ScriptHandler>,
+    bounds: WindowBounds,
+    input: RecordingInput,
+}
+
+#[cfg_attr(not(test), allow(dead_code))]
+impl ScriptedPage {
+    pub fn new() -> Self {
+        Self {
+            snapshots: Vec::new(),
+            cursor: Mutex::new(Cursor::default()),
+            handlers: Vec::new(),
+            bounds: WindowBounds {
+                left: 0,
+                top: 0,
+                width: 1280.0,
+                height: 720.0,
+            },
+            input: RecordingInput::new(),
+        }
+    }
+
+    /// Add a snapshot the page leaves on the next input
+    pub fn then(mut self, html: impl Into<String>) -> Self {
+        self.snapshots.push(Snapshot {
+            html: html.into(),
+            reads: None,
+        });
+        self
+    }
+
+    /// Add a snapshot the page leaves on the next input or after it was read `reads` times
+    pub fn then_for(mut self, html: impl Into<String>, reads: usize) -> Self {
+        self.snapshots.push(Snapshot {
+            html: html.into(),
+            reads: Some(reads),
+        });
+        self
+    }
+
+    /// Answer evaluated scripts with the handler, which gets the script and the HTML and
+    /// index of the current snapshot
+    pub fn on_evaluate(
+        mut self,
+        handler: impl Fn(&str, &str, usize) -> Option<Value> + Send + Sync + 'static,
+    ) -> Self {
+        self.handlers.push(Box::new(handler));
+        self
+    }
+
+    pub fn with_bounds(mut self, bounds: WindowBounds) -> Self {
+        self.bounds = bounds;
+        self
+    }
+
+    /// The input sent to the page so far
+    pub fn input(&self) -> &RecordingInput {
+        &self.input
+    }
+
+    /// Index of the snapshot that is shown
+    pub fn index(&self) -> usize {
+        self.cursor.lock().unwrap().index
+    }
+
+    /// Read the current snapshot, moving on when it has been read often enough
+    fn read(&self) -> Result<(usize, String)> {
+        let mut cursor = self.cursor.lock().unwrap();
+        let snapshot = self
+            .snapshots
+            .get(cursor.index)
+            .ok_or_else(|| anyhow::anyhow!("The scripted page has no snapshots"))?;
+        let read = (cursor.index, snapshot.html.clone());
+
+        cursor.reads += 1;
+        if snapshot.reads.is_some_and(|reads| cursor.reads >= reads) {
+            self.advance(&mut cursor);
+        }
+        Ok(read)
+    }
+
+    /// Move on to the next snapshot, `false` when the last one is already shown
+    fn advance(&self, cursor: &mut Cursor) -> bool {
+        if cursor.index + 1 >= self.snapshots.len() {
+            return false;
+        }
+        cursor.index += 1;
+        cursor.reads = 0;
+        true
+    }
+
+    fn react_to_input(&self) {
+        self.advance(&mut self.cursor.lock().unwrap());
+    }
+
+    /// Whether the current snapshot has an element matching the selector
+    fn has_element(&self, selector: &Selector) -> Result<bool> {
+        let (_, html) = self.read()?;
+        Ok(Html::parse_document(&html)
+            .select(selector)
+            .next()
+            .is_some())
+    }
+
+    fn element(&self, path: Vec<(Selector, usize)>) -> Box<dyn PageElement + '_> {
+        Box::new(ScriptedElement { page: self, path })
+    }
+}
+
+impl Default for ScriptedPage {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Page for ScriptedPage {
+    fn wait_for_element(&self, selector: &str) -> Result<Box<dyn PageElement + '_>> {
+        let parsed = parse_selector(selector)?;
+        loop {
+            let (index, html) = self.read()?;
+            if Html::parse_document(&html).select(&parsed).next().is_some() {
+                return Ok(self.element(vec![(parsed, 0)]));
+            }
+
+            // Move on, unless the read already did
+            let mut cursor = self.cursor.lock().unwrap();
+            if cursor.index == index && !self.advance(&mut cursor) {
+                anyhow::bail!("No snapshot has an element matching {}", selector);
+            }
+        }
+    }
+
+    fn find_element(&self, selector: &str) -> Result<Box<dyn PageElement + '_>> {
+        let parsed = parse_selector(selector)?;
+        if !self.has_element(&parsed)? {
+            anyhow::bail!("No element matches {}", selector);
+        }
+        Ok(self.element(vec![(parsed, 0)]))
+    }
+
+    fn find_elements(&self, selector: &str) -> Result<Vec<Box<dyn PageElement + '_>>> {
+        let parsed = parse_selector(selector)?;
+        let (_, html) = self.read()?;
+        let count = Html::parse_document(&html).select(&parsed).count();
+        Ok((0..count)
+            .map(|index| self.element(vec![(parsed.clone(), index)]))
+            .collect())
+    }
+
+    fn bounds(&self) -> Result<WindowBounds> {
+        Ok(self.bounds)
+    }
+
+    fn evaluate(&self, script: &str, _await_promise: bool) -> Result<Option<Value>> {
+        let (index, html) = self.read()?;
+        if script == "document.body.innerText" {
+            let document = Html::parse_document(&html);
+            return Ok(Some(Value::String(inner_text(document.root_element()))));
+        }
+
+        self.handlers
+            .iter()
+            .find_map(|handler| handler(script, &html, index))
+            .map(Some)
+            .ok_or_else(|| anyhow::anyhow!("No scripted result for: {}", script.trim()))
+    }
+}
+
+impl Input for ScriptedPage {
+    fn move_to(&self, point: &Point) -> Result<()> {
+        self.input.move_to(point)
+    }
+
+    fn click(&self, point: &Point) -> Result<()> {
+        self.input.click(point)?;
+        self.react_to_input();
+        Ok(())
+    }
+
+    fn send_keys(&self, actions: &[KeyAction]) -> Result<()> {
+        self.input.send_keys(actions)?;
+        self.react_to_input();
+        Ok(())
+    }
+
+    fn insert_text(&self, text: &str) -> Result<()> {
+        self.input.insert_text(text)?;
+        self.react_to_input();
+        Ok(())
+    }
+}
+
+/// An element of a [`ScriptedPage`], found again in the current snapshot on every call so it
+/// follows the page like a live element
+struct ScriptedElement<'a> {
+    page: &'a ScriptedPage,
+    /// Selectors and match index from the document down to the element
+    path: Vec<(Selector, usize)>,
+}
+
+impl ScriptedElement<'_> {
+    /// Find the element in the current snapshot and run `f` on it
+    fn with_element<T>(&self, f: impl FnOnce(ElementRef) -> Result<T>) -> Result<T> {
+        let (_, html) = self.page.read()?;
+        let document = Html::parse_document(&html);
+        let mut element = document.root_element();
+        for (selector, index) in &self.path {
+            element = element
+                .select(selector)
+                .nth(*index)
+                .ok_or_else(|| anyhow::anyhow!("The element is no longer on the page"))?;
+        }
+        f(element)
+    }
+}
+
+impl PageElement for ScriptedElement<'_> {
+    fn find_elements(&self, selector: &str) -> Result<Vec<Box<dyn PageElement + '_>>> {
+        let parsed = parse_selector(selector)?;
+        let count = self.with_element(|element| Ok(element.select(&parsed).count()))?;
+        Ok((0..count)
+            .map(|index| {
+                let mut path = self.path.clone();
+                path.push((parsed.clone(), index));
+                self.page.element(path)
+            })
+            .collect())
+    }
+
+    fn get_content(&self) -> Result<String> {
+        self.with_element(|element| Ok(element.html()))
+    }
+
+    fn get_inner_text(&self) -> Result<String> {
+        self.with_element(|element| Ok(inner_text(element)))
+    }
+
+    fn box_model(&self) -> Result<ContentBox> {
+        self.with_element(|element| {
+            let data_box = element
+                .value()
+                .attr("data-box")
+                .ok_or_else(|| anyhow::anyhow!("The element has no data-box attribute"))?;
+            let values = data_box
+                .split_whitespace()
+                .map(str::parse)
+                .collect::<Result<Vec<f64>, _>>()?;
+            let [left, top, right, bottom] = values[..] else {
+                anyhow::bail!("data-box needs four values, got {}", data_box);
+            };
+            Ok(ContentBox {
+                left,
+                top,
+                right,
+                bottom,
+            })
+        })
+    }
+
+    /// Clicking an element only moves the page on, it is not recorded as input
+    fn click(&self) -> Result<()> {
+        self.with_element(|_| Ok(()))?;
+        self.page.react_to_input();
+        Ok(())
+    }
+}
+
+fn parse_selector(selector: &str) -> Result<Selector> {
+    Selector::parse(selector).map_err(|e| anyhow::anyhow!("Invalid selector {}: {}", selector, e))
+}
+
+/// The text of the element with every text node on its own line, close to the rendered text
+fn inner_text(element: ElementRef) -> String {
+    element
+        .text()
+        .map(str::trim)
+        .filter(|text| !text.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn plays_back_snapshots_on_input() {
+        let page = ScriptedPage::new()
+            .then(r#"<button class="start" data-box="10 20 30 40">Start</button>"#)
+            .then_for("<p>Loading</p>", 2)
+            .then(r#"<div class="word">bishop</div>"#)
+            .on_evaluate(|script, _, index| (script == "index").then(|| Value::from(index)));
+
+        let start = page.find_element(".start").unwrap();
+        assert_eq!(start.get_inner_text().unwrap(), "Start");
+        assert_eq!(start.box_model().unwrap().center(), (20.0, 30.0));
+        start.click().unwrap();
+        assert_eq!(page.index(), 1);
+        assert!(start.get_content().is_err());
+
+        // The loading screen moves on by itself, waiting skips ahead as well
+        let word = page.wait_for_element(".word").unwrap();
+        assert_eq!(word.get_inner_text().unwrap(), "bishop");
+        assert_eq!(page.evaluate("index", false).unwrap(), Some(Value::from(2)));
+        assert_eq!(
+            page.evaluate("document.body.innerText", false).unwrap(),
+            Some(Value::from("bishop"))
+        );
+        assert!(page.evaluate("unknown()", false).is_err());
+
+        page.click(&Point { x: 1, y: 2 }).unwrap();
+        assert_eq!(page.index(), 2);
+        assert_eq!(page.input().clicks(), vec![Point { x: 1, y: 2 }]);
+    }
+}
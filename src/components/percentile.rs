@@ -0,0 +1,294 @@
+use super::options::options;
+use super::results::{self, TestResult};
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+use std::sync::OnceLock;
+use tracing::{info, warn};
+
+/// Version of the distribution file format, files of other versions are not read
+pub const DISTRIBUTIONS_VERSION: u32 = 1;
+
+static DISTRIBUTIONS: OnceLock<Distributions> = OnceLock::new();
+
+/// How the scores of all users of a test are distributed
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Distribution {
+    pub unit: String,
+    /// Whether a lower score is better, like a reaction time
+    pub lower_is_better: bool,
+    /// Scores with the percentage of users that scored lower, by increasing score
+    pub cdf: Vec<(f64, f64)>,
+}
+
+impl Distribution {
+    /// Percentage of users the score is better than, interpolated between the points of the
+    /// distribution and clamped to its ends
+    pub fn percentile(&self, score: f64) -> f64 {
+        let lower = match self.cdf.iter().position(|(at, _)| *at >= score) {
+            Some(0) => self.cdf[0].1,
+            Some(index) => {
+                let (low_score, low_share) = self.cdf[index - 1];
+                let (high_score, high_share) = self.cdf[index];
+                low_share
+                    + (high_share - low_share) * (score - low_score) / (high_score - low_score)
+            }
+            None => self.cdf[self.cdf.len() - 1].1,
+        };
+
+        if self.lower_is_better {
+            100.0 - lower
+        } else {
+            lower
+        }
+    }
+
+    /// Check that the points go up, so the interpolation holds
+    fn validate(&self) -> Result<()> {
+        if self.cdf.is_empty() {
+            anyhow::bail!("The distribution has no points");
+        }
+        for pair in self.cdf.windows(2) {
+            let [(low_score, low_share), (high_score, high_share)] = [pair[0], pair[1]];
+            if high_score <= low_score || high_share < low_share {
+                anyhow::bail!(
+                    "The points ({}, {}) and ({}, {}) are not in increasing order",
+                    low_score,
+                    low_share,
+                    high_score,
+                    high_share
+                );
+            }
+        }
+        if self
+            .cdf
+            .iter()
+            .any(|(_, share)| !(0.0..=100.0).contains(share))
+        {
+            anyhow::bail!("A share of users is not between 0 and 100");
+        }
+        Ok(())
+    }
+}
+
+/// The score distributions of the tests, from a versioned file so percentiles are computed
+/// offline
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Distributions {
+    pub version: u32,
+    /// Where the distributions come from
+    pub source: String,
+    /// Whether the distributions are approximations instead of the data of the site, so the
+    /// percentiles are estimates
+    #[serde(default)]
+    pub estimated: bool,
+    /// Distribution per test, by the name of the test in the logs
+    pub tests: HashMap<String, Distribution>,
+}
+
+impl Distributions {
+    /// No distributions, so no test has a percentile
+    pub fn empty() -> Self {
+        Self {
+            version: DISTRIBUTIONS_VERSION,
+            source: String::new(),
+            estimated: false,
+            tests: HashMap::new(),
+        }
+    }
+
+    /// Read the distributions from a file
+    ///
+    /// ## Errors
+    /// Returns an error when the file cannot be read, has another version or a distribution
+    /// that does not go up
+    pub fn load(path: &Path) -> Result<Self> {
+        let json = fs::read_to_string(path)
+            .with_context(|| format!("Could not read the distributions {}", path.display()))?;
+        Self::parse(&json).with_context(|| format!("Invalid distributions in {}", path.display()))
+    }
+
+    fn parse(json: &str) -> Result<Self> {
+        let distributions: Distributions = serde_json::from_str(json)?;
+        if distributions.version != DISTRIBUTIONS_VERSION {
+            anyhow::bail!(
+                "The distributions have version {}, only version {} is supported",
+                distributions.version,
+                DISTRIBUTIONS_VERSION
+            );
+        }
+        for (test, distribution) in &distributions.tests {
+            distribution
+                .validate()
+                .with_context(|| format!("Invalid distribution of {}", test))?;
+        }
+        Ok(distributions)
+    }
+
+    /// Write the distributions to a file, in the format [`Distributions::load`] reads
+    ///
+    /// ## Errors
+    /// Returns an error when the file cannot be written
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let json = serde_json::to_string_pretty(self)?;
+        fs::write(path, json + "\n")
+            .with_context(|| format!("Could not write the distributions {}", path.display()))
+    }
+
+    /// Percentage of users the score of the test is better than, `None` for tests without a
+    /// distribution
+    pub fn percentile(&self, test: &str, score: f64) -> Option<f64> {
+        self.tests
+            .get(test)
+            .map(|distribution| distribution.percentile(score))
+    }
+
+    /// How the percentile reads in a message, marked as an estimate when the distributions
+    /// are approximations
+    pub fn describe(&self, percentile: f64) -> String {
+        match self.estimated {
+            true => format!(
+                "better than about {:.1}% of users (estimate from approximate distributions)",
+                percentile
+            ),
+            false => format!("better than {:.1}% of users", percentile),
+        }
+    }
+}
+
+/// The distributions of the `--distributions` file, or none when there is no such file or
+/// it cannot be read
+pub fn distributions() -> &'static Distributions {
+    DISTRIBUTIONS.get_or_init(|| {
+        let path = &options().distributions;
+        if !path.exists() {
+            info!(
+                "📈 No percentiles without the score distributions in {}, `human_benchmark distributions` reads them from the site",
+                path.display()
+            );
+            return Distributions::empty();
+        }
+        Distributions::load(path).unwrap_or_else(|e| {
+            warn!("No percentiles: {:#}", e);
+            Distributions::empty()
+        })
+    })
+}
+
+/// Log how the score of the test compares to all users, when its distribution is known,
+/// and add the score with its percentile to the results store
+///
+/// The percentile is also a field of the message, so the log file keeps it with the score.
+/// It is not saved to the account with `--save-score`: the site saves the score of its result
+/// screen and computes its own statistics.
+pub fn report(test: &str, score: f64) {
+    let distributions = distributions();
+    let percentile = distributions.percentile(test, score);
+    results::record(&TestResult::new(
+        test,
+        score,
+        percentile,
+        distributions.estimated,
+    ));
+
+    let (Some(distribution), Some(percentile)) = (distributions.tests.get(test), percentile) else {
+        return;
+    };
+    info!(
+        benchmark = test,
+        score,
+        percentile,
+        estimated = distributions.estimated,
+        distributions = distributions.version,
+        "📈 {} {} is {}",
+        score,
+        distribution.unit,
+        distributions.describe(percentile)
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn distribution(lower_is_better: bool) -> Distribution {
+        Distribution {
+            unit: "ms".to_string(),
+            lower_is_better,
+            cdf: vec![(200.0, 10.0), (300.0, 50.0), (400.0, 90.0)],
+        }
+    }
+
+    #[test]
+    fn interpolates_between_the_points() {
+        let higher_is_better = distribution(false);
+        assert_eq!(higher_is_better.percentile(300.0), 50.0);
+        assert_eq!(higher_is_better.percentile(250.0), 30.0);
+        assert_eq!(higher_is_better.percentile(100.0), 10.0);
+        assert_eq!(higher_is_better.percentile(1000.0), 90.0);
+
+        let lower_is_better = distribution(true);
+        assert_eq!(lower_is_better.percentile(250.0), 70.0);
+        assert_eq!(lower_is_better.percentile(1000.0), 10.0);
+    }
+
+    #[test]
+    fn writes_the_distributions_it_reads() {
+        let mut distributions = Distributions::empty();
+        assert_eq!(distributions.percentile("reaction_time", 250.0), None);
+
+        distributions.source = "Charts of the test pages".to_string();
+        distributions
+            .tests
+            .insert("reaction_time".to_string(), distribution(true));
+        let path = std::env::temp_dir().join(format!(
+            "human_benchmark_distributions_{}.json",
+            std::process::id()
+        ));
+        distributions.save(&path).unwrap();
+        let loaded = Distributions::load(&path);
+        fs::remove_file(&path).unwrap();
+
+        let loaded = loaded.unwrap();
+        assert_eq!(loaded, distributions);
+        assert_eq!(loaded.percentile("reaction_time", 250.0), Some(70.0));
+    }
+
+    #[test]
+    fn marks_estimated_percentiles() {
+        let mut distributions =
+            Distributions::parse(r#"{"version": 1, "source": "", "tests": {}}"#).unwrap();
+        assert!(!distributions.estimated);
+        assert_eq!(distributions.describe(42.25), "better than 42.2% of users");
+
+        distributions.estimated = true;
+        assert_eq!(
+            distributions.describe(42.25),
+            "better than about 42.2% of users (estimate from approximate distributions)"
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_distributions() {
+        let error = Distributions::parse(r#"{"version": 2, "source": "", "tests": {}}"#)
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("version 2"), "{}", error);
+
+        let error = Distributions::parse(
+            r#"{"version": 1, "source": "", "tests": {
+                "typing": {"unit": "wpm", "lower_is_better": false, "cdf": [[50, 70], [40, 50]]}
+            }}"#,
+        )
+        .unwrap_err();
+        assert_eq!(error.to_string(), "Invalid distribution of typing");
+        assert!(Distributions::parse(
+            r#"{"version": 1, "source": "", "tests": {
+                "typing": {"unit": "wpm", "lower_is_better": false, "cdf": [[40, 150]]}
+            }}"#
+        )
+        .is_err());
+    }
+}
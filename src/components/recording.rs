@@ -0,0 +1,221 @@
+use anyhow::{Context, Result};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use headless_chrome::browser::tab::EventListener;
+use headless_chrome::protocol::cdp::{types::Event, Page::StartScreencastFormatOption};
+use headless_chrome::Tab;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, Weak};
+use std::time::{SystemTime, UNIX_EPOCH};
+use tracing::{info, warn};
+
+/// A frame of the screencast, saved as a PNG file in the frames directory
+#[derive(Debug, Clone, PartialEq)]
+struct Frame {
+    file: String,
+    /// Seconds since the Unix epoch at which the page was drawn like this
+    timestamp: f64,
+}
+
+/// Records a tab with the screencast of the DevTools Protocol, for showcases and bug reports
+///
+/// Chrome sends a frame every time the page is drawn. Every frame is saved as a numbered PNG
+/// file in a directory next to the manifest, named after it with `_frames` added. When the
+/// recording finishes, the manifest gets how long every frame was shown, in the ffconcat
+/// format, so ffmpeg can encode it into a video as it is:
+/// `ffmpeg -f concat -i chimp.ffconcat -vsync vfr -pix_fmt yuv420p chimp.mp4`
+pub struct Recording {
+    tab: Arc<Tab>,
+    listener: Weak<dyn EventListener<Event> + Send + Sync>,
+    manifest: PathBuf,
+    frames_dir: PathBuf,
+    frames: Arc<Mutex<Vec<Frame>>>,
+}
+
+impl Recording {
+    /// Start recording the tab, an earlier recording to the same manifest is replaced
+    pub fn start(tab: &Arc<Tab>, manifest: &Path) -> Result<Self> {
+        let frames_dir = frames_dir(manifest);
+        if frames_dir.exists() {
+            fs::remove_dir_all(&frames_dir)
+                .with_context(|| format!("Could not clear {}", frames_dir.display()))?;
+        }
+        fs::create_dir_all(&frames_dir)
+            .with_context(|| format!("Could not create {}", frames_dir.display()))?;
+
+        let frames = Arc::new(Mutex::new(Vec::new()));
+        let listener = {
+            // The tab keeps its listeners, so the listener must not keep the tab
+            let tab = Arc::downgrade(tab);
+            let frames = frames.clone();
+            let frames_dir = frames_dir.clone();
+            Arc::new(move |event: &Event| {
+                let Event::PageScreencastFrame(frame) = event else {
+                    return;
+                };
+                let params = &frame.params;
+
+                // Chrome only sends the next frame once this one is acknowledged
+                if let Some(tab) = tab.upgrade() {
+                    let _ = tab.ack_screencast(params.session_id);
+                }
+
+                let mut frames = frames.lock().unwrap();
+                let file = format!("{:06}.png", frames.len() + 1);
+                match save_frame(&frames_dir.join(&file), &params.data) {
+                    Ok(()) => frames.push(Frame {
+                        file,
+                        timestamp: params.metadata.timestamp.unwrap_or_else(now),
+                    }),
+                    Err(e) => warn!("Could not save screencast frame {}: {:#}", file, e),
+                }
+            })
+        };
+        let listener = tab.add_event_listener(listener)?;
+        tab.start_screencast(
+            Some(StartScreencastFormatOption::Png),
+            None,
+            None,
+            None,
+            None,
+        )?;
+
+        info!("🎥 Recording the screencast to {}", manifest.display());
+        Ok(Self {
+            tab: tab.clone(),
+            listener,
+            manifest: manifest.to_path_buf(),
+            frames_dir,
+            frames,
+        })
+    }
+
+    /// Stop recording and write the manifest with the timing of the frames
+    ///
+    /// ## Returns
+    /// Returns the path of the manifest
+    pub fn finish(self) -> Result<PathBuf> {
+        let end = now();
+
+        // The tab can already be gone when the benchmark failed, the frames are still kept
+        let _ = self.tab.stop_screencast();
+        self.tab.remove_event_listener(&self.listener)?;
+
+        let frames = self.frames.lock().unwrap();
+        let frames_dir_name = self
+            .frames_dir
+            .file_name()
+            .map(|name| name.to_string_lossy().to_string())
+            .unwrap_or_default();
+        fs::write(&self.manifest, manifest(&frames, &frames_dir_name, end))
+            .with_context(|| format!("Could not write {}", self.manifest.display()))?;
+
+        info!(
+            "🎥 Recorded {} frames to {}",
+            frames.len(),
+            self.frames_dir.display()
+        );
+        Ok(self.manifest.clone())
+    }
+}
+
+/// The directory with the frames of the manifest, next to it
+fn frames_dir(manifest: &Path) -> PathBuf {
+    let stem = manifest
+        .file_stem()
+        .map(|stem| stem.to_string_lossy().to_string())
+        .unwrap_or_else(|| "recording".to_string());
+    manifest.with_file_name(format!("{}_frames", stem))
+}
+
+/// Decode the base64 PNG data of a frame and write it to the file
+fn save_frame(path: &Path, data: &str) -> Result<()> {
+    let png = STANDARD.decode(data)?;
+    fs::write(path, png)?;
+    Ok(())
+}
+
+/// The ffconcat manifest, showing every frame until the next one and the last one until the end
+///
+/// The last frame is listed twice, ffmpeg ignores the duration of the final entry.
+fn manifest(frames: &[Frame], frames_dir_name: &str, end: f64) -> String {
+    let start = frames.first().map_or(end, |frame| frame.timestamp);
+    let mut manifest = format!(
+        "ffconcat version 1.0\n# {} frames over {:.3} seconds\n",
+        frames.len(),
+        (end - start).max(0.0)
+    );
+
+    for (index, frame) in frames.iter().enumerate() {
+        let next = frames.get(index + 1).map_or(end, |next| next.timestamp);
+        manifest.push_str(&format!(
+            "# {:.3}s\nfile '{}/{}'\nduration {:.6}\n",
+            frame.timestamp - start,
+            frames_dir_name,
+            frame.file,
+            (next - frame.timestamp).max(0.0)
+        ));
+    }
+    if let Some(last) = frames.last() {
+        manifest.push_str(&format!("file '{}/{}'\n", frames_dir_name, last.file));
+    }
+
+    manifest
+}
+
+/// Seconds since the Unix epoch, like the timestamps of the frames
+fn now() -> f64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0.0, |since| since.as_secs_f64())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(file: &str, timestamp: f64) -> Frame {
+        Frame {
+            file: file.to_string(),
+            timestamp,
+        }
+    }
+
+    #[test]
+    fn names_the_frames_directory_after_the_manifest() {
+        assert_eq!(
+            frames_dir(Path::new("media/chimp.ffconcat")),
+            PathBuf::from("media/chimp_frames")
+        );
+    }
+
+    #[test]
+    fn shows_every_frame_until_the_next_one() {
+        let frames = [
+            frame("000001.png", 100.0),
+            frame("000002.png", 100.5),
+            frame("000003.png", 100.75),
+        ];
+
+        assert_eq!(
+            manifest(&frames, "chimp_frames", 101.0),
+            "ffconcat version 1.0
+# 3 frames over 1.000 seconds
+# 0.000s
+file 'chimp_frames/000001.png'
+duration 0.500000
+# 0.500s
+file 'chimp_frames/000002.png'
+duration 0.250000
+# 0.750s
+file 'chimp_frames/000003.png'
+duration 0.250000
+file 'chimp_frames/000003.png'
+"
+        );
+        assert_eq!(
+            manifest(&[], "chimp_frames", 101.0),
+            "ffconcat version 1.0\n# 0 frames over 0.000 seconds\n"
+        );
+    }
+}
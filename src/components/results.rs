@@ -0,0 +1,94 @@
+use super::artifacts::utc_date_time;
+use super::options::options;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::Path;
+use std::time::SystemTime;
+use tracing::{debug, warn};
+
+/// The score of a test run, as a line of the results store
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TestResult {
+    /// UTC time of the result, like `2026-10-18T14:03:07Z`
+    pub time: String,
+    /// Name of the test in the logs
+    pub benchmark: String,
+    pub score: f64,
+    /// Percentage of users the score is better than, `None` without a distribution of the test
+    pub percentile: Option<f64>,
+    /// Whether the percentile comes from approximate distributions
+    pub estimated: bool,
+}
+
+impl TestResult {
+    /// The result of the test at the current time
+    pub fn new(benchmark: &str, score: f64, percentile: Option<f64>, estimated: bool) -> Self {
+        let (date, clock) = utc_date_time(SystemTime::now());
+        Self {
+            time: format!("{}T{}Z", date, clock),
+            benchmark: benchmark.to_string(),
+            score,
+            percentile,
+            estimated,
+        }
+    }
+}
+
+/// Add the result to the results store of `--results`
+///
+/// A result that cannot be stored is only a warning, the run itself went well.
+pub fn record(result: &TestResult) {
+    let path = &options().results;
+    match append(path, result) {
+        Ok(()) => debug!(
+            "Added the result of {} to {}",
+            result.benchmark,
+            path.display()
+        ),
+        Err(e) => warn!(
+            "Could not store the result of {}: {:#}",
+            result.benchmark, e
+        ),
+    }
+}
+
+/// Append the result to the file as a JSON line, creating the file when needed
+fn append(path: &Path, result: &TestResult) -> Result<()> {
+    let mut file = OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .with_context(|| format!("Could not open the results {}", path.display()))?;
+    writeln!(file, "{}", serde_json::to_string(result)?)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn appends_the_results_as_json_lines() {
+        let path = std::env::temp_dir().join(format!(
+            "human_benchmark_results_{}.jsonl",
+            std::process::id()
+        ));
+        let typing = TestResult::new("typing", 87.0, Some(96.5), false);
+        let aim = TestResult::new("aim_trainer", 512.0, None, false);
+        append(&path, &typing).unwrap();
+        append(&path, &aim).unwrap();
+        let lines = fs::read_to_string(&path);
+        fs::remove_file(&path).unwrap();
+
+        let results: Vec<TestResult> = lines
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect();
+        assert_eq!(results, vec![typing, aim]);
+        assert!(results[0].time.ends_with('Z'));
+    }
+}
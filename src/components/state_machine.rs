@@ -0,0 +1,616 @@
+use super::page::Page;
+use super::utils::is_kill_switch_pressed;
+use anyhow::Result;
+use scraper::{Html, Selector};
+#[cfg(test)]
+use serde_json::Value;
+use std::fmt::Debug;
+use std::time::{Duration, Instant};
+use tracing::{debug, warn};
+
+/// A rule that decides whether a page snapshot is in a certain state
+#[derive(Debug, Clone)]
+pub enum Detection {
+    /// An element matching the selector is present
+    SelectorPresent(String),
+    /// The text of an element matching the selector contains the text
+    TextContains { selector: String, text: String },
+    /// An attribute of an element matching the selector has the value
+    AttributeEquals {
+        selector: String,
+        attribute: String,
+        value: String,
+    },
+    /// All of the rules match
+    All(Vec<Detection>),
+    /// Any of the rules matches
+    Any(Vec<Detection>),
+    /// The rule does not match
+    Not(Box<Detection>),
+}
+
+impl Detection {
+    pub fn selector(selector: &str) -> Self {
+        Detection::SelectorPresent(selector.to_string())
+    }
+
+    pub fn text(selector: &str, text: &str) -> Self {
+        Detection::TextContains {
+            selector: selector.to_string(),
+            text: text.to_string(),
+        }
+    }
+
+    pub fn attribute(selector: &str, attribute: &str, value: &str) -> Self {
+        Detection::AttributeEquals {
+            selector: selector.to_string(),
+            attribute: attribute.to_string(),
+            value: value.to_string(),
+        }
+    }
+
+    /// Check the rule against a parsed page snapshot
+    ///
+    /// Invalid selectors never match.
+    pub fn matches(&self, document: &Html) -> bool {
+        match self {
+            Detection::SelectorPresent(selector) => select(document, selector, |_| true),
+            Detection::TextContains { selector, text } => select(document, selector, |element| {
+                element.text().collect::<String>().contains(text.as_str())
+            }),
+            Detection::AttributeEquals {
+                selector,
+                attribute,
+                value,
+            } => select(document, selector, |element| {
+                element.value().attr(attribute) == Some(value.as_str())
+            }),
+            Detection::All(rules) => rules.iter().all(|rule| rule.matches(document)),
+            Detection::Any(rules) => rules.iter().any(|rule| rule.matches(document)),
+            Detection::Not(rule) => !rule.matches(document),
+        }
+    }
+
+    /// The rule as a JavaScript expression that checks it in the page, with the `select`
+    /// helper of the detection script
+    fn to_script(&self) -> String {
+        match self {
+            Detection::SelectorPresent(selector) => {
+                format!("select({}).length > 0", js_string(selector))
+            }
+            Detection::TextContains { selector, text } => format!(
+                "select({}).some((element) => element.textContent.includes({}))",
+                js_string(selector),
+                js_string(text)
+            ),
+            Detection::AttributeEquals {
+                selector,
+                attribute,
+                value,
+            } => format!(
+                "select({}).some((element) => element.getAttribute({}) === {})",
+                js_string(selector),
+                js_string(attribute),
+                js_string(value)
+            ),
+            Detection::All(rules) => join_scripts(rules, " && ", "true"),
+            Detection::Any(rules) => join_scripts(rules, " || ", "false"),
+            Detection::Not(rule) => format!("!({})", rule.to_script()),
+        }
+    }
+}
+
+/// The rules joined with the operator, `empty` when there are none
+fn join_scripts(rules: &[Detection], operator: &str, empty: &str) -> String {
+    if rules.is_empty() {
+        return empty.to_string();
+    }
+    let scripts: Vec<String> = rules.iter().map(Detection::to_script).collect();
+    format!("({})", scripts.join(operator))
+}
+
+/// The text as a JavaScript string literal
+fn js_string(text: &str) -> String {
+    serde_json::Value::from(text).to_string()
+}
+
+/// Script that checks the rules of the states in order and returns the index of the first
+/// one that matches, `-1` when none does
+///
+/// Invalid selectors never match, like with [`Detection::matches`].
+fn detection_script(rules: &[Detection]) -> String {
+    let rules: Vec<String> = rules
+        .iter()
+        .map(|rule| format!("() => {}", rule.to_script()))
+        .collect();
+    format!(
+        r#"(() => {{
+    const select = (selector) => {{
+        try {{
+            return Array.from(document.querySelectorAll(selector));
+        }} catch (e) {{
+            return [];
+        }}
+    }};
+    return [{}].findIndex((rule) => rule());
+}})()"#,
+        rules.join(", ")
+    )
+}
+
+/// Check whether any element matching the selector passes the predicate
+fn select(
+    document: &Html,
+    selector: &str,
+    predicate: impl Fn(&scraper::ElementRef) -> bool,
+) -> bool {
+    match Selector::parse(selector) {
+        Ok(selector) => document
+            .select(&selector)
+            .any(|element| predicate(&element)),
+        Err(_) => false,
+    }
+}
+
+/// How a wait for the page to change ended
+enum Wait<S> {
+    Changed(Option<S>),
+    /// The kill switch was pressed
+    Stopped,
+}
+
+/// What the state machine does after handling a state
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Step {
+    /// Wait for the page to move to the next state
+    Continue,
+    /// Stop the state machine
+    Stop,
+}
+
+/// A change of state observed by the state machine
+#[derive(Debug, Clone, PartialEq)]
+pub struct TraceEntry<S> {
+    /// Time since the state machine started
+    pub elapsed: Duration,
+    pub from: Option<S>,
+    pub to: Option<S>,
+    /// Whether the transition was declared, always `true` when no transitions are declared
+    pub expected: bool,
+}
+
+/// A state machine that follows the states of a benchmark page
+///
+/// Every state is declared with the rule that detects it, in order of priority: the first
+/// state whose rule matches the page snapshot is the current state. The machine polls the
+/// page, calls the handler for every state it enters and then waits for the page to leave
+/// that state. When the page stays in a state for longer than its timeout, the handler is
+/// called again, up to the configured number of retries.
+///
+/// The page is polled with a single evaluate that checks the rules in the page and only
+/// returns the index of the state, so a poll does not transfer or parse the page HTML.
+///
+/// Every benchmark follows its screens with one. A handler can stay in its state for a while,
+/// like Verbal Memory answering the words of its single game screen, and returns once the
+/// page is about to leave it. Pressing the kill switch stops the machine while it waits.
+pub struct StateMachine<S> {
+    name: String,
+    states: Vec<(S, Detection, Option<Duration>)>,
+    /// The detection script of the states, rebuilt when a state is declared
+    script: String,
+    transitions: Vec<(S, S)>,
+    timeout: Duration,
+    retries: u32,
+    poll_interval: Duration,
+    trace: Vec<TraceEntry<S>>,
+}
+
+impl<S: Copy + PartialEq + Debug> StateMachine<S> {
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            states: Vec::new(),
+            script: detection_script(&[]),
+            transitions: Vec::new(),
+            timeout: Duration::from_secs(10),
+            retries: 2,
+            poll_interval: Duration::from_millis(5),
+            trace: Vec::new(),
+        }
+    }
+
+    /// Declare a state and the rule that detects it
+    pub fn state(self, state: S, detection: Detection) -> Self {
+        self.push_state(state, detection, None)
+    }
+
+    /// Declare a state that may last longer (or shorter) than the default timeout
+    pub fn state_with_timeout(self, state: S, detection: Detection, timeout: Duration) -> Self {
+        self.push_state(state, detection, Some(timeout))
+    }
+
+    fn push_state(mut self, state: S, detection: Detection, timeout: Option<Duration>) -> Self {
+        self.states.push((state, detection, timeout));
+        self.script = detection_script(&self.rules());
+        self
+    }
+
+    fn rules(&self) -> Vec<Detection> {
+        self.states
+            .iter()
+            .map(|(_, detection, _)| detection.clone())
+            .collect()
+    }
+
+    /// Declare an expected transition. Once any transition is declared, undeclared ones
+    /// are marked as unexpected in the trace.
+    pub fn transition(mut self, from: S, to: S) -> Self {
+        self.transitions.push((from, to));
+        self
+    }
+
+    /// Set the default time the page may stay in a state before the handler is retried
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Set how often the handler is retried when the page stays in a state
+    pub fn retries(mut self, retries: u32) -> Self {
+        self.retries = retries;
+        self
+    }
+
+    /// Set the time between two page snapshots
+    pub fn poll_interval(mut self, poll_interval: Duration) -> Self {
+        self.poll_interval = poll_interval;
+        self
+    }
+
+    /// Detect the state of a page snapshot, like a saved fixture
+    pub fn detect(&self, html_content: &str) -> Option<S> {
+        let document = Html::parse_document(html_content);
+        self.states
+            .iter()
+            .find(|(_, detection, _)| detection.matches(&document))
+            .map(|(state, _, _)| *state)
+    }
+
+    /// Detect the state of the page with a single evaluate of the detection script
+    pub fn detect_in(&self, page: &dyn Page) -> Result<Option<S>> {
+        let index = page
+            .evaluate(&self.script, false)?
+            .and_then(|value| value.as_i64())
+            .ok_or_else(|| {
+                anyhow::anyhow!("[{}] The detection script returned no state", self.name)
+            })?;
+        Ok(usize::try_from(index)
+            .ok()
+            .and_then(|index| self.states.get(index))
+            .map(|(state, _, _)| *state))
+    }
+
+    /// Answer the detection script on a scripted page by checking the rules against the HTML
+    /// of its snapshot, as the page would
+    #[cfg(test)]
+    pub fn answer_detection(&self) -> impl Fn(&str, &str, usize) -> Option<Value> + Send + Sync {
+        let script = self.script.clone();
+        let rules = self.rules();
+        move |evaluated, html, _| {
+            if evaluated != script {
+                return None;
+            }
+            let document = Html::parse_document(html);
+            let index = rules
+                .iter()
+                .position(|rule| rule.matches(&document))
+                .map_or(-1, |index| index as i64);
+            Some(Value::from(index))
+        }
+    }
+
+    /// The state transitions observed so far
+    pub fn trace(&self) -> &[TraceEntry<S>] {
+        &self.trace
+    }
+
+    /// Run the state machine on the page until the handler stops it
+    ///
+    /// `handle` is called with the state and the attempt number, starting at 0, so it can
+    /// tell a retry from entering the state. Polling blocks the calling thread, so async
+    /// callers run it inside `run_blocking`.
+    pub fn run(
+        &mut self,
+        page: &dyn Page,
+        mut handle: impl FnMut(S, u32) -> Result<Step>,
+    ) -> Result<()> {
+        let start_time = Instant::now();
+        let mut current = self.observe(page, None, start_time)?;
+
+        loop {
+            // Wait for a recognized state
+            let Some(state) = current else {
+                match self.wait_for_change(page, None, start_time)? {
+                    Wait::Changed(next) => current = next,
+                    Wait::Stopped => return Ok(()),
+                }
+                continue;
+            };
+
+            let mut attempt = 0;
+            loop {
+                if handle(state, attempt)? == Step::Stop {
+                    return Ok(());
+                }
+
+                match self.wait_for_change(page, Some(state), start_time) {
+                    Ok(Wait::Changed(next)) => {
+                        current = next;
+                        break;
+                    }
+                    Ok(Wait::Stopped) => return Ok(()),
+                    Err(e) if attempt >= self.retries => return Err(e),
+                    Err(_) => {
+                        attempt += 1;
+                        warn!(
+                            "[{}] Retrying {:?} (attempt {} of {})",
+                            self.name, state, attempt, self.retries
+                        );
+                    }
+                }
+            }
+        }
+    }
+
+    /// Poll the page until its state differs from `state`, failing after the timeout
+    fn wait_for_change(
+        &mut self,
+        page: &dyn Page,
+        state: Option<S>,
+        start_time: Instant,
+    ) -> Result<Wait<S>> {
+        let timeout = self.timeout_for(state);
+        let wait_start = Instant::now();
+
+        loop {
+            if is_kill_switch_pressed() {
+                warn!(
+                    "[{}] 🛑 Kill switch activated (ESC pressed), stopping",
+                    self.name
+                );
+                return Ok(Wait::Stopped);
+            }
+
+            let next = self.observe(page, state, start_time)?;
+            if next != state {
+                return Ok(Wait::Changed(next));
+            }
+
+            if wait_start.elapsed() > timeout {
+                anyhow::bail!(
+                    "[{}] Page stayed in state {:?} for more than {} ms",
+                    self.name,
+                    state,
+                    timeout.as_millis()
+                );
+            }
+
+            std::thread::sleep(self.poll_interval);
+        }
+    }
+
+    /// Detect the state of the page and record a trace entry when it changed
+    fn observe(
+        &mut self,
+        page: &dyn Page,
+        previous: Option<S>,
+        start_time: Instant,
+    ) -> Result<Option<S>> {
+        let state = self.detect_in(page)?;
+
+        if state != previous || self.trace.is_empty() {
+            let expected = self.is_expected(previous, state);
+            let entry = TraceEntry {
+                elapsed: start_time.elapsed(),
+                from: previous,
+                to: state,
+                expected,
+            };
+            if expected {
+                debug!(
+                    "[{}] {:>6} ms: {:?} -> {:?}",
+                    self.name,
+                    entry.elapsed.as_millis(),
+                    entry.from,
+                    entry.to
+                );
+            } else {
+                warn!(
+                    "[{}] {:>6} ms: {:?} -> {:?} (unexpected)",
+                    self.name,
+                    entry.elapsed.as_millis(),
+                    entry.from,
+                    entry.to
+                );
+            }
+            self.trace.push(entry);
+        }
+
+        Ok(state)
+    }
+
+    fn is_expected(&self, from: Option<S>, to: Option<S>) -> bool {
+        match (from, to) {
+            (Some(from), Some(to)) if !self.transitions.is_empty() => {
+                self.transitions.contains(&(from, to))
+            }
+            _ => true,
+        }
+    }
+
+    fn timeout_for(&self, state: Option<S>) -> Duration {
+        self.states
+            .iter()
+            .find(|(candidate, _, _)| Some(*candidate) == state)
+            .and_then(|(_, _, timeout)| *timeout)
+            .unwrap_or(self.timeout)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::page::ScriptedPage;
+
+    #[derive(Debug, Clone, Copy, PartialEq)]
+    enum Light {
+        Red,
+        Green,
+    }
+
+    fn traffic_light() -> StateMachine<Light> {
+        StateMachine::new("light")
+            .state(
+                Light::Green,
+                Detection::attribute(".light", "data-color", "green"),
+            )
+            .state(Light::Red, Detection::selector(".light"))
+            .transition(Light::Red, Light::Green)
+            .timeout(Duration::from_millis(20))
+            .poll_interval(Duration::from_millis(1))
+    }
+
+    /// Page that shows every snapshot for one poll, repeating the last one
+    fn scripted(machine: &StateMachine<Light>, snapshots: &[&str]) -> ScriptedPage {
+        let (last, polled) = snapshots.split_last().unwrap();
+        polled
+            .iter()
+            .fold(ScriptedPage::new(), |page, snapshot| {
+                page.then_for(*snapshot, 1)
+            })
+            .then(*last)
+            .on_evaluate(machine.answer_detection())
+    }
+
+    const RED: &str = r#"<div class="light" data-color="red">Wait</div>"#;
+    const GREEN: &str = r#"<div class="light" data-color="green">Go</div>"#;
+
+    #[test]
+    fn detection_rules() {
+        let document = Html::parse_document(GREEN);
+        assert!(Detection::selector(".light").matches(&document));
+        assert!(Detection::text(".light", "Go").matches(&document));
+        assert!(!Detection::text(".light", "Wait").matches(&document));
+        assert!(Detection::attribute(".light", "data-color", "green").matches(&document));
+        assert!(Detection::Not(Box::new(Detection::selector(".missing"))).matches(&document));
+        assert!(Detection::Any(vec![
+            Detection::selector(".missing"),
+            Detection::selector(".light"),
+        ])
+        .matches(&document));
+        assert!(!Detection::All(vec![
+            Detection::selector(".missing"),
+            Detection::selector(".light"),
+        ])
+        .matches(&document));
+        assert!(!Detection::selector("[invalid").matches(&document));
+    }
+
+    #[test]
+    fn states_are_detected_in_order_of_priority() {
+        let machine = traffic_light();
+        assert_eq!(machine.detect(GREEN), Some(Light::Green));
+        assert_eq!(machine.detect(RED), Some(Light::Red));
+        assert_eq!(machine.detect("<p>Loading</p>"), None);
+    }
+
+    #[test]
+    fn detects_in_the_page_with_the_rules_as_script() {
+        let machine = traffic_light();
+        assert!(machine.script.contains(
+            r#"[() => select(".light").some((element) => element.getAttribute("data-color") === "green"), () => select(".light").length > 0]"#
+        ));
+        assert_eq!(
+            Detection::Not(Box::new(Detection::Any(vec![
+                Detection::text("h1", "Say \"hi\""),
+                Detection::All(vec![]),
+            ])))
+            .to_script(),
+            r#"!((select("h1").some((element) => element.textContent.includes("Say \"hi\"")) || true))"#
+        );
+
+        let page = ScriptedPage::new()
+            .then_for("<p>Loading</p>", 1)
+            .then(GREEN)
+            .on_evaluate(machine.answer_detection());
+        assert_eq!(machine.detect_in(&page).unwrap(), None);
+        assert_eq!(machine.detect_in(&page).unwrap(), Some(Light::Green));
+    }
+
+    #[test]
+    fn handles_every_state_and_traces_transitions() {
+        let mut machine = traffic_light();
+        let page = scripted(&machine, &["<p>Loading</p>", RED, GREEN]);
+        let mut handled = Vec::new();
+
+        machine
+            .run(&page, |state, _| {
+                handled.push(state);
+                Ok(if state == Light::Green {
+                    Step::Stop
+                } else {
+                    Step::Continue
+                })
+            })
+            .unwrap();
+
+        assert_eq!(handled, vec![Light::Red, Light::Green]);
+        let transitions: Vec<_> = machine
+            .trace()
+            .iter()
+            .map(|entry| (entry.from, entry.to, entry.expected))
+            .collect();
+        assert_eq!(
+            transitions,
+            vec![
+                (None, None, true),
+                (None, Some(Light::Red), true),
+                (Some(Light::Red), Some(Light::Green), true),
+            ]
+        );
+    }
+
+    #[test]
+    fn marks_undeclared_transitions() {
+        let mut machine = traffic_light();
+        let page = scripted(&machine, &[GREEN, RED]);
+        machine
+            .run(&page, |state, _| {
+                Ok(if state == Light::Red {
+                    Step::Stop
+                } else {
+                    Step::Continue
+                })
+            })
+            .unwrap();
+
+        let last = machine.trace().last().unwrap();
+        assert_eq!((last.from, last.to), (Some(Light::Green), Some(Light::Red)));
+        assert!(!last.expected);
+    }
+
+    #[test]
+    fn retries_and_times_out_when_the_page_is_stuck() {
+        let mut machine = traffic_light().retries(2);
+        let page = scripted(&machine, &[RED]);
+        let mut attempts = Vec::new();
+
+        let result = machine.run(&page, |_, attempt| {
+            attempts.push(attempt);
+            Ok(Step::Continue)
+        });
+
+        assert!(result.is_err());
+        assert_eq!(attempts, vec![0, 1, 2]);
+    }
+}
@@ -0,0 +1,871 @@
+use super::input::{Input, KeyAction};
+use super::options::options;
+use super::page::{ContentBox, Page, PageElement, WindowBounds};
+use super::utils::Point;
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::fs;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use tracing::{info, warn};
+
+/// Version of the trace format, traces of other versions are not replayed
+const TRACE_VERSION: u32 = 1;
+
+/// A call of the solver on the page or on its input, without what it returned
+///
+/// Elements are numbered in the order the page returned them, and scripts are numbered in
+/// the list of scripts of the trace, so the trace does not repeat them for every call.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "call", rename_all = "snake_case")]
+pub enum Request {
+    WaitForElement {
+        selector: String,
+    },
+    FindElement {
+        selector: String,
+    },
+    /// Find elements in the page, or in an element when it is set
+    FindElements {
+        element: Option<usize>,
+        selector: String,
+    },
+    Bounds,
+    Evaluate {
+        script: usize,
+        await_promise: bool,
+    },
+    GetContent {
+        element: usize,
+    },
+    GetInnerText {
+        element: usize,
+    },
+    BoxModel {
+        element: usize,
+    },
+    ClickElement {
+        element: usize,
+    },
+    Origin,
+    MoveTo {
+        point: Point,
+    },
+    Click {
+        point: Point,
+    },
+    SendKeys {
+        keys: Vec<KeyAction>,
+    },
+    InsertText {
+        text: String,
+    },
+}
+
+impl Request {
+    /// Whether the call is input the solver emitted, instead of an observation of the page
+    fn is_input(&self) -> bool {
+        matches!(
+            self,
+            Request::ClickElement { .. }
+                | Request::MoveTo { .. }
+                | Request::Click { .. }
+                | Request::SendKeys { .. }
+                | Request::InsertText { .. }
+        )
+    }
+}
+
+/// What a call returned, errors are kept as their message
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Response {
+    Element(usize),
+    Elements(Vec<usize>),
+    Bounds(WindowBounds),
+    Value(Option<Value>),
+    Text(String),
+    Box(ContentBox),
+    Point(Point),
+    Done,
+    Error(String),
+}
+
+/// A call and what it returned, with the time since the solver started
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TraceEntry {
+    pub elapsed_ms: f64,
+    #[serde(flatten)]
+    pub request: Request,
+    pub response: Response,
+}
+
+/// The first line of a trace file
+#[derive(Debug, Serialize, Deserialize)]
+struct TraceHeader {
+    version: u32,
+    /// The solver that made the calls and its arguments
+    solver: Value,
+    scripts: Vec<String>,
+}
+
+/// Every page observation a solver made and every input it emitted, in the order it made them
+///
+/// A trace file has a line with a JSON object for the header, followed by a line for every
+/// call. It is recorded with `--trace <file>` and checked with the `replay <file>` command.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Trace {
+    pub solver: Value,
+    pub scripts: Vec<String>,
+    pub entries: Vec<TraceEntry>,
+}
+
+impl Trace {
+    pub fn load(path: &Path) -> Result<Self> {
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("Could not read the trace {}", path.display()))?;
+        Self::parse(&text).with_context(|| format!("Could not parse the trace {}", path.display()))
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        fs::write(path, self.to_json_lines()?)
+            .with_context(|| format!("Could not write the trace {}", path.display()))
+    }
+
+    fn parse(text: &str) -> Result<Self> {
+        let mut lines = text.lines().filter(|line| !line.trim().is_empty());
+        let header: TraceHeader =
+            serde_json::from_str(lines.next().context("The trace is empty")?)?;
+        if header.version != TRACE_VERSION {
+            anyhow::bail!(
+                "The trace has version {}, only version {} can be replayed",
+                header.version,
+                TRACE_VERSION
+            );
+        }
+
+        let entries = lines
+            .enumerate()
+            .map(|(index, line)| {
+                serde_json::from_str(line).with_context(|| format!("Call {} is invalid", index + 1))
+            })
+            .collect::<Result<_>>()?;
+        Ok(Self {
+            solver: header.solver,
+            scripts: header.scripts,
+            entries,
+        })
+    }
+
+    fn to_json_lines(&self) -> Result<String> {
+        let header = TraceHeader {
+            version: TRACE_VERSION,
+            solver: self.solver.clone(),
+            scripts: self.scripts.clone(),
+        };
+        let mut lines = serde_json::to_string(&header)? + "\n";
+        for entry in &self.entries {
+            lines += &(serde_json::to_string(entry)? + "\n");
+        }
+        Ok(lines)
+    }
+}
+
+/// Run the solver on the page and input, and save a trace of it when `--trace` is set
+///
+/// The trace is also saved when the solver fails, its header gets the solver, which must
+/// describe the solver and its arguments well enough for the replay to call it again.
+pub fn traced<T>(
+    solver: &impl Serialize,
+    page: &dyn Page,
+    input: &dyn Input,
+    solve: impl FnOnce(&dyn Page, &dyn Input) -> Result<T>,
+) -> Result<T> {
+    let Some(path) = &options().trace else {
+        return solve(page, input);
+    };
+
+    let tracing_page = TracingPage::new(page, input);
+    let result = solve(&tracing_page, &tracing_page);
+
+    let saved = serde_json::to_value(solver)
+        .map_err(anyhow::Error::from)
+        .and_then(|solver| {
+            let trace = tracing_page.into_trace(solver);
+            trace.save(path)?;
+            Ok(trace.entries.len())
+        });
+    match saved {
+        Ok(calls) => info!("📼 Saved a trace of {} calls to {}", calls, path.display()),
+        Err(e) => warn!("Could not save the trace: {:#}", e),
+    }
+
+    result
+}
+
+/// The calls recorded so far
+#[derive(Default)]
+struct Recorded {
+    scripts: Vec<String>,
+    entries: Vec<TraceEntry>,
+}
+
+/// Records the calls that pass through a [`TracingPage`] and its elements
+struct Recorder {
+    start: Instant,
+    next_element: AtomicUsize,
+    recorded: Mutex<Recorded>,
+}
+
+impl Recorder {
+    fn record<T>(
+        &self,
+        request: Request,
+        result: Result<T>,
+        respond: impl FnOnce(&T) -> Response,
+    ) -> Result<T> {
+        let response = match &result {
+            Ok(value) => respond(value),
+            Err(e) => Response::Error(format!("{:#}", e)),
+        };
+        self.recorded.lock().unwrap().entries.push(TraceEntry {
+            elapsed_ms: self.start.elapsed().as_secs_f64() * 1000.0,
+            request,
+            response,
+        });
+        result
+    }
+
+    /// Number the script in the list of scripts of the trace
+    fn script(&self, script: &str) -> usize {
+        let scripts = &mut self.recorded.lock().unwrap().scripts;
+        match scripts.iter().position(|known| known == script) {
+            Some(index) => index,
+            None => {
+                scripts.push(script.to_string());
+                scripts.len() - 1
+            }
+        }
+    }
+
+    /// Number the elements and wrap them, so their calls are recorded too
+    fn elements<'a>(
+        &'a self,
+        request: Request,
+        found: Result<Vec<Box<dyn PageElement + 'a>>>,
+    ) -> Result<Vec<Box<dyn PageElement + 'a>>> {
+        let found = found.map(|elements| {
+            elements
+                .into_iter()
+                .map(|element| self.element(element))
+                .collect::<Vec<_>>()
+        });
+        let found = self.record(request, found, |elements| {
+            Response::Elements(elements.iter().map(|element| element.id).collect())
+        })?;
+        Ok(found
+            .into_iter()
+            .map(|element| Box::new(element) as Box<dyn PageElement + 'a>)
+            .collect())
+    }
+
+    fn element<'a>(&'a self, element: Box<dyn PageElement + 'a>) -> TracingElement<'a> {
+        TracingElement {
+            element,
+            id: self.next_element.fetch_add(1, Ordering::Relaxed),
+            recorder: self,
+        }
+    }
+}
+
+/// Passes the calls of a solver on to the page and input, and records them
+pub struct TracingPage<'a> {
+    page: &'a dyn Page,
+    input: &'a dyn Input,
+    recorder: Recorder,
+}
+
+impl<'a> TracingPage<'a> {
+    pub fn new(page: &'a dyn Page, input: &'a dyn Input) -> Self {
+        Self {
+            page,
+            input,
+            recorder: Recorder {
+                start: Instant::now(),
+                next_element: AtomicUsize::new(0),
+                recorded: Mutex::new(Recorded::default()),
+            },
+        }
+    }
+
+    /// The trace of the calls so far, with the solver for its header
+    pub fn into_trace(self, solver: Value) -> Trace {
+        let recorded = self.recorder.recorded.into_inner().unwrap();
+        Trace {
+            solver,
+            scripts: recorded.scripts,
+            entries: recorded.entries,
+        }
+    }
+
+    /// Record a lookup of a single element
+    fn found<'b>(
+        &'b self,
+        request: Request,
+        found: Result<Box<dyn PageElement + 'b>>,
+    ) -> Result<Box<dyn PageElement + 'b>> {
+        let found = found.map(|element| self.recorder.element(element));
+        let element = self
+            .recorder
+            .record(request, found, |element| Response::Element(element.id))?;
+        Ok(Box::new(element))
+    }
+}
+
+impl Page for TracingPage<'_> {
+    fn wait_for_element(&self, selector: &str) -> Result<Box<dyn PageElement + '_>> {
+        let request = Request::WaitForElement {
+            selector: selector.to_string(),
+        };
+        self.found(request, self.page.wait_for_element(selector))
+    }
+
+    fn find_element(&self, selector: &str) -> Result<Box<dyn PageElement + '_>> {
+        let request = Request::FindElement {
+            selector: selector.to_string(),
+        };
+        self.found(request, self.page.find_element(selector))
+    }
+
+    fn find_elements(&self, selector: &str) -> Result<Vec<Box<dyn PageElement + '_>>> {
+        let request = Request::FindElements {
+            element: None,
+            selector: selector.to_string(),
+        };
+        self.recorder
+            .elements(request, self.page.find_elements(selector))
+    }
+
+    fn bounds(&self) -> Result<WindowBounds> {
+        self.recorder
+            .record(Request::Bounds, self.page.bounds(), |bounds| {
+                Response::Bounds(*bounds)
+            })
+    }
+
+    fn evaluate(&self, script: &str, await_promise: bool) -> Result<Option<Value>> {
+        let request = Request::Evaluate {
+            script: self.recorder.script(script),
+            await_promise,
+        };
+        self.recorder.record(
+            request,
+            self.page.evaluate(script, await_promise),
+            |value| Response::Value(value.clone()),
+        )
+    }
+}
+
+impl Input for TracingPage<'_> {
+    fn move_to(&self, point: &Point) -> Result<()> {
+        let request = Request::MoveTo {
+            point: point.clone(),
+        };
+        self.recorder
+            .record(request, self.input.move_to(point), |_| Response::Done)
+    }
+
+    fn click(&self, point: &Point) -> Result<()> {
+        let request = Request::Click {
+            point: point.clone(),
+        };
+        self.recorder
+            .record(request, self.input.click(point), |_| Response::Done)
+    }
+
+    fn send_keys(&self, actions: &[KeyAction]) -> Result<()> {
+        let request = Request::SendKeys {
+            keys: actions.to_vec(),
+        };
+        self.recorder
+            .record(request, self.input.send_keys(actions), |_| Response::Done)
+    }
+
+    fn insert_text(&self, text: &str) -> Result<()> {
+        let request = Request::InsertText {
+            text: text.to_string(),
+        };
+        self.recorder
+            .record(request, self.input.insert_text(text), |_| Response::Done)
+    }
+
+    fn origin(&self) -> Result<Point> {
+        self.recorder
+            .record(Request::Origin, self.input.origin(), |origin| {
+                Response::Point(origin.clone())
+            })
+    }
+}
+
+/// An element of a [`TracingPage`]
+struct TracingElement<'a> {
+    element: Box<dyn PageElement + 'a>,
+    id: usize,
+    recorder: &'a Recorder,
+}
+
+impl PageElement for TracingElement<'_> {
+    fn find_elements(&self, selector: &str) -> Result<Vec<Box<dyn PageElement + '_>>> {
+        let request = Request::FindElements {
+            element: Some(self.id),
+            selector: selector.to_string(),
+        };
+        self.recorder
+            .elements(request, self.element.find_elements(selector))
+    }
+
+    fn get_content(&self) -> Result<String> {
+        let request = Request::GetContent { element: self.id };
+        self.recorder
+            .record(request, self.element.get_content(), |html| {
+                Response::Text(html.clone())
+            })
+    }
+
+    fn get_inner_text(&self) -> Result<String> {
+        let request = Request::GetInnerText { element: self.id };
+        self.recorder
+            .record(request, self.element.get_inner_text(), |text| {
+                Response::Text(text.clone())
+            })
+    }
+
+    fn box_model(&self) -> Result<ContentBox> {
+        let request = Request::BoxModel { element: self.id };
+        self.recorder
+            .record(request, self.element.box_model(), |content_box| {
+                Response::Box(*content_box)
+            })
+    }
+
+    fn click(&self) -> Result<()> {
+        let request = Request::ClickElement { element: self.id };
+        self.recorder
+            .record(request, self.element.click(), |_| Response::Done)
+    }
+}
+
+/// Which calls of the trace were replayed
+struct ReplayState {
+    replayed: Vec<bool>,
+    remaining: usize,
+    inputs_left: usize,
+    /// Why the solver left the trace, once it did
+    diverged: Option<String>,
+}
+
+/// A fake page that answers the calls of a solver from a trace, for replaying real runs
+///
+/// Page observations are answered by the first call of the trace that asks the same and
+/// was not replayed yet, so observations the solver makes from several threads can come in
+/// another order. An observation the trace has no call left for is answered like the last
+/// time it was made, the page did not change in between. Input has to come in the order of
+/// the trace, and a call the trace cannot answer ends the replay.
+///
+/// Calls are answered right away, unless [`ReplayPage::in_real_time`] makes every answer
+/// wait until the time it was made in the recorded run.
+pub struct ReplayPage {
+    trace: Trace,
+    state: Mutex<ReplayState>,
+    start: Instant,
+    real_time: bool,
+}
+
+impl ReplayPage {
+    pub fn new(trace: Trace) -> Self {
+        let calls = trace.entries.len();
+        let inputs = trace
+            .entries
+            .iter()
+            .filter(|entry| entry.request.is_input())
+            .count();
+        Self {
+            trace,
+            state: Mutex::new(ReplayState {
+                replayed: vec![false; calls],
+                remaining: calls,
+                inputs_left: inputs,
+                diverged: None,
+            }),
+            start: Instant::now(),
+            real_time: false,
+        }
+    }
+
+    /// Answer every call at the time it was answered in the recorded run, so solvers that
+    /// poll for a while poll about as often as they did
+    pub fn in_real_time(self) -> Self {
+        Self {
+            real_time: true,
+            start: Instant::now(),
+            ..self
+        }
+    }
+
+    /// Check how the replay went, after the solver returned
+    ///
+    /// Observations of the trace the solver did not make are fine, a poll can end sooner
+    /// than in the recorded run, but every input of the trace has to be made.
+    ///
+    /// ## Returns
+    /// Returns the number of calls replayed when the solver made the input of the trace and
+    /// no other
+    pub fn verify(&self) -> Result<usize> {
+        let state = self.state.lock().unwrap();
+        if let Some(diverged) = &state.diverged {
+            anyhow::bail!("{}", diverged);
+        }
+        let entries = &self.trace.entries;
+        if let Some(index) = (0..entries.len())
+            .find(|index| !state.replayed[*index] && entries[*index].request.is_input())
+        {
+            anyhow::bail!(
+                "The solver stopped with {} inputs of the trace left, starting with call {}: {:?}",
+                state.inputs_left,
+                index + 1,
+                entries[index].request
+            );
+        }
+        Ok(entries.len() - state.remaining)
+    }
+
+    /// Answer the call from the trace, in real time at the time it was answered in the
+    /// recorded run
+    fn replay(&self, request: Request) -> Result<Response> {
+        let (response, elapsed_ms) = self.find(request)?;
+        if self.real_time {
+            let due = Duration::from_secs_f64(elapsed_ms.max(0.0) / 1000.0);
+            if let Some(wait) = due.checked_sub(self.start.elapsed()) {
+                std::thread::sleep(wait);
+            }
+        }
+        Ok(response)
+    }
+
+    /// Find the answer to the call in the trace, with the time it was made
+    fn find(&self, request: Request) -> Result<(Response, f64)> {
+        let mut state = self.state.lock().unwrap();
+        if let Some(diverged) = &state.diverged {
+            anyhow::bail!("{}", diverged);
+        }
+
+        let entries = &self.trace.entries;
+        let mut unreplayed = (0..entries.len()).filter(|index| !state.replayed[*index]);
+        let found = if request.is_input() {
+            // Input must be the next input of the trace
+            unreplayed
+                .find(|index| entries[*index].request.is_input())
+                .filter(|index| entries[*index].request == request)
+        } else {
+            unreplayed.find(|index| entries[*index].request == request)
+        };
+
+        if let Some(index) = found {
+            state.replayed[index] = true;
+            state.remaining -= 1;
+            if request.is_input() {
+                state.inputs_left -= 1;
+            }
+            return Ok((entries[index].response.clone(), entries[index].elapsed_ms));
+        }
+
+        if !request.is_input() {
+            // Polled more often than in the recorded run, the page still looks the same
+            let last = (0..entries.len())
+                .rev()
+                .find(|index| state.replayed[*index] && entries[*index].request == request);
+            if let Some(index) = last {
+                return Ok((entries[index].response.clone(), 0.0));
+            }
+            if state.inputs_left == 0 {
+                anyhow::bail!("The trace has ended");
+            }
+        }
+
+        let diverged = format!(
+            "The solver left the trace after {} calls: the trace has no {:?}",
+            entries.len() - state.remaining,
+            request
+        );
+        state.diverged = Some(diverged.clone());
+        anyhow::bail!(diverged)
+    }
+
+    /// Answer the call from the trace, with the value `take` gets out of the response
+    fn answer<T>(&self, request: Request, take: impl FnOnce(Response) -> Option<T>) -> Result<T> {
+        match self.replay(request.clone())? {
+            Response::Error(message) => Err(anyhow::anyhow!(message)),
+            response => take(response.clone()).ok_or_else(|| {
+                anyhow::anyhow!("The trace answers {:?} with {:?}", request, response)
+            }),
+        }
+    }
+
+    fn done(&self, request: Request) -> Result<()> {
+        self.answer(request, |response| {
+            matches!(response, Response::Done).then_some(())
+        })
+    }
+
+    fn element(&self, request: Request) -> Result<Box<dyn PageElement + '_>> {
+        let id = self.answer(request, |response| match response {
+            Response::Element(id) => Some(id),
+            _ => None,
+        })?;
+        Ok(Box::new(ReplayElement { page: self, id }))
+    }
+
+    fn elements(&self, request: Request) -> Result<Vec<Box<dyn PageElement + '_>>> {
+        let ids = self.answer(request, |response| match response {
+            Response::Elements(ids) => Some(ids),
+            _ => None,
+        })?;
+        Ok(ids
+            .into_iter()
+            .map(|id| Box::new(ReplayElement { page: self, id }) as Box<dyn PageElement>)
+            .collect())
+    }
+}
+
+impl Page for ReplayPage {
+    fn wait_for_element(&self, selector: &str) -> Result<Box<dyn PageElement + '_>> {
+        self.element(Request::WaitForElement {
+            selector: selector.to_string(),
+        })
+    }
+
+    fn find_element(&self, selector: &str) -> Result<Box<dyn PageElement + '_>> {
+        self.element(Request::FindElement {
+            selector: selector.to_string(),
+        })
+    }
+
+    fn find_elements(&self, selector: &str) -> Result<Vec<Box<dyn PageElement + '_>>> {
+        self.elements(Request::FindElements {
+            element: None,
+            selector: selector.to_string(),
+        })
+    }
+
+    fn bounds(&self) -> Result<WindowBounds> {
+        self.answer(Request::Bounds, |response| match response {
+            Response::Bounds(bounds) => Some(bounds),
+            _ => None,
+        })
+    }
+
+    fn evaluate(&self, script: &str, await_promise: bool) -> Result<Option<Value>> {
+        // A script the trace does not know can never be answered, so it is always a divergence
+        let script = self
+            .trace
+            .scripts
+            .iter()
+            .position(|known| known == script)
+            .unwrap_or(self.trace.scripts.len());
+        self.answer(
+            Request::Evaluate {
+                script,
+                await_promise,
+            },
+            |response| match response {
+                Response::Value(value) => Some(value),
+                _ => None,
+            },
+        )
+    }
+}
+
+impl Input for ReplayPage {
+    fn move_to(&self, point: &Point) -> Result<()> {
+        self.done(Request::MoveTo {
+            point: point.clone(),
+        })
+    }
+
+    fn click(&self, point: &Point) -> Result<()> {
+        self.done(Request::Click {
+            point: point.clone(),
+        })
+    }
+
+    fn send_keys(&self, actions: &[KeyAction]) -> Result<()> {
+        self.done(Request::SendKeys {
+            keys: actions.to_vec(),
+        })
+    }
+
+    fn insert_text(&self, text: &str) -> Result<()> {
+        self.done(Request::InsertText {
+            text: text.to_string(),
+        })
+    }
+
+    fn origin(&self) -> Result<Point> {
+        self.answer(Request::Origin, |response| match response {
+            Response::Point(origin) => Some(origin),
+            _ => None,
+        })
+    }
+}
+
+/// An element of a [`ReplayPage`], known by its number in the trace
+struct ReplayElement<'a> {
+    page: &'a ReplayPage,
+    id: usize,
+}
+
+impl PageElement for ReplayElement<'_> {
+    fn find_elements(&self, selector: &str) -> Result<Vec<Box<dyn PageElement + '_>>> {
+        self.page.elements(Request::FindElements {
+            element: Some(self.id),
+            selector: selector.to_string(),
+        })
+    }
+
+    fn get_content(&self) -> Result<String> {
+        self.page.answer(
+            Request::GetContent { element: self.id },
+            |response| match response {
+                Response::Text(html) => Some(html),
+                _ => None,
+            },
+        )
+    }
+
+    fn get_inner_text(&self) -> Result<String> {
+        self.page.answer(
+            Request::GetInnerText { element: self.id },
+            |response| match response {
+                Response::Text(text) => Some(text),
+                _ => None,
+            },
+        )
+    }
+
+    fn box_model(&self) -> Result<ContentBox> {
+        self.page.answer(
+            Request::BoxModel { element: self.id },
+            |response| match response {
+                Response::Box(content_box) => Some(content_box),
+                _ => None,
+            },
+        )
+    }
+
+    fn click(&self) -> Result<()> {
+        self.page.done(Request::ClickElement { element: self.id })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::page::ScriptedPage;
+
+    /// Click the button until the page shows the result
+    fn click_until_done(page: &dyn Page, input: &dyn Input) -> Result<()> {
+        let origin = input.origin()?;
+        while page.find_element(".result").is_err() {
+            let button = page.wait_for_element("button")?;
+            let (x, y) = button.box_model()?.center();
+            input.click(&Point {
+                x: x as i32 + origin.x,
+                y: y as i32 + origin.y,
+            })?;
+        }
+        Ok(())
+    }
+
+    fn record(solve: impl FnOnce(&dyn Page, &dyn Input) -> Result<()>) -> Trace {
+        let page = ScriptedPage::new()
+            .then(r#"<body><button data-box="10 10 30 30">Go</button></body>"#)
+            .then(r#"<body><button data-box="50 10 70 30">Again</button></body>"#)
+            .then(r#"<body><div class="result">Done</div></body>"#);
+        let tracing_page = TracingPage::new(&page, &page);
+        solve(&tracing_page, &tracing_page).unwrap();
+        tracing_page.into_trace(serde_json::json!({ "benchmark": "test" }))
+    }
+
+    #[test]
+    fn replays_the_same_decisions() {
+        let trace = record(click_until_done);
+        assert_eq!(
+            trace
+                .entries
+                .iter()
+                .filter(|entry| entry.request.is_input())
+                .count(),
+            2
+        );
+
+        // The trace survives the file format
+        let trace = Trace::parse(&trace.to_json_lines().unwrap()).unwrap();
+        assert_eq!(trace.solver, serde_json::json!({ "benchmark": "test" }));
+
+        let replay = ReplayPage::new(trace.clone());
+        click_until_done(&replay, &replay).unwrap();
+        assert_eq!(replay.verify().unwrap(), trace.entries.len());
+    }
+
+    #[test]
+    fn replays_in_real_time_only_when_asked() {
+        let mut trace = record(click_until_done);
+        for entry in &mut trace.entries {
+            entry.elapsed_ms = 200.0;
+        }
+
+        let start = Instant::now();
+        let replay = ReplayPage::new(trace.clone());
+        click_until_done(&replay, &replay).unwrap();
+        assert!(start.elapsed() < Duration::from_millis(200));
+
+        let start = Instant::now();
+        let replay = ReplayPage::new(trace).in_real_time();
+        click_until_done(&replay, &replay).unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(200));
+        assert!(replay.verify().is_ok());
+    }
+
+    #[test]
+    fn reports_where_the_solver_diverges() {
+        let trace = record(click_until_done);
+
+        // A solver that clicks somewhere else
+        let replay = ReplayPage::new(trace.clone());
+        let _ = (|| -> Result<()> {
+            replay.wait_for_element("button")?;
+            replay.click(&Point { x: 0, y: 0 })
+        })();
+        let error = replay.verify().unwrap_err().to_string();
+        assert!(
+            error.contains("Click { point: Point { x: 0, y: 0 } }"),
+            "{}",
+            error
+        );
+
+        // A solver that stops early
+        let replay = ReplayPage::new(trace);
+        replay.origin().unwrap();
+        let error = replay.verify().unwrap_err().to_string();
+        assert!(error.contains("inputs of the trace left"), "{}", error);
+    }
+
+    #[test]
+    fn rejects_other_versions() {
+        assert!(Trace::parse(r#"{"version":0,"solver":null,"scripts":[]}"#).is_err());
+        assert!(Trace::parse("").is_err());
+    }
+}
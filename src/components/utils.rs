@@ -1,58 +1,118 @@
+use super::options::options;
+use super::page::PageElement;
 use anyhow::Result;
-use headless_chrome::{Element, Tab};
+use headless_chrome::Tab;
+use serde::{Deserialize, Serialize};
+use std::io::{self, BufRead, Write};
 use std::sync::{
     atomic::{AtomicBool, Ordering},
     Arc,
 };
-use std::thread::{self, JoinHandle};
-use std::time::Duration;
+use std::time::{Duration, Instant};
+use tracing::warn;
+
+#[cfg(target_os = "windows")]
 use winapi::um::winuser::{
     mouse_event, GetAsyncKeyState, SetCursorPos, MOUSEEVENTF_LEFTDOWN, MOUSEEVENTF_LEFTUP,
     VK_ESCAPE,
 };
 
-#[derive(Debug, Clone, PartialEq)]
+/// The "Save score" button, which every test shows on its result screen
+pub const RESULT_SCREEN_SELECTOR: &str = ".css-qm6rs9.e19owgy710";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Point {
     pub x: i32,
     pub y: i32,
 }
 
-/// Finds and clicks the cookies acceptance button on the Human Benchmark website.
+/// Runs blocking work, like synchronous CDP calls or Win32 input loops, on a dedicated thread
 ///
-/// This function waits for the cookies button element to appear, then attempts to click it.
-/// A 500ms delay is added after clicking to allow the page to process the action.
+/// The `headless_chrome` api and the solvers block the calling thread, so running them
+/// directly inside a benchmark would stall the tokio runtime and every other task on it.
+/// The work runs inside the current span, so its messages keep the benchmark they belong to.
 ///
-/// ## Arguments
-/// * `tab` - A reference to the browser tab where the action should be performed
+/// ## Errors
+/// Returns the error of the work, or an error if the thread panicked
+pub async fn run_blocking<T, F>(work: F) -> Result<T>
+where
+    T: Send + 'static,
+    F: FnOnce() -> Result<T> + Send + 'static,
+{
+    let span = tracing::Span::current();
+    tokio::task::spawn_blocking(move || span.in_scope(work)).await?
+}
+
+/// Waits until the element is present and the page has stopped loading resources.
 ///
-/// ## Returns
-/// Returns `Ok(())` if the cookies button was found and clicked successfully, or if no
-/// cookies button was present. Returns an error if waiting for the element fails.
+/// Replaces a fixed delay for the ads to load in: the element wait is bounded by the ready
+/// timeout, and the network is considered idle once no new resources were requested for
+/// 500ms. When ads keep loading, the wait gives up after the idle timeout and continues.
 ///
 /// ## Errors
-/// This function will return an error if:
-/// - The tab fails to wait for the `.fc-button-label` element
-/// - The element click operation fails
-pub fn click_cookies_button(tab: &Arc<Tab>) -> Result<()> {
-    // Wait for the element to appear
-    std::thread::sleep(Duration::from_secs(3));
-
-    // Try to find and click the first type of cookie button
-    if let Ok(accept_cookies_button) = tab.find_element(".fc-button-label") {
-        accept_cookies_button.click()?;
-        println!("Clicked accept cookies button (.fc-button-label)");
-        return Ok(());
+/// Returns an error if the element does not appear within the ready timeout
+pub async fn wait_until_ready(tab: &Arc<Tab>, selector: &str) -> Result<()> {
+    let page = tab.clone();
+    let selector = selector.to_string();
+    run_blocking(move || {
+        page.wait_for_element_with_custom_timeout(&selector, options().ready_timeout)?;
+        Ok(())
+    })
+    .await?;
+
+    wait_for_network_idle(tab).await
+}
+
+/// Waits until the page is loaded and has not requested new resources for 500ms
+async fn wait_for_network_idle(tab: &Arc<Tab>) -> Result<()> {
+    let start_time = Instant::now();
+    let mut last_resource_count = None;
+    let mut idle_since = Instant::now();
+    let mut interval = tokio::time::interval(Duration::from_millis(50));
+
+    loop {
+        interval.tick().await;
+
+        let page = tab.clone();
+        let resource_count = run_blocking(move || {
+            Ok(page
+                .evaluate(
+                    "document.readyState === 'complete' ? performance.getEntriesByType('resource').length : -1",
+                    false,
+                )?
+                .value
+                .and_then(|value| value.as_i64())
+                .filter(|count| *count >= 0))
+        })
+        .await?;
+
+        if resource_count != last_resource_count {
+            last_resource_count = resource_count;
+            idle_since = Instant::now();
+        } else if resource_count.is_some() && idle_since.elapsed() >= Duration::from_millis(500) {
+            return Ok(());
+        }
+
+        if start_time.elapsed() > options().idle_timeout {
+            warn!("Page is still loading resources, continuing anyway");
+            return Ok(());
+        }
     }
+}
 
-    // If first type not found, try the second type
-    if let Ok(accept_cookies_button) = tab.find_element(".css-47sehv") {
-        accept_cookies_button.click()?;
-        println!("Clicked accept cookies button (.css-47sehv)");
+/// Waits for Enter before the test ends and the browser closes, when `--pause-at-end` is set.
+pub async fn pause_at_end() -> Result<()> {
+    if !options().pause_at_end {
         return Ok(());
     }
 
-    println!("No cookie button found to click");
-    Ok(())
+    run_blocking(|| {
+        print!("Press Enter to close the browser...");
+        io::stdout().flush()?;
+        io::stdin().lock().read_line(&mut String::new())?;
+        Ok(())
+    })
+    .await
 }
 
 /// Sets the cursor to the pixel coordinates and performs a mouse click
@@ -66,6 +126,7 @@ pub fn click_cookies_button(tab: &Arc<Tab>) -> Result<()> {
 ///
 /// ## Returns
 /// Returns `Ok(())` if the click operation was successful
+#[cfg(target_os = "windows")]
 pub fn click_on_pixel(x: i32, y: i32) -> Result<()> {
     unsafe {
         // Set the cursor position using the Win32 Api
@@ -77,17 +138,42 @@ pub fn click_on_pixel(x: i32, y: i32) -> Result<()> {
     Ok(())
 }
 
+/// Pixel clicks need the Win32 Api, so they are not available on other platforms
+#[cfg(not(target_os = "windows"))]
+pub fn click_on_pixel(x: i32, y: i32) -> Result<()> {
+    anyhow::bail!(
+        "Clicking on pixel ({}, {}) is only supported on Windows",
+        x,
+        y
+    )
+}
+
+/// Finds the first number that directly follows the label in the text of the page, ignoring case
+///
+/// Used to read counters like "NUMBERS 7" or "Lives | 3". Separators between the label and
+/// the number are skipped.
+pub fn number_after_label(text: &str, label: &str) -> Option<u32> {
+    let lowercase = text.to_ascii_lowercase();
+    let label = label.to_ascii_lowercase();
+    lowercase.match_indices(&label).find_map(|(index, _)| {
+        let digits: String = text[index + label.len()..]
+            .trim_start_matches(|c: char| c.is_whitespace() || c == ':' || c == '|')
+            .chars()
+            .take_while(|c| c.is_ascii_digit())
+            .collect();
+        digits.parse().ok()
+    })
+}
+
 /// Calculate the position of the element by determining the pixel coordinates of the element's center.
 pub fn determ_center_of_element(
-    element: &Element,
+    element: &dyn PageElement,
     window_x: &i32,
     window_y: &i32,
     x_offset: &i32,
     y_offset: &i32,
 ) -> Result<Point> {
-    let quad = element.get_box_model()?.content;
-    let center_x = (quad.top_left.x + quad.top_right.x) / 2.0;
-    let center_y = (quad.top_left.y + quad.bottom_left.y) / 2.0;
+    let (center_x, center_y) = element.box_model()?.center();
     let screen_position = Point {
         x: center_x as i32 + window_x + x_offset,
         y: center_y as i32 + window_y + y_offset,
@@ -102,30 +188,30 @@ pub fn determ_center_of_element(
 ///
 /// ## Returns
 /// Returns `true` if the ESC key is currently pressed, `false` otherwise
+#[cfg(target_os = "windows")]
 pub fn is_kill_switch_pressed() -> bool {
-    unsafe {
+    let pressed = unsafe {
         // Check if ESC key is pressed (0x8000 bit indicates key is currently down)
         (GetAsyncKeyState(VK_ESCAPE) as u16 & 0x8000) != 0
+    };
+    if pressed {
+        KILL_SWITCH_SEEN.store(true, Ordering::Relaxed);
     }
+    pressed
 }
 
-/// Spawns a background thread that monitors for a DOM element's presence
-/// and signals completion when found.
-pub fn spawn_completion_monitor(
-    tab: Arc<Tab>,
-    completion_signal: Arc<AtomicBool>,
-) -> JoinHandle<()> {
-    // Spawn a new thread to monitor for the completion signal
-    thread::spawn(move || {
-        while !completion_signal.load(Ordering::Relaxed) {
-            // Check if the 'Save score' button is present
-            if tab.find_element(".css-qm6rs9.e19owgy710").is_ok() {
-                println!("🏁 Test completed - found completion element",);
-                completion_signal.store(true, Ordering::Relaxed);
-                break;
-            }
-            // Check every 100ms to avoid overwhelming the browser
-            thread::sleep(Duration::from_millis(100));
-        }
-    })
+/// The kill switch relies on the Win32 Api, so it never triggers on other platforms
+#[cfg(not(target_os = "windows"))]
+pub fn is_kill_switch_pressed() -> bool {
+    false
+}
+
+/// Set when a check saw the kill switch pressed, until it is taken
+static KILL_SWITCH_SEEN: AtomicBool = AtomicBool::new(false);
+
+/// Returns `true` if a check saw the kill switch pressed since the last call
+///
+/// The key is only down for a moment, this tells afterwards whether a benchmark was cancelled.
+pub fn take_kill_switch() -> bool {
+    KILL_SWITCH_SEEN.swap(false, Ordering::Relaxed)
 }
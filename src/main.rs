@@ -5,12 +5,34 @@ mod benchmarks;
 mod components;
 
 use benchmarks::*;
-pub use components::utils;
+pub use components::{
+    account, artifacts, blocking, consent, input, logging, options, page, percentile, recording,
+    results, state_machine, trace, utils,
+};
 
 #[tokio::main]
 async fn main() -> Result<()> {
+    options::init(options::Options::from_args(std::env::args().skip(1))?);
+    logging::init(options::options())?;
+
+    match &options::options().command {
+        options::Command::Words => return verbal_memory::print_words(),
+        options::Command::Replay(path) => return replay::run(path, options::options().real_time),
+        options::Command::Distributions => {
+            return distributions::run(&options::options().distributions).await
+        }
+        options::Command::Menu => {}
+    }
+
     println!("=== Human Benchmark Test Suite ===\n");
 
+    if let Some(sessions) = options::options().parallel {
+        let tests = get_parallel_tests_input(
+            "Enter test to run in parallel: reaction, typing, chimp or all (default: all): ",
+        )?;
+        return parallel::run(tests, sessions).await;
+    }
+
     loop {
         display_menu();
 
@@ -19,12 +41,28 @@ async fn main() -> Result<()> {
 
         match choice.as_str() {
             "1" | "reaction" | "reaction-time" => {
-                println!("Running Reaction Time test...");
-                reaction_time::run().await?;
+                let mode = get_reaction_mode_input(&format!(
+                    "Enter reaction mode: polling or hook (default: {:?}): ",
+                    reaction_time::ReactionMode::default()
+                ))?;
+                let default_rounds = options::options().reaction_rounds;
+                let rounds = get_numeric_input(
+                    &format!("Enter number of rounds (default: {}): ", default_rounds),
+                    default_rounds,
+                )?;
+                println!(
+                    "Running Reaction Time test for {} rounds in {:?} mode...",
+                    rounds, mode
+                );
+                reaction_time::run(mode, rounds).await?;
             }
             "2" | "typing" => {
-                println!("Running Typing test...");
-                typing::run().await?;
+                let method = get_typing_method_input(&format!(
+                    "Enter typing method: unicode, layout or cdp (default: {:?}): ",
+                    typing::TypingMethod::default()
+                ))?;
+                println!("Running Typing test with {:?} input...", method);
+                typing::run(method).await?;
             }
             "3" | "sequence" | "sequence-memory" => {
                 let max_level = get_numeric_input("Enter max level (default: 10): ", 10)?;
@@ -115,3 +153,60 @@ fn get_numeric_input(prompt: &str, default: u32) -> Result<u32> {
         }
     }
 }
+
+fn get_typing_method_input(prompt: &str) -> Result<typing::TypingMethod> {
+    let input = get_user_input(prompt)?;
+    let input = input.trim();
+    if input.is_empty() {
+        return Ok(typing::TypingMethod::default());
+    }
+
+    match typing::TypingMethod::from_name(input) {
+        Some(method) => match method.check_supported() {
+            Ok(()) => Ok(method),
+            Err(e) => {
+                let default = typing::TypingMethod::default();
+                println!("{}, using default method: {:?}", e, default);
+                Ok(default)
+            }
+        },
+        None => {
+            let default = typing::TypingMethod::default();
+            println!("Invalid typing method, using default method: {:?}", default);
+            Ok(default)
+        }
+    }
+}
+
+fn get_reaction_mode_input(prompt: &str) -> Result<reaction_time::ReactionMode> {
+    let input = get_user_input(prompt)?;
+    let input = input.trim();
+    if input.is_empty() {
+        return Ok(reaction_time::ReactionMode::default());
+    }
+
+    match reaction_time::ReactionMode::from_name(input) {
+        Some(mode) => Ok(mode),
+        None => {
+            let default = reaction_time::ReactionMode::default();
+            println!("Invalid reaction mode, using default mode: {:?}", default);
+            Ok(default)
+        }
+    }
+}
+
+fn get_parallel_tests_input(prompt: &str) -> Result<Vec<parallel::ParallelTest>> {
+    let input = get_user_input(prompt)?;
+    let input = input.trim();
+    if input.is_empty() || input.eq_ignore_ascii_case("all") {
+        return Ok(parallel::ParallelTest::ALL.to_vec());
+    }
+
+    match parallel::ParallelTest::from_name(input) {
+        Some(test) => Ok(vec![test]),
+        None => {
+            println!("Invalid test, running the whole suite");
+            Ok(parallel::ParallelTest::ALL.to_vec())
+        }
+    }
+}